@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::symbols::InternedGrammar;
+use crate::SlrRule;
+
+/// Parse trees kept for every symbol and sentence; two are enough to show ambiguity.
+const MAX_TREES: usize = 2;
+
+/// Parse tree over the interned grammar.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Tree {
+    Leaf(usize),
+    /// Rule used and the trees of the symbols of its body.
+    Node(usize, Vec<Rc<Tree>>),
+}
+
+impl Tree {
+    /// Tree in brackets, as the counterexamples print them: `[E [E id] + [T id]]`.
+    pub fn to_string(&self, grammar: &InternedGrammar, extended_grammar: &[SlrRule]) -> String {
+        match self {
+            Tree::Leaf(terminal) => grammar.name(*terminal).clone(),
+            Tree::Node(rule, children) => {
+                let mut ret = format!("[{}", extended_grammar[*rule].origin);
+                for child in children.iter() {
+                    ret += " ";
+                    ret += &child.to_string(grammar, extended_grammar);
+                }
+                ret + "]"
+            }
+        }
+    }
+
+    /// Rules of the leftmost derivation of the tree, in order.
+    pub fn leftmost_derivation(&self) -> Vec<usize> {
+        let mut ret = Vec::new();
+        let mut pending = vec![self];
+        while let Some(tree) = pending.pop() {
+            if let Tree::Node(rule, children) = tree {
+                ret.push(*rule);
+                pending.extend(children.iter().rev().map(|child| child.as_ref()));
+            }
+        }
+        ret
+    }
+}
+
+/// Shortest sentence (first in shortlex order) with at most `max_length` tokens that has two
+/// different parse trees, with both trees (from the user's start symbol, without the
+/// augmented rule).
+///
+/// Like `generate::enumerate`, it's a fixed point over the rules that finds the sentences of
+/// every symbol, but keeping up to two parse trees for each one instead of only the
+/// sentence. A sentence with two trees for the start symbol has two leftmost derivations.
+pub fn find_ambiguity(
+    grammar: &InternedGrammar,
+    max_length: usize,
+) -> Option<(Vec<usize>, Rc<Tree>, Rc<Tree>)> {
+    let mut trees: Vec<HashMap<Vec<usize>, Vec<Rc<Tree>>>> =
+        vec![HashMap::new(); grammar.names.len()];
+    for (id, terminal) in trees.iter_mut().enumerate().take(grammar.eof) {
+        terminal.insert(vec![id], vec![Rc::new(Tree::Leaf(id))]);
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (num, rule) in grammar.rules.iter().enumerate() {
+            // children of the rule for each sentence it derives
+            let mut partial: HashMap<Vec<usize>, Vec<Vec<Rc<Tree>>>> =
+                HashMap::from([(Vec::new(), vec![Vec::new()])]);
+            for (pos, symbol) in rule.body.iter().enumerate() {
+                let needed = rule.body[pos + 1..]
+                    .iter()
+                    .filter(|symbol| !grammar.nullable[**symbol])
+                    .count();
+                let mut next: HashMap<Vec<usize>, Vec<Vec<Rc<Tree>>>> = HashMap::new();
+                for (prefix, prefix_children) in partial.iter() {
+                    for (sentence, symbol_trees) in trees[*symbol].iter() {
+                        if prefix.len() + sentence.len() + needed > max_length {
+                            continue;
+                        }
+                        let entry = next
+                            .entry([prefix.as_slice(), sentence.as_slice()].concat())
+                            .or_default();
+                        for children in prefix_children.iter() {
+                            for tree in symbol_trees.iter() {
+                                if entry.len() < MAX_TREES {
+                                    let mut children = children.clone();
+                                    children.push(tree.clone());
+                                    entry.push(children);
+                                }
+                            }
+                        }
+                    }
+                }
+                partial = next;
+                if partial.is_empty() {
+                    break;
+                }
+            }
+            for (sentence, all_children) in partial {
+                let entry = trees[rule.origin].entry(sentence).or_default();
+                for children in all_children {
+                    let tree = Tree::Node(num, children);
+                    if entry.len() < MAX_TREES && !entry.iter().any(|known| **known == tree) {
+                        entry.push(Rc::new(tree));
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut ambiguous: Vec<(&Vec<usize>, &Vec<Rc<Tree>>)> = trees[grammar.start]
+        .iter()
+        .filter(|(_, trees)| trees.len() == MAX_TREES)
+        .collect();
+    ambiguous.sort_by(|a, b| a.0.len().cmp(&b.0.len()).then_with(|| a.0.cmp(b.0)));
+    let (sentence, found) = ambiguous.first()?;
+    // skip the augmented rule
+    let user_tree = |tree: &Rc<Tree>| match tree.as_ref() {
+        Tree::Node(_, children) => children[0].clone(),
+        Tree::Leaf(_) => tree.clone(),
+    };
+    // the trees are found in hash order, print them in the order of their derivations
+    let mut found = [user_tree(&found[0]), user_tree(&found[1])];
+    found.sort_by_key(|tree| tree.leftmost_derivation());
+    let [first, second] = found;
+    Some((sentence.to_vec(), first, second))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Terminals at the leaves of a tree, left to right.
+    fn leaves(tree: &Tree) -> Vec<usize> {
+        match tree {
+            Tree::Leaf(terminal) => vec![*terminal],
+            Tree::Node(_, children) => children.iter().flat_map(|child| leaves(child)).collect(),
+        }
+    }
+
+    #[test]
+    fn finds_the_shortest_ambiguous_sentence() {
+        let extended_grammar =
+            crate::extended_grammar_of(include_str!("../test_txts/input_ambiguous.txt"));
+        let grammar = &InternedGrammar::new(&extended_grammar);
+        let (sentence, first, second) = find_ambiguity(grammar, 5).unwrap();
+        assert_eq!(
+            crate::interned_names(grammar, &sentence),
+            ["id", "*", "id", "*", "id"]
+        );
+        assert_ne!(first, second);
+        assert_eq!(leaves(&first), sentence);
+        assert_eq!(leaves(&second), sentence);
+        assert_eq!(
+            first.to_string(grammar, &extended_grammar),
+            "[E [E [E id] * [E id]] * [E id]]"
+        );
+        assert_eq!(first.leftmost_derivation(), [2, 2, 4, 4, 4]);
+        assert_eq!(second.leftmost_derivation(), [2, 4, 2, 4, 4]);
+
+        // no sentence of 4 tokens or less has two trees
+        assert!(find_ambiguity(grammar, 4).is_none());
+    }
+
+    #[test]
+    fn unambiguous_grammars_have_no_witness() {
+        for txt in [
+            include_str!("../test_txts/input2.txt"),
+            include_str!("../test_txts/input5.txt"),
+        ] {
+            let grammar = InternedGrammar::new(&crate::extended_grammar_of(txt));
+            assert!(find_ambiguity(&grammar, 6).is_none());
+        }
+    }
+
+    #[test]
+    fn the_empty_sentence_can_be_ambiguous() {
+        let extended_grammar = crate::extended_grammar_of("3\nS -> A\nS -> ' '\nA -> ' '\n");
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (sentence, first, second) = find_ambiguity(&grammar, 2).unwrap();
+        assert!(sentence.is_empty());
+        let trees = [
+            first.to_string(&grammar, &extended_grammar),
+            second.to_string(&grammar, &extended_grammar),
+        ];
+        assert!(trees.contains(&"[S [A]]".to_string()));
+        assert!(trees.contains(&"[S]".to_string()));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::driver::{self, ParseEvent, ParseResult};
+use crate::json::Json;
+use crate::SlrRule;
+
+/// How a rule builds its AST node, written in the grammar file as annotations on the rule:
+/// `sym:!` drops a symbol, `sym:^` lifts a child to be the node, and a trailing `:@Name`
+/// names the node (by default nodes are named after the rule's origin).
+/// For example `E -> E +:^ T` builds `(+ E T)` and `F -> (:! E ):!` gives `E` itself.
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct AstShape {
+    pub name: Option<String>,
+    /// Positions of the dropped symbols in the rule body.
+    pub dropped: Vec<usize>,
+    /// Position of the lifted symbol in the rule body.
+    pub lifted: Option<usize>,
+}
+
+impl AstShape {
+    pub fn is_default(&self) -> bool {
+        *self == AstShape::default()
+    }
+}
+
+/// Removes the annotations from the symbols of a production and returns them. The `:`
+/// keeps terminals such as `!`, `^`, `a^` or `@x` from being read as annotations.
+pub fn split_annotations(prod: &mut Vec<String>) -> Result<AstShape, String> {
+    let mut shape = AstShape::default();
+    if let Some(name) = prod.last().and_then(|last| last.strip_prefix(":@")) {
+        if !name.is_empty() {
+            shape.name = Some(name.to_string());
+            prod.pop();
+        }
+    }
+    for (pos, symbol) in prod.iter_mut().enumerate() {
+        let base_len = symbol.len().saturating_sub(2);
+        if base_len == 0 || symbol == "' '" {
+            continue;
+        }
+        if let Some(base) = symbol.strip_suffix(":!") {
+            *symbol = base.to_string();
+            shape.dropped.push(pos);
+        } else if let Some(base) = symbol.strip_suffix(":^") {
+            if shape.lifted.is_some() {
+                return Err("only one symbol of a rule can be lifted".to_string());
+            }
+            *symbol = base.to_string();
+            shape.lifted = Some(pos);
+        }
+    }
+    Ok(shape)
+}
+
+/// Production with its annotations written back, as in the grammar file.
+pub fn annotated_body(rule: &SlrRule, shape: &AstShape) -> String {
+    let mut symbols: Vec<String> = rule
+        .prod
+        .iter()
+        .enumerate()
+        .map(|(pos, symbol)| {
+            if shape.dropped.contains(&pos) {
+                format!("{}:!", symbol)
+            } else if shape.lifted == Some(pos) {
+                format!("{}:^", symbol)
+            } else {
+                symbol.clone()
+            }
+        })
+        .collect();
+    if let Some(name) = &shape.name {
+        symbols.push(format!(":@{}", name));
+    }
+    symbols.join(" ")
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Ast {
+    Leaf { kind: String, lexeme: String },
+    Node { name: String, children: Vec<Ast> },
+}
+
+impl fmt::Display for Ast {
+    /// S-expression: `(name child child)`, with leaves written as their lexeme.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ast::Leaf { lexeme, .. } => write!(f, "{}", sexpr_atom(lexeme)),
+            Ast::Node { name, children } => {
+                write!(f, "({}", sexpr_atom(name))?;
+                for child in children.iter() {
+                    write!(f, " {}", child)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+fn sexpr_atom(txt: &str) -> String {
+    if txt.is_empty() || txt.contains(|c: char| c.is_whitespace() || "()\"".contains(c)) {
+        format!("{:?}", txt)
+    } else {
+        txt.to_string()
+    }
+}
+
+impl Ast {
+    /// `{"node": name, "children": [...]}` for nodes and `{"token": kind, "lexeme": text}`
+    /// for leaves.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Ast::Leaf { kind, lexeme } => Json::Object(vec![
+                ("token".to_string(), Json::String(kind.clone())),
+                ("lexeme".to_string(), Json::String(lexeme.clone())),
+            ]),
+            Ast::Node { name, children } => Json::Object(vec![
+                ("node".to_string(), Json::String(name.clone())),
+                (
+                    "children".to_string(),
+                    Json::Array(children.iter().map(|child| child.to_json()).collect()),
+                ),
+            ]),
+        }
+    }
+}
+
+/// Node for a reduction of `rule` with the trees of its body symbols.
+fn shape_node(rule: &SlrRule, shape: &AstShape, children: Vec<Ast>) -> Ast {
+    let mut lifted = None;
+    let mut kept = Vec::new();
+    for (pos, child) in children.into_iter().enumerate() {
+        if shape.lifted == Some(pos) {
+            lifted = Some(child);
+        } else if !shape.dropped.contains(&pos) {
+            kept.push(child);
+        }
+    }
+
+    match lifted {
+        // a lifted token alone is the tree itself, as `F -> num:^`
+        Some(leaf @ Ast::Leaf { .. }) if kept.is_empty() && shape.name.is_none() => leaf,
+        // the lifted symbol becomes the node, with the other symbols as its children
+        Some(Ast::Leaf { lexeme, .. }) => Ast::Node {
+            name: shape.name.clone().unwrap_or(lexeme),
+            children: kept,
+        },
+        Some(Ast::Node { name, mut children }) => {
+            children.extend(kept);
+            Ast::Node {
+                name: shape.name.clone().unwrap_or(name),
+                children,
+            }
+        }
+        // only drops: a single remaining child stands for the whole rule
+        None if shape.name.is_none() && !shape.dropped.is_empty() && kept.len() == 1 => {
+            kept.pop().unwrap()
+        }
+        None => Ast::Node {
+            name: shape.name.clone().unwrap_or_else(|| rule.origin.clone()),
+            children: kept,
+        },
+    }
+}
+
+/// Trees of the symbols on the parser stack, updated with the events of a parse so the AST
+/// is built in the same pass that evaluates the semantic actions.
+#[derive(Default)]
+pub struct AstBuilder {
+    trees: Vec<Ast>,
+}
+
+impl AstBuilder {
+    /// Applies a step of the parse of `tokens` (kind and lexeme).
+    pub fn event(
+        &mut self,
+        event: &ParseEvent,
+        extended_grammar: &[SlrRule],
+        tokens: &[(String, String)],
+    ) {
+        match *event {
+            ParseEvent::Shift(pos) => self.trees.push(Ast::Leaf {
+                kind: tokens[pos].0.clone(),
+                lexeme: tokens[pos].1.clone(),
+            }),
+            ParseEvent::Reduce(rule) => {
+                let rule = &extended_grammar[rule];
+                let children = self
+                    .trees
+                    .split_off(self.trees.len() - driver::rule_len(rule));
+                self.trees.push(shape_node(rule, &rule.ast, children));
+            }
+            ParseEvent::Recover(popped) => {
+                self.trees.truncate(self.trees.len() - popped);
+                self.trees.push(Ast::Leaf {
+                    kind: driver::ERROR_TERMINAL.to_string(),
+                    lexeme: driver::ERROR_TERMINAL.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Tree of the start symbol, if the input was accepted.
+    pub fn finish(mut self, result: &ParseResult) -> Option<Ast> {
+        if result.accepted {
+            self.trees.pop()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic;
+    use crate::symbols::InternedGrammar;
+
+    fn symbols(txt: &str) -> Vec<String> {
+        txt.split_whitespace()
+            .map(|symbol| symbol.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn annotations_need_a_colon() {
+        let mut prod = symbols("a! b^ ! ^ c:! d:^ :@Name");
+        let shape = split_annotations(&mut prod).unwrap();
+        assert_eq!(prod, symbols("a! b^ ! ^ c d"));
+        assert_eq!(shape.dropped, vec![4]);
+        assert_eq!(shape.lifted, Some(5));
+        assert_eq!(shape.name.as_deref(), Some("Name"));
+
+        // a terminal that starts with `@` is not a name
+        let mut prod = symbols("x @y");
+        assert!(split_annotations(&mut prod).unwrap().is_default());
+        assert_eq!(prod, symbols("x @y"));
+
+        let mut prod = symbols("a:^ b:^");
+        assert!(split_annotations(&mut prod).is_err());
+    }
+
+    /// Tree and value of `input` (tokens written as `kind:lexeme`) for a grammar file.
+    fn parse(txt: &str, input: &str) -> (Option<Ast>, Option<Result<semantic::Value, String>>) {
+        let extended_grammar = crate::extended_grammar_of(txt);
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        let tokens = driver::tokenize_with_lexemes(input);
+        let mut builder = AstBuilder::default();
+        let (result, value) =
+            semantic::evaluate(&table, &grammar, &extended_grammar, &tokens, |event| {
+                builder.event(event, &extended_grammar, &tokens)
+            });
+        (builder.finish(&result), value)
+    }
+
+    #[test]
+    fn builds_the_ast_while_evaluating() {
+        let (tree, value) = parse(
+            include_str!("../test_txts/input_ast.txt"),
+            "num:2 * ( num:3 + num:4 )",
+        );
+        assert_eq!(value.unwrap().unwrap().to_string(), "14");
+        assert_eq!(tree.unwrap().to_string(), "(* 2 (+ 3 4))");
+    }
+
+    #[test]
+    fn names_nodes_and_keeps_at_terminals() {
+        let txt = "2\nS -> S @ x :@Pair\nS -> x";
+        let extended_grammar = crate::extended_grammar_of(txt);
+        assert_eq!(extended_grammar[1].prod, symbols("S @ x"));
+        assert_eq!(
+            annotated_body(&extended_grammar[1], &extended_grammar[1].ast),
+            "S @ x :@Pair"
+        );
+        let (tree, _) = parse(txt, "x @ x");
+        assert_eq!(tree.unwrap().to_string(), "(Pair (S x) @ x)");
+    }
+}
@@ -0,0 +1,315 @@
+use crate::counterexample::action_string;
+use crate::ll;
+use crate::lr1::{self, Lr1State};
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{Action, SlrItem, SlrRow, SlrRule, SlrState};
+
+/// Result of checking the grammar against one class.
+pub struct ClassResult {
+    pub class: &'static str,
+    /// States of the automaton the table is built from (`None` for LL(1)).
+    pub states: Option<usize>,
+    /// Conflicting cells of the table, or conflicting states for LR(0), where a reduction
+    /// fills the whole row.
+    pub conflicts: usize,
+    /// Why the grammar isn't in the class: the first conflict and the items causing it.
+    pub explanation: Option<String>,
+}
+
+/// Builds the LL(1), LR(0), SLR(1), LALR(1) and canonical LR(1) tables of the grammar and
+/// counts their conflicts. `slr` and `table` are the LR(0) automaton and SLR(1) table
+/// already built.
+pub fn classify(
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    slr: &[SlrState],
+    table: &[SlrRow],
+) -> Vec<ClassResult> {
+    let mut ret = Vec::new();
+
+    // LL(1): rules of a non terminal with overlapping predict sets
+    let ll_conflicts = ll::ll1_conflicts(grammar);
+    ret.push(ClassResult {
+        class: "LL(1)",
+        states: None,
+        conflicts: ll_conflicts.len(),
+        explanation: ll_conflicts
+            .first()
+            .map(|conflict| ll::conflict_to_string(conflict, extended_grammar)),
+    });
+
+    // LR(0): complete items reduce on every terminal, so conflicts are per state
+    let mut lr0_conflicts = Vec::new();
+    for (num, state) in slr.iter().enumerate() {
+        for terminal in 0..=grammar.eof {
+            let mut actions = Vec::new();
+            if let Some((_, target)) = state
+                .transitions
+                .iter()
+                .find(|(symbol, _)| *symbol == terminal)
+            {
+                actions.push(Action::S(*target));
+            }
+            for rule in state.get_end_rules(grammar) {
+                if rule != 0 {
+                    actions.push(Action::R(rule));
+                } else if terminal == grammar.eof {
+                    actions.push(Action::Acc);
+                }
+            }
+            if actions.len() > 1 {
+                // a state that reduces conflicts on every terminal, count it once
+                lr0_conflicts.push((num, terminal, actions));
+                break;
+            }
+        }
+    }
+    ret.push(ClassResult {
+        class: "LR(0)",
+        states: Some(slr.len()),
+        conflicts: lr0_conflicts.len(),
+        explanation: lr0_conflicts.first().map(|(state, terminal, actions)| {
+            let items = slr[*state]
+                .items()
+                .filter(|item| involved(item, *terminal, grammar))
+                .map(|item| item.to_string(extended_grammar))
+                .collect();
+            describe(
+                *state,
+                grammar.name(*terminal),
+                actions,
+                items,
+                extended_grammar,
+            ) + "\n    (LR(0) reduces on every terminal)"
+        }),
+    });
+
+    // SLR(1): the table already built, reducing on FOLLOW
+    let mut slr_conflicts = Vec::new();
+    for (num, row) in table.iter().enumerate() {
+        let mut terminals: Vec<usize> = row.conflicts.keys().copied().collect();
+        terminals.sort();
+        for terminal in terminals {
+            slr_conflicts.push((num, terminal, row.conflicts[&terminal].clone()));
+        }
+    }
+    ret.push(ClassResult {
+        class: "SLR(1)",
+        states: Some(slr.len()),
+        conflicts: slr_conflicts.len(),
+        explanation: slr_conflicts.first().map(|(state, terminal, actions)| {
+            let terminal_name = grammar.name(*terminal);
+            let items = slr[*state]
+                .items()
+                .filter(|item| involved(item, *terminal, grammar))
+                .map(|item| item.to_string(extended_grammar))
+                .collect();
+            let origins: Vec<String> = actions
+                .iter()
+                .filter_map(|action| match action {
+                    Action::R(rule) => Some(extended_grammar[*rule].origin.clone()),
+                    _ => None,
+                })
+                .collect();
+            let mut ret = describe(*state, terminal_name, actions, items, extended_grammar);
+            if !origins.is_empty() {
+                ret += &format!(
+                    "\n    ({} is in FOLLOW({}))",
+                    terminal_name,
+                    origins.join("), FOLLOW(")
+                );
+            }
+            ret
+        }),
+    });
+
+    // LALR(1) and LR(1): lookaheads carried by the items
+    let lr1_states = lr1::build_lr1(grammar);
+    let lalr_states = lr1::merge_lalr(&lr1_states);
+    for (class, states) in [("LALR(1)", &lalr_states), ("LR(1)", &lr1_states)] {
+        let conflicts = lr1_conflicts(grammar, states);
+        ret.push(ClassResult {
+            class,
+            states: Some(states.len()),
+            conflicts: conflicts.len(),
+            explanation: conflicts.first().map(|(state, terminal, actions)| {
+                let lr1_state = &states[*state];
+                let items = lr1_state
+                    .items
+                    .iter()
+                    .filter(|(item, _)| involved(item, *terminal, grammar))
+                    .map(|(item, lookaheads)| {
+                        Lr1State::item_string(item, lookaheads, grammar, extended_grammar)
+                    })
+                    .collect();
+                let mut ret =
+                    describe(*state, grammar.name(*terminal), actions, items, extended_grammar);
+                if class == "LALR(1)" && lr1_state.merged_from.len() > 1 {
+                    let merged: Vec<String> = lr1_state
+                        .merged_from
+                        .iter()
+                        .map(|num| num.to_string())
+                        .collect();
+                    ret += &format!(
+                        "\n    (the state merges LR(1) states {}, with the union of their lookaheads)",
+                        merged.join(", ")
+                    );
+                }
+                ret
+            }),
+        });
+    }
+    ret
+}
+
+/// Conflicting cells of an LR(1) or LALR(1) automaton: state, terminal id and actions.
+fn lr1_conflicts(
+    grammar: &InternedGrammar,
+    states: &[Lr1State],
+) -> Vec<(usize, usize, Vec<Action>)> {
+    let mut ret = Vec::new();
+    for (num, state) in states.iter().enumerate() {
+        for (terminal, actions) in lr1::state_actions(grammar, state) {
+            if actions.len() > 1 {
+                ret.push((num, terminal, actions));
+            }
+        }
+    }
+    ret
+}
+
+/// Whether an item is worth showing for a conflict on `terminal`: kernel items, plus the
+/// closure items that reduce or shift the terminal.
+fn involved(item: &SlrItem, terminal: usize, grammar: &InternedGrammar) -> bool {
+    item.dot > 0
+        || item.rule == 0
+        || item.is_complete(grammar)
+        || item.get_reading_symbol(grammar) == Some(terminal)
+}
+
+/// `state 4 on +: shift 6 / reduce 1. E -> E + E`, followed by the items.
+fn describe(
+    state: usize,
+    terminal: &str,
+    actions: &[Action],
+    items: Vec<String>,
+    extended_grammar: &[SlrRule],
+) -> String {
+    let actions: Vec<String> = actions
+        .iter()
+        .map(|action| action_string(action, extended_grammar))
+        .collect();
+    let mut ret = format!("state {} on {}: {}", state, terminal, actions.join(" / "));
+    for item in items {
+        ret += &format!("\n      {}", item);
+    }
+    ret
+}
+
+/// Summary table: one row per class with its states, conflicts and whether the grammar
+/// belongs to it.
+pub fn classification_table(results: &[ClassResult]) -> TextTable {
+    TextTable {
+        headers: vec![
+            "class".to_string(),
+            "states".to_string(),
+            "conflicts".to_string(),
+            "belongs".to_string(),
+        ],
+        rows: results
+            .iter()
+            .map(|result| {
+                vec![
+                    result.class.to_string(),
+                    result
+                        .states
+                        .map_or("-".to_string(), |states| states.to_string()),
+                    result.conflicts.to_string(),
+                    if result.conflicts == 0 { "yes" } else { "no" }.to_string(),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Classification of a grammar, with the SLR(1) table built from it.
+    fn results(txt: &str) -> Vec<ClassResult> {
+        let extended_grammar = crate::extended_grammar_of(txt);
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (slr, table) = crate::slr_table_of(&grammar);
+        classify(&grammar, &extended_grammar, &slr, &table)
+    }
+
+    /// Class, states and conflicts of every result.
+    fn summary(txt: &str) -> Vec<(&'static str, Option<usize>, usize)> {
+        results(txt)
+            .iter()
+            .map(|result| (result.class, result.states, result.conflicts))
+            .collect()
+    }
+
+    #[test]
+    fn ll1_grammar_is_in_every_class_but_lr0() {
+        assert_eq!(
+            summary(include_str!("../test_txts/input1.txt")),
+            [
+                ("LL(1)", None, 0),
+                ("LR(0)", Some(16), 4),
+                ("SLR(1)", Some(16), 0),
+                ("LALR(1)", Some(16), 0),
+                ("LR(1)", Some(30), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn lr0_counts_each_conflicting_state_once() {
+        // after `a` the reduction fills the row, clashing with the shifts of `b` and `c`
+        let results = results("3\nS -> a\nS -> a b\nS -> a c\n");
+        assert_eq!(
+            (results[1].class, results[1].states, results[1].conflicts),
+            ("LR(0)", Some(5), 1)
+        );
+        assert!(results[1]
+            .explanation
+            .as_ref()
+            .unwrap()
+            .ends_with("(LR(0) reduces on every terminal)"));
+        assert_eq!((results[2].class, results[2].conflicts), ("SLR(1)", 0));
+    }
+
+    #[test]
+    fn lalr_lookaheads_solve_what_follow_does_not() {
+        let txt = "5\nS -> L = R\nS -> R\nL -> * R\nL -> id\nR -> L\n";
+        assert_eq!(
+            summary(txt),
+            [
+                ("LL(1)", None, 1),
+                ("LR(0)", Some(10), 1),
+                ("SLR(1)", Some(10), 1),
+                ("LALR(1)", Some(10), 0),
+                ("LR(1)", Some(14), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn merging_lr1_states_can_add_conflicts() {
+        let txt = "6\nS -> a A d\nS -> b B d\nS -> a B e\nS -> b A e\nA -> c\nB -> c\n";
+        let results = results(txt);
+        let lalr = &results[3];
+        assert_eq!((lalr.class, lalr.conflicts), ("LALR(1)", 2));
+        assert!(lalr
+            .explanation
+            .as_ref()
+            .unwrap()
+            .contains("the state merges LR(1) states 4, 7"));
+        assert_eq!((results[4].class, results[4].conflicts), ("LR(1)", 0));
+        assert_eq!(results[4].explanation, None);
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::symbols::InternedGrammar;
+use crate::{Action, SlrRow, SlrRule};
+
+/// Lowercase words naming a grammar symbol, used to build identifiers in generated code.
+/// Letters and digits are kept (`num_lit` gives `num`, `lit`) and punctuation is spelled out
+/// (`(` gives `left`, `paren`, `==` gives `equals`, `equals`).
+pub fn symbol_words(name: &str) -> Vec<String> {
+    words_of(name, false)
+}
+
+/// Same as `symbol_words`, but also splitting at case changes (`NumLit` gives `num`, `lit`),
+/// so the recursive descent parser can name its functions `parse_e_prime`.
+pub fn symbol_words_by_case(name: &str) -> Vec<String> {
+    words_of(name, true)
+}
+
+fn words_of(name: &str, split_case: bool) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    for (i, chr) in chars.iter().copied().enumerate() {
+        if chr.is_ascii_alphanumeric() {
+            let prev = if i > 0 { chars[i - 1] } else { ' ' };
+            let next = chars.get(i + 1).copied().unwrap_or(' ');
+            let new_word = split_case
+                && chr.is_ascii_uppercase()
+                && (prev.is_ascii_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_ascii_uppercase() && next.is_ascii_lowercase()));
+            if new_word && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.push(chr.to_ascii_lowercase());
+            continue;
+        }
+        if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        let spelled: &[&str] = match chr {
+            '+' => &["plus"],
+            '-' => &["minus"],
+            '*' => &["star"],
+            '/' => &["slash"],
+            '(' => &["left", "paren"],
+            ')' => &["right", "paren"],
+            '[' => &["left", "bracket"],
+            ']' => &["right", "bracket"],
+            '{' => &["left", "brace"],
+            '}' => &["right", "brace"],
+            ',' => &["comma"],
+            ';' => &["semicolon"],
+            ':' => &["colon"],
+            '.' => &["dot"],
+            '=' => &["equals"],
+            '<' => &["less"],
+            '>' => &["greater"],
+            '!' => &["bang"],
+            '?' => &["question"],
+            '&' => &["amp"],
+            '|' => &["pipe"],
+            '^' => &["caret"],
+            '%' => &["percent"],
+            '#' => &["hash"],
+            '@' => &["at"],
+            '~' => &["tilde"],
+            '\'' => &["quote"],
+            '"' => &["double", "quote"],
+            '\\' => &["backslash"],
+            '$' => &["dollar"],
+            _ => &[],
+        };
+        if !spelled.is_empty() {
+            words.extend(spelled.iter().map(|w| w.to_string()));
+        } else if chr != '_' && !chr.is_whitespace() {
+            words.push(format!("u{:x}", chr as u32));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if words.is_empty() || words[0].starts_with(|c: char| c.is_ascii_digit()) {
+        words.insert(0, "t".to_string());
+    }
+    words
+}
+
+/// Identifiers for `names` built with `to_ident`, with a numeric suffix on the ones that
+/// would clash with a previous identifier or with `reserved`.
+pub fn unique_idents(
+    names: &[String],
+    reserved: &[&str],
+    to_ident: impl Fn(&[String]) -> String,
+) -> Vec<String> {
+    unique_idents_with(names, reserved, symbol_words, to_ident)
+}
+
+/// Same as `unique_idents`, splitting the names into words with `words`.
+pub fn unique_idents_with(
+    names: &[String],
+    reserved: &[&str],
+    words: fn(&str) -> Vec<String>,
+    to_ident: impl Fn(&[String]) -> String,
+) -> Vec<String> {
+    let mut used: HashSet<String> = reserved.iter().map(|s| s.to_string()).collect();
+    let mut ret = Vec::new();
+    for name in names.iter() {
+        let base = to_ident(&words(name));
+        let mut ident = base.clone();
+        let mut suffix = 2;
+        while used.contains(&ident) {
+            ident = format!("{}{}", base, suffix);
+            suffix += 1;
+        }
+        used.insert(ident.clone());
+        ret.push(ident);
+    }
+    ret
+}
+
+pub fn camel_case(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Names of the terminals in table column order (sorted, then `$`) and of the non
+/// terminals sorted, the same columns as the printed table. The columns are the symbol ids.
+pub fn table_columns(grammar: &InternedGrammar) -> (Vec<String>, Vec<String>) {
+    let names = |ids: std::ops::Range<usize>| ids.map(|id| grammar.name(id).clone()).collect();
+    (names(0..grammar.eof + 1), names(grammar.non_terminals()))
+}
+
+/// States with at least one conflicting cell, which the generated parsers treat as errors.
+pub fn conflict_states(table: &[SlrRow]) -> Vec<usize> {
+    table
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| !row.conflicts.is_empty())
+        .map(|(state, _)| state)
+        .collect()
+}
+
+/// Escapes a string to be written inside a comment of generated code.
+pub fn comment(txt: &str) -> String {
+    txt.replace("*/", "* /").replace('\n', " ")
+}
+
+/// Rust module with the parse table as static arrays, `Token` and `NonTerminal` enums and a
+/// `parse` function. Every reduction calls back with the rule number of the augmented
+/// grammar and the values of the reduced symbols, and its result becomes the value of the
+/// non terminal.
+pub fn rust_parser(
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    table: &[SlrRow],
+) -> String {
+    let (terminals, non_terminals) = table_columns(grammar);
+    let user_terminals = &terminals[..terminals.len() - 1];
+    let mut token_idents = unique_idents(user_terminals, &["Eof"], camel_case);
+    token_idents.push("Eof".to_string());
+    let non_terminal_idents = unique_idents(&non_terminals, &[], camel_case);
+
+    let mut ret = String::new();
+    ret += "// Parser generated by proyecto_compiladores, do not edit.\n";
+    ret += "//\n";
+    ret += "// Rules of the augmented grammar (the numbers passed to the reduce callback):\n";
+    for rule in extended_grammar.iter() {
+        let _ = writeln!(ret, "//   {}", comment(rule.to_string().trim_end()));
+    }
+    let conflicts = conflict_states(table);
+    if !conflicts.is_empty() {
+        let states: Vec<String> = conflicts.iter().map(|s| s.to_string()).collect();
+        let _ = writeln!(
+            ret,
+            "//\n// WARNING: the grammar has conflicts in states {}; those cells are errors.",
+            states.join(", ")
+        );
+    }
+    ret += "\n#![allow(dead_code)]\n\n";
+
+    // enums
+    ret += "/// Terminals of the grammar, in table column order.\n";
+    ret += "#[derive(Clone, Copy, PartialEq, Eq, Debug)]\n";
+    ret += "pub enum Token {\n";
+    for (ident, name) in token_idents.iter().zip(terminals.iter()) {
+        let _ = writeln!(ret, "    /// `{}`\n    {},", comment(name), ident);
+    }
+    ret += "}\n\n";
+    ret += "/// Non terminals of the grammar, in table column order.\n";
+    ret += "#[derive(Clone, Copy, PartialEq, Eq, Debug)]\n";
+    ret += "pub enum NonTerminal {\n";
+    for (ident, name) in non_terminal_idents.iter().zip(non_terminals.iter()) {
+        let _ = writeln!(ret, "    /// `{}`\n    {},", comment(name), ident);
+    }
+    ret += "}\n\n";
+
+    ret += "impl Token {\n";
+    ret += "    /// Name of the terminal in the grammar.\n";
+    ret += "    pub fn name(self) -> &'static str {\n";
+    ret += "        TERMINALS[self as usize]\n";
+    ret += "    }\n\n";
+    ret += "    /// Token for a terminal written as in the grammar.\n";
+    ret += "    pub fn from_name(name: &str) -> Option<Token> {\n";
+    ret += "        TOKENS.iter().copied().find(|token| token.name() == name)\n";
+    ret += "    }\n";
+    ret += "}\n\n";
+
+    let _ = writeln!(
+        ret,
+        "pub const TERMINALS: [&str; {}] = [{}];",
+        terminals.len(),
+        terminals
+            .iter()
+            .map(|t| format!("{:?}", t))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let _ = writeln!(
+        ret,
+        "const TOKENS: [Token; {}] = [{}];",
+        token_idents.len(),
+        token_idents
+            .iter()
+            .map(|t| format!("Token::{}", t))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let _ = writeln!(
+        ret,
+        "pub const NON_TERMINALS: [&str; {}] = [{}];\n",
+        non_terminals.len(),
+        non_terminals
+            .iter()
+            .map(|t| format!("{:?}", t))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+
+    // tables
+    ret += "#[derive(Clone, Copy, PartialEq, Eq, Debug)]\n";
+    ret += "enum Action {\n    Error,\n    Shift(usize),\n    Reduce(usize),\n    Accept,\n}\n\n";
+    ret += "/// Non terminal and body length of every rule (rule 0 is the augmented start rule).\n";
+    let _ = writeln!(
+        ret,
+        "const RULES: [(Option<NonTerminal>, usize); {}] = [",
+        extended_grammar.len()
+    );
+    for rule in extended_grammar.iter() {
+        let lhs = match non_terminals.iter().position(|n| *n == rule.origin) {
+            Some(idx) => format!("Some(NonTerminal::{})", non_terminal_idents[idx]),
+            None => "None".to_string(),
+        };
+        let _ = writeln!(ret, "    ({}, {}),", lhs, rule.body().len());
+    }
+    ret += "];\n\n";
+
+    let _ = writeln!(
+        ret,
+        "static ACTION: [[Action; {}]; {}] = [",
+        terminals.len(),
+        table.len()
+    );
+    for row in table.iter() {
+        let cells: Vec<String> = (0..terminals.len())
+            .map(|term| match row.actions.get(&term) {
+                Some(Action::S(next)) => format!("Action::Shift({})", next),
+                Some(Action::R(rule)) => format!("Action::Reduce({})", rule),
+                Some(Action::Acc) => "Action::Accept".to_string(),
+                Some(Action::Err) | None => "Action::Error".to_string(),
+            })
+            .collect();
+        let _ = writeln!(ret, "    [{}],", cells.join(", "));
+    }
+    ret += "];\n\n";
+
+    let _ = writeln!(
+        ret,
+        "static GOTO: [[Option<usize>; {}]; {}] = [",
+        non_terminals.len(),
+        table.len()
+    );
+    for row in table.iter() {
+        let cells: Vec<String> = grammar
+            .non_terminals()
+            .map(|nterm| match row.gotos.get(&nterm) {
+                Some(next) => format!("Some({})", next),
+                None => "None".to_string(),
+            })
+            .collect();
+        let _ = writeln!(ret, "    [{}],", cells.join(", "));
+    }
+    ret += "];\n\n";
+
+    ret += PARSE_FN;
+    ret
+}
+
+const PARSE_FN: &str =
+    "/// Position (number of tokens read before it) and kind of the token that couldn't be parsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub position: usize,
+    pub token: Token,
+}
+
+/// Shift/reduce parser. `tokens` gives every token with its value (without `Token::Eof`).
+/// On each reduction `reduce` gets the rule number and the values of the symbols of its
+/// body, and returns the value of the reduced non terminal. Returns the value of the start
+/// symbol.
+pub fn parse<V>(
+    tokens: impl IntoIterator<Item = (Token, V)>,
+    mut reduce: impl FnMut(usize, Vec<V>) -> V,
+) -> Result<V, ParseError> {
+    let mut tokens = tokens.into_iter();
+    let mut states: Vec<usize> = vec![0];
+    let mut values: Vec<V> = Vec::new();
+    let mut position = 0;
+    let mut lookahead = tokens.next();
+
+    loop {
+        let state = *states.last().unwrap();
+        let token = lookahead.as_ref().map_or(Token::Eof, |(token, _)| *token);
+        match ACTION[state][token as usize] {
+            Action::Shift(next) => {
+                // `$` is never shifted, so there is a token here
+                let (_, value) = lookahead.take().unwrap();
+                values.push(value);
+                states.push(next);
+                lookahead = tokens.next();
+                position += 1;
+            }
+            Action::Reduce(rule) => {
+                let (lhs, len) = RULES[rule];
+                let children = values.split_off(values.len() - len);
+                states.truncate(states.len() - len);
+                let top = *states.last().unwrap();
+                let next = lhs.and_then(|lhs| GOTO[top][lhs as usize]);
+                match next {
+                    Some(next) => {
+                        values.push(reduce(rule, children));
+                        states.push(next);
+                    }
+                    None => return Err(ParseError { position, token }),
+                }
+            }
+            Action::Accept => return Ok(values.pop().unwrap()),
+            Action::Error => return Err(ParseError { position, token }),
+        }
+    }
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_descent_parser_splits_by_case() {
+        assert_eq!(symbol_words("EPrime"), vec!["eprime"]);
+        assert_eq!(symbol_words("num_lit"), vec!["num", "lit"]);
+        assert_eq!(symbol_words_by_case("EPrime"), vec!["e", "prime"]);
+        assert_eq!(symbol_words_by_case("NumLit"), vec!["num", "lit"]);
+    }
+
+    #[test]
+    fn clashing_identifiers_get_a_suffix() {
+        let names: Vec<String> = ["a_b", "a-b", "eof", "1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            unique_idents(&names, &["Eof"], camel_case),
+            ["AB", "AMinusB", "Eof2", "T1"]
+        );
+        let names = vec!["ab".to_string(), "a_b".to_string()];
+        assert_eq!(
+            unique_idents(&names, &[], |words| words.concat()),
+            ["ab", "ab2"]
+        );
+    }
+
+    #[test]
+    fn rust_parser_has_the_slr_table() {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input2.txt"));
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        let code = rust_parser(&grammar, &extended_grammar, &table);
+        assert!(code.contains(
+            "const TOKENS: [Token; 6] = [Token::LeftParen, Token::RightParen, Token::Star, \
+             Token::Plus, Token::Id, Token::Eof];\n"
+        ));
+        assert!(code.contains("pub const NON_TERMINALS: [&str; 3] = [\"E\", \"F\", \"T\"];\n"));
+        assert!(code.contains("    (None, 1),\n    (Some(NonTerminal::E), 3),\n"));
+        assert!(code.contains(
+            "static ACTION: [[Action; 6]; 12] = [\n    [Action::Shift(1), Action::Error, \
+             Action::Error, Action::Error, Action::Shift(2), Action::Error],\n"
+        ));
+        assert!(code.contains(
+            "static GOTO: [[Option<usize>; 3]; 12] = [\n    [Some(3), Some(4), Some(5)],\n"
+        ));
+        assert!(!code.contains("WARNING"));
+
+        let extended_grammar = crate::extended_grammar_of("2\nE -> E + E\nE -> id");
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        let code = rust_parser(&grammar, &extended_grammar, &table);
+        let states: Vec<String> = conflict_states(&table)
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert!(code.contains(&format!(
+            "// WARNING: the grammar has conflicts in states {}; those cells are errors.",
+            states.join(", ")
+        )));
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt::Write;
+
+use crate::codegen::{comment, conflict_states, symbol_words, table_columns, unique_idents};
+use crate::compress::CompressedTable;
+use crate::symbols::InternedGrammar;
+use crate::{Action, SlrRow, SlrRule};
+
+/// Header and source of a C parser. `prefix` (e.g. the name of the output file) is put in
+/// front of every public name, so several parsers can be linked together. The tables are
+/// the compressed ones, with default reductions, and the token constants and rule numbers
+/// are the same as in the generated Rust module.
+pub fn c_parser(
+    prefix: &str,
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    table: &[SlrRow],
+) -> (String, String) {
+    let prefix = c_prefix(prefix);
+    let upper = prefix.to_uppercase();
+    let compressed = CompressedTable::new(table, grammar, true);
+    let (terminals, non_terminals) = table_columns(grammar);
+    let user_terminals = &terminals[..terminals.len() - 1];
+    let mut token_idents = unique_idents(user_terminals, &["EOF"], |words| words.join("_"));
+    token_idents.push("EOF".to_string());
+    let token_idents: Vec<String> = token_idents.iter().map(|t| t.to_uppercase()).collect();
+
+    // header
+    let mut header = String::new();
+    let _ = writeln!(header, "#ifndef {}_H\n#define {}_H\n", upper, upper);
+    header += "/* Parser generated by proyecto_compiladores, do not edit. */\n\n";
+    header += "/* Token kinds, in table column order. */\n";
+    for (i, (ident, name)) in token_idents.iter().zip(terminals.iter()).enumerate() {
+        let _ = writeln!(
+            header,
+            "#define {}_TOK_{} {} /* {} */",
+            upper,
+            ident,
+            i,
+            comment(name)
+        );
+    }
+    let _ = writeln!(header, "#define {}_NUM_TOKENS {}\n", upper, terminals.len());
+    header += &HEADER
+        .replace("PREFIX_", &format!("{}_", upper))
+        .replace("prefix_", &format!("{}_", prefix));
+
+    // source
+    let mut source = String::new();
+    source += "/* Parser generated by proyecto_compiladores, do not edit.\n\n";
+    source += "   Rules of the augmented grammar (the numbers passed to reduce):\n";
+    for rule in extended_grammar.iter() {
+        let _ = writeln!(source, "     {}", comment(rule.to_string().trim_end()));
+    }
+    let conflicts = conflict_states(table);
+    if !conflicts.is_empty() {
+        let states: Vec<String> = conflicts.iter().map(|s| s.to_string()).collect();
+        let _ = writeln!(
+            source,
+            "\n   WARNING: the grammar has conflicts in states {}; those cells are errors.",
+            states.join(", ")
+        );
+    }
+    source += "*/\n\n";
+    let _ = writeln!(source, "#include \"{}.h\"\n", prefix);
+    source += TABLES_COMMENT;
+
+    let action_values: Vec<i64> = compressed
+        .action_value
+        .iter()
+        .map(|action| match action {
+            Some(Action::S(next)) => *next as i64 + 1,
+            Some(Action::R(rule)) => -(*rule as i64) - 1,
+            Some(Action::Acc) => -1,
+            Some(Action::Err) | None => 0,
+        })
+        .collect();
+    let defaults: Vec<i64> = compressed
+        .default_reductions
+        .iter()
+        .map(|rule| rule.map_or(-1, |r| r as i64))
+        .collect();
+    let rule_lhs: Vec<i64> = extended_grammar
+        .iter()
+        .map(|rule| {
+            non_terminals
+                .iter()
+                .position(|n| *n == rule.origin)
+                .map_or(-1, |col| col as i64)
+        })
+        .collect();
+    let rule_length: Vec<i64> = extended_grammar
+        .iter()
+        .map(|rule| rule.body().len() as i64)
+        .collect();
+
+    source += &c_array(&prefix, "action_row", &as_i64(&compressed.action_rows));
+    source += &c_array(&prefix, "default_reduction", &defaults);
+    source += &c_array(&prefix, "action_base", &as_i64(&compressed.action_base));
+    source += &c_array(&prefix, "action_check", &as_i64(&compressed.action_check));
+    source += &c_array(&prefix, "action_value", &action_values);
+    source += &c_array(&prefix, "goto_row", &as_i64(&compressed.goto_rows));
+    source += &c_array(&prefix, "goto_base", &as_i64(&compressed.goto_base));
+    source += &c_array(&prefix, "goto_check", &as_i64(&compressed.goto_check));
+    source += &c_array(&prefix, "goto_value", &as_i64(&compressed.goto_value));
+    source += &c_array(&prefix, "rule_lhs", &rule_lhs);
+    source += &c_array(&prefix, "rule_length", &rule_length);
+
+    let names: Vec<String> = terminals.iter().map(|t| c_string(t)).collect();
+    let _ = writeln!(
+        source,
+        "static const char *const {}_token_names[] = {{{}}};\n",
+        prefix,
+        names.join(", ")
+    );
+    let _ = writeln!(
+        source,
+        "#define ACTION_SLOTS {}\n#define GOTO_SLOTS {}\n",
+        compressed.action_check.len(),
+        compressed.goto_check.len()
+    );
+
+    source += &DRIVER
+        .replace("PREFIX_", &format!("{}_", upper))
+        .replace("prefix_", &format!("{}_", prefix));
+    (header, source)
+}
+
+/// C identifier to put in front of the generated names.
+fn c_prefix(name: &str) -> String {
+    let words = symbol_words(name);
+    words.join("_")
+}
+
+/// C string literal.
+fn c_string(txt: &str) -> String {
+    let mut ret = "\"".to_string();
+    for chr in txt.chars() {
+        match chr {
+            '"' => ret += "\\\"",
+            '\\' => ret += "\\\\",
+            '\n' => ret += "\\n",
+            c if c.is_ascii() && !c.is_ascii_control() => ret.push(c),
+            c => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    let _ = write!(ret, "\\{:03o}", byte);
+                }
+            }
+        }
+    }
+    ret + "\""
+}
+
+/// Free slots of the packed arrays are `usize::MAX`, written as -1.
+fn as_i64(values: &[usize]) -> Vec<i64> {
+    values
+        .iter()
+        .map(|v| if *v == usize::MAX { -1 } else { *v as i64 })
+        .collect()
+}
+
+/// `static const` array, 16 values per line. Empty arrays get a single -1 since C doesn't
+/// allow arrays of size 0.
+fn c_array(prefix: &str, name: &str, values: &[i64]) -> String {
+    let values: Vec<i64> = if values.is_empty() {
+        vec![-1]
+    } else {
+        values.to_vec()
+    };
+    let fits_short = values
+        .iter()
+        .all(|v| *v >= i16::MIN as i64 && *v <= i16::MAX as i64);
+    let ty = if fits_short { "short" } else { "long" };
+    let mut ret = format!("static const {} {}_{}[] = {{\n", ty, prefix, name);
+    for chunk in values.chunks(16) {
+        let cells: Vec<String> = chunk.iter().map(|v| v.to_string()).collect();
+        let _ = writeln!(ret, "    {},", cells.join(", "));
+    }
+    ret + "};\n\n"
+}
+
+const HEADER: &str = "#define PREFIX_STACK_SIZE 1024
+
+/* Returns the kind of the next token, PREFIX_TOK_EOF at the end of the input. */
+typedef int (*prefix_next_token_fn)(void *ctx);
+/* Called when a token is shifted. */
+typedef void (*prefix_shift_fn)(void *ctx, int token);
+/* Called on every reduction with the rule number of the augmented grammar and the number of
+   symbols of its body. */
+typedef void (*prefix_reduce_fn)(void *ctx, int rule, int length);
+
+/* Parses the tokens given by next_token. shift and reduce may be NULL. Returns 0 if the input
+   is accepted, -1 on a syntax error and -2 if the stack overflows. */
+int prefix_parse(prefix_next_token_fn next_token, prefix_shift_fn shift, prefix_reduce_fn reduce,
+    void *ctx);
+
+/* Name of a token kind as written in the grammar. */
+const char *prefix_token_name(int token);
+
+#endif
+";
+
+const TABLES_COMMENT: &str =
+    "/* Actions: 0 is an error, n > 0 shifts to state n - 1 and n < 0 reduces by rule -n - 1
+   (rule 0 accepts). A state looks up its row, and the action for a token is at
+   base[row] + token if check says that slot belongs to the row; otherwise it is the default
+   reduction of the row, if it has one. Gotos are packed the same way. */
+
+";
+
+const DRIVER: &str = "static int prefix_action(int state, int token)
+{
+    int row = prefix_action_row[state];
+    int slot = prefix_action_base[row] + token;
+    if (slot < ACTION_SLOTS && prefix_action_check[slot] == row)
+        return prefix_action_value[slot];
+    if (prefix_default_reduction[row] >= 0)
+        return -prefix_default_reduction[row] - 1;
+    return 0;
+}
+
+static int prefix_goto(int state, int non_terminal)
+{
+    int row = prefix_goto_row[state];
+    int slot = prefix_goto_base[row] + non_terminal;
+    if (slot < GOTO_SLOTS && prefix_goto_check[slot] == row)
+        return prefix_goto_value[slot];
+    return -1;
+}
+
+const char *prefix_token_name(int token)
+{
+    if (token < 0 || token >= PREFIX_NUM_TOKENS)
+        return \"?\";
+    return prefix_token_names[token];
+}
+
+int prefix_parse(prefix_next_token_fn next_token, prefix_shift_fn shift, prefix_reduce_fn reduce,
+    void *ctx)
+{
+    int stack[PREFIX_STACK_SIZE];
+    int top = 0;
+    int token = next_token(ctx);
+    stack[0] = 0;
+
+    for (;;) {
+        int action;
+        if (token < 0 || token >= PREFIX_NUM_TOKENS)
+            return -1;
+        action = prefix_action(stack[top], token);
+        if (action > 0) {
+            if (top + 1 >= PREFIX_STACK_SIZE)
+                return -2;
+            stack[++top] = action - 1;
+            if (shift)
+                shift(ctx, token);
+            token = next_token(ctx);
+        } else if (action < 0) {
+            int rule = -action - 1;
+            int next;
+            if (rule == 0)
+                return 0;
+            if (reduce)
+                reduce(ctx, rule, prefix_rule_length[rule]);
+            top -= prefix_rule_length[rule];
+            next = prefix_goto(stack[top], prefix_rule_lhs[rule]);
+            if (next < 0)
+                return -1;
+            if (top + 1 >= PREFIX_STACK_SIZE)
+                return -2;
+            stack[++top] = next;
+        } else {
+            return -1;
+        }
+    }
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn c_literals_and_arrays() {
+        assert_eq!(c_prefix("my-parser"), "my_minus_parser");
+        assert_eq!(c_string("a\"\\\né"), "\"a\\\"\\\\\\n\\303\\251\"");
+        assert_eq!(
+            c_array("p", "empty", &[]),
+            "static const short p_empty[] = {\n    -1,\n};\n\n"
+        );
+        assert_eq!(
+            c_array("p", "big", &[1, 70000]),
+            "static const long p_big[] = {\n    1, 70000,\n};\n\n"
+        );
+        assert_eq!(as_i64(&[3, usize::MAX]), [3, -1]);
+    }
+
+    #[test]
+    fn c_parser_has_the_tokens_and_rules() {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input2.txt"));
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        let (header, source) = c_parser("calc", &grammar, &extended_grammar, &table);
+        assert!(header.starts_with("#ifndef CALC_H\n#define CALC_H\n"));
+        assert!(header.contains("#define CALC_TOK_LEFT_PAREN 0 /* ( */\n"));
+        assert!(header.contains("#define CALC_TOK_ID 4 /* id */\n"));
+        assert!(header.contains("#define CALC_TOK_EOF 5 /* $ */\n"));
+        assert!(header.contains("#define CALC_NUM_TOKENS 6\n"));
+        assert!(header.contains("int calc_parse(calc_next_token_fn next_token"));
+
+        assert!(source.contains("#include \"calc.h\"\n"));
+        assert!(source
+            .contains("static const short calc_rule_length[] = {\n    1, 3, 1, 1, 3, 3, 1,\n};"));
+        assert!(source
+            .contains("static const short calc_rule_lhs[] = {\n    -1, 0, 0, 1, 1, 2, 2,\n};"));
+        assert!(source.contains(
+            "static const char *const calc_token_names[] = {\"(\", \")\", \"*\", \"+\", \"id\", \"$\"};"
+        ));
+        assert!(!source.contains("PREFIX_") && !source.contains("prefix_"));
+    }
+}
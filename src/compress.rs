@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{Action, SlrRow};
+
+/// Slot of the packed arrays that no row uses.
+const FREE: usize = usize::MAX;
+
+/// Parse table packed with the usual yacc techniques:
+///
+/// - default reductions: the most frequent reduction of a state is taken out of its row and
+///   used for every lookahead that has no explicit action (optional, since it turns some
+///   errors into reductions that fail later),
+/// - equivalent-row merging: states with identical rows share a single row,
+/// - row displacement (comb packing): every row is shifted by a `base` so that the non empty
+///   cells of all the rows fit together in one array, and `check` tells which row owns a slot.
+///
+/// Action and goto rows are merged and packed separately. Action columns are the terminal
+/// ids (`$` last) and goto columns the non terminal ids, counted from the first one.
+pub struct CompressedTable {
+    pub action_columns: usize,
+    pub goto_columns: usize,
+    /// Id of the first non terminal, the one in goto column 0.
+    first_non_terminal: usize,
+    /// Merged action row of every state.
+    pub action_rows: Vec<usize>,
+    /// Default reduction of every merged action row.
+    pub default_reductions: Vec<Option<usize>>,
+    pub action_base: Vec<usize>,
+    pub action_check: Vec<usize>,
+    pub action_value: Vec<Option<Action>>,
+    /// Merged goto row of every state.
+    pub goto_rows: Vec<usize>,
+    pub goto_base: Vec<usize>,
+    pub goto_check: Vec<usize>,
+    pub goto_value: Vec<usize>,
+}
+
+/// Action row once its default reduction is taken out: the default and the remaining
+/// `(column, action)` cells.
+type ActionRow = (Option<usize>, Vec<(usize, Action)>);
+
+/// Sizes of the dense and compressed tables, counted in table entries.
+pub struct CompressionStats {
+    pub states: usize,
+    pub dense_actions: usize,
+    pub dense_gotos: usize,
+    pub filled_actions: usize,
+    pub filled_gotos: usize,
+    pub action_rows: usize,
+    pub goto_rows: usize,
+    pub default_reductions: usize,
+    pub packed_actions: usize,
+    pub packed_gotos: usize,
+}
+
+impl CompressedTable {
+    pub fn new(
+        table: &[SlrRow],
+        grammar: &InternedGrammar,
+        use_default_reductions: bool,
+    ) -> CompressedTable {
+        let action_columns = grammar.eof + 1;
+        let first_non_terminal = grammar.non_terminals().start;
+        let goto_columns = grammar.non_terminals().len();
+
+        let mut action_rows = Vec::new();
+        let mut merged_actions: Vec<ActionRow> = Vec::new();
+        for row in table.iter() {
+            let mut cells: Vec<(usize, Action)> = (0..action_columns)
+                .filter_map(|term| row.actions.get(&term).map(|a| (term, a.clone())))
+                .collect();
+            let default = if use_default_reductions {
+                most_frequent_reduction(&cells)
+            } else {
+                None
+            };
+            if let Some(rule) = default {
+                cells.retain(|(_, action)| *action != Action::R(rule));
+            }
+            action_rows.push(merge_row(&mut merged_actions, (default, cells)));
+        }
+
+        let mut goto_rows = Vec::new();
+        let mut merged_gotos: Vec<Vec<(usize, usize)>> = Vec::new();
+        for row in table.iter() {
+            let cells: Vec<(usize, usize)> = (0..goto_columns)
+                .filter_map(|col| {
+                    row.gotos
+                        .get(&(first_non_terminal + col))
+                        .map(|g| (col, *g))
+                })
+                .collect();
+            goto_rows.push(merge_row(&mut merged_gotos, cells));
+        }
+
+        let default_reductions = merged_actions.iter().map(|(d, _)| *d).collect();
+        let action_cells: Vec<Vec<(usize, Action)>> =
+            merged_actions.into_iter().map(|(_, cells)| cells).collect();
+        let (action_base, action_check, action_value) = pack_rows(&action_cells);
+        let (goto_base, goto_check, goto_value) = pack_rows(&merged_gotos);
+
+        CompressedTable {
+            action_columns,
+            goto_columns,
+            first_non_terminal,
+            action_rows,
+            default_reductions,
+            action_base,
+            action_check,
+            action_value,
+            goto_rows,
+            goto_base,
+            goto_check,
+            goto_value: goto_value.into_iter().map(|g| g.unwrap_or(0)).collect(),
+        }
+    }
+
+    /// Action of `state` on the terminal with id `terminal`, the same one the dense `SlrRow`
+    /// has, except for the cells of the default reduction of the state, which were taken out
+    /// of the row and give `None` (see `default_reduction`).
+    pub fn action(&self, state: usize, terminal: usize) -> Option<Action> {
+        let row = *self.action_rows.get(state)?;
+        if terminal >= self.action_columns {
+            return None;
+        }
+        let slot = self.action_base[row] + terminal;
+        if self.action_check.get(slot) == Some(&row) {
+            self.action_value[slot].clone()
+        } else {
+            None
+        }
+    }
+
+    /// Default reduction of `state`. A parser that uses it reduces by this rule on every
+    /// lookahead without an `action`, so some errors are only found after reducing.
+    pub fn default_reduction(&self, state: usize) -> Option<usize> {
+        self.default_reductions[*self.action_rows.get(state)?]
+    }
+
+    /// Goto of `state` on the non terminal with id `non_terminal`.
+    pub fn goto(&self, state: usize, non_terminal: usize) -> Option<usize> {
+        let row = *self.goto_rows.get(state)?;
+        let col = non_terminal.checked_sub(self.first_non_terminal)?;
+        if col >= self.goto_columns {
+            return None;
+        }
+        let slot = self.goto_base[row] + col;
+        if self.goto_check.get(slot) == Some(&row) {
+            Some(self.goto_value[slot])
+        } else {
+            None
+        }
+    }
+
+    /// Checks every cell of the dense table against the lookup functions. The only cells
+    /// `action` leaves out are the ones with the default reduction of their state.
+    pub fn matches(&self, table: &[SlrRow]) -> bool {
+        table.iter().enumerate().all(|(state, row)| {
+            let default = self.default_reduction(state).map(Action::R);
+            let actions_ok = (0..self.action_columns).all(|term| {
+                let expected = row
+                    .actions
+                    .get(&term)
+                    .filter(|action| Some(*action) != default.as_ref());
+                self.action(state, term).as_ref() == expected
+            });
+            let gotos_ok = (0..self.goto_columns).all(|col| {
+                let nterm = self.first_non_terminal + col;
+                self.goto(state, nterm) == row.gotos.get(&nterm).copied()
+            });
+            actions_ok && gotos_ok
+        })
+    }
+
+    pub fn stats(&self, table: &[SlrRow]) -> CompressionStats {
+        let states = table.len();
+        CompressionStats {
+            states,
+            dense_actions: states * self.action_columns,
+            dense_gotos: states * self.goto_columns,
+            filled_actions: table.iter().map(|row| row.actions.len()).sum(),
+            filled_gotos: table.iter().map(|row| row.gotos.len()).sum(),
+            action_rows: self.action_base.len(),
+            goto_rows: self.goto_base.len(),
+            default_reductions: self.default_reductions.iter().flatten().count(),
+            packed_actions: self.action_check.len(),
+            packed_gotos: self.goto_check.len(),
+        }
+    }
+}
+
+impl CompressionStats {
+    /// Entries of the dense action and goto tables.
+    pub fn dense_size(&self) -> usize {
+        self.dense_actions + self.dense_gotos
+    }
+
+    /// Entries of every array of the compressed table: the row map of each state, the
+    /// default reduction and base of each merged row, and `check` plus value for each slot.
+    pub fn compressed_size(&self) -> usize {
+        2 * self.states
+            + 2 * self.action_rows
+            + self.goto_rows
+            + 2 * self.packed_actions
+            + 2 * self.packed_gotos
+    }
+
+    pub fn to_table(&self) -> TextTable {
+        let headers = vec![
+            "".to_string(),
+            "dense".to_string(),
+            "compressed".to_string(),
+        ];
+        let rows = vec![
+            vec![
+                "states / rows".to_string(),
+                self.states.to_string(),
+                format!("{} action, {} goto", self.action_rows, self.goto_rows),
+            ],
+            vec![
+                "action entries".to_string(),
+                format!("{} ({} filled)", self.dense_actions, self.filled_actions),
+                format!("{} slots", self.packed_actions),
+            ],
+            vec![
+                "goto entries".to_string(),
+                format!("{} ({} filled)", self.dense_gotos, self.filled_gotos),
+                format!("{} slots", self.packed_gotos),
+            ],
+            vec![
+                "default reductions".to_string(),
+                "".to_string(),
+                self.default_reductions.to_string(),
+            ],
+            vec![
+                "total entries".to_string(),
+                self.dense_size().to_string(),
+                self.compressed_size().to_string(),
+            ],
+        ];
+        TextTable { headers, rows }
+    }
+}
+
+/// Rule reduced in the most cells of a row (the lowest rule on ties).
+fn most_frequent_reduction(cells: &[(usize, Action)]) -> Option<usize> {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for (_, action) in cells.iter() {
+        if let Action::R(rule) = action {
+            *counts.entry(*rule).or_insert(0) += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|(r1, c1), (r2, c2)| c1.cmp(c2).then(r2.cmp(r1)))
+        .map(|(rule, _)| rule)
+}
+
+/// Index of `row` among the already merged rows, adding it if it's new.
+fn merge_row<T: PartialEq>(merged: &mut Vec<T>, row: T) -> usize {
+    match merged.iter().position(|r| *r == row) {
+        Some(idx) => idx,
+        None => {
+            merged.push(row);
+            merged.len() - 1
+        }
+    }
+}
+
+/// Row displacement: places the rows, fullest first, at the lowest base where none of their
+/// cells lands on a used slot. Returns the base of every row, and the check and value arrays.
+fn pack_rows<T: Clone>(rows: &[Vec<(usize, T)>]) -> (Vec<usize>, Vec<usize>, Vec<Option<T>>) {
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by_key(|idx| std::cmp::Reverse(rows[*idx].len()));
+
+    let mut base = vec![0; rows.len()];
+    let mut check: Vec<usize> = Vec::new();
+    let mut value: Vec<Option<T>> = Vec::new();
+    for idx in order {
+        let cells = &rows[idx];
+        if cells.is_empty() {
+            continue;
+        }
+        let fits = |b: usize, check: &[usize]| {
+            cells
+                .iter()
+                .all(|(col, _)| check.get(b + col).is_none_or(|owner| *owner == FREE))
+        };
+        let mut b = 0;
+        while !fits(b, &check) {
+            b += 1;
+        }
+        base[idx] = b;
+        for (col, cell) in cells.iter() {
+            let slot = b + col;
+            if slot >= check.len() {
+                check.resize(slot + 1, FREE);
+                value.resize(slot + 1, None);
+            }
+            check[slot] = idx;
+            value[slot] = Some(cell.clone());
+        }
+    }
+    (base, check, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grammar and dense table of every test grammar.
+    fn dense_tables() -> Vec<(InternedGrammar, Vec<SlrRow>)> {
+        [
+            include_str!("../test_txts/input1.txt"),
+            include_str!("../test_txts/input2.txt"),
+            include_str!("../test_txts/input4.txt"),
+            // ambiguous, so some cells have conflicts
+            "4\nE -> E + E\nE -> E * E\nE -> ( E )\nE -> id\n",
+        ]
+        .iter()
+        .map(|txt| {
+            let grammar = InternedGrammar::new(&crate::extended_grammar_of(txt));
+            let (_, table) = crate::slr_table_of(&grammar);
+            (grammar, table)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn lookup_gives_the_dense_cells() {
+        for (grammar, table) in dense_tables() {
+            let compressed = CompressedTable::new(&table, &grammar, false);
+            for (state, row) in table.iter().enumerate() {
+                assert_eq!(compressed.default_reduction(state), None);
+                for term in 0..=grammar.eof {
+                    assert_eq!(
+                        compressed.action(state, term),
+                        row.actions.get(&term).cloned()
+                    );
+                }
+                for nterm in grammar.non_terminals() {
+                    assert_eq!(
+                        compressed.goto(state, nterm),
+                        row.gotos.get(&nterm).copied()
+                    );
+                }
+                // terminals have no goto
+                assert_eq!(compressed.goto(state, 0), None);
+            }
+            assert!(compressed.matches(&table));
+        }
+    }
+
+    #[test]
+    fn default_reductions_only_leave_out_their_own_cells() {
+        for (grammar, table) in dense_tables() {
+            let compressed = CompressedTable::new(&table, &grammar, true);
+            for (state, row) in table.iter().enumerate() {
+                let cells: Vec<(usize, Action)> = row
+                    .actions
+                    .iter()
+                    .map(|(term, action)| (*term, action.clone()))
+                    .collect();
+                let default = compressed.default_reduction(state);
+                assert_eq!(default, most_frequent_reduction(&cells));
+                for term in 0..=grammar.eof {
+                    let expected = row
+                        .actions
+                        .get(&term)
+                        .filter(|action| default.map(Action::R).as_ref() != Some(*action));
+                    assert_eq!(compressed.action(state, term).as_ref(), expected);
+                }
+            }
+            assert!(compressed.matches(&table));
+            let stats = compressed.stats(&table);
+            assert!(stats.default_reductions > 0);
+            assert!(stats.packed_actions < stats.filled_actions);
+        }
+    }
+
+    #[test]
+    fn equal_rows_are_merged_and_packed_without_overlap() {
+        let mut merged = Vec::new();
+        assert_eq!(merge_row(&mut merged, vec![(0, 1)]), 0);
+        assert_eq!(merge_row(&mut merged, vec![(1, 2)]), 1);
+        assert_eq!(merge_row(&mut merged, vec![(0, 1)]), 0);
+
+        let rows = vec![
+            vec![(0, 'a'), (2, 'b')],
+            vec![(1, 'c')],
+            vec![(0, 'd'), (1, 'e')],
+        ];
+        let (base, check, value) = pack_rows(&rows);
+        for (row, cells) in rows.iter().enumerate() {
+            for (col, cell) in cells.iter() {
+                assert_eq!(check[base[row] + col], row);
+                assert_eq!(value[base[row] + col], Some(*cell));
+            }
+        }
+        assert_eq!(check.len(), 5);
+
+        assert_eq!(
+            most_frequent_reduction(&[(0, Action::R(3)), (1, Action::R(2)), (2, Action::R(3))]),
+            Some(3)
+        );
+        assert_eq!(most_frequent_reduction(&[(0, Action::S(1))]), None);
+    }
+}
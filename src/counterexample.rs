@@ -0,0 +1,626 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::symbols::InternedGrammar;
+use crate::{interned_names, Action, SlrRow, SlrRule, SlrState};
+
+/// Longest stack the searches consider, so grammars with epsilon rules can't make them
+/// grow forever.
+const MAX_STACK: usize = 40;
+/// Configurations explored by each search before giving up.
+const MAX_NODES: usize = 20000;
+/// Different ways of reaching a conflict tried when looking for an ambiguous input.
+const MAX_PREFIXES: usize = 10;
+
+/// What the parser does in a run, to rebuild its parse tree.
+#[derive(Clone, Debug)]
+enum Step {
+    Shift(usize),
+    Reduce(usize),
+}
+
+/// Stack of states and the steps done to reach it.
+#[derive(Clone)]
+struct Run {
+    stack: Vec<usize>,
+    steps: Vec<Step>,
+}
+
+/// Input that reaches a conflict, and what each of two of its actions leads to.
+pub struct Counterexample {
+    pub state: usize,
+    pub terminal: String,
+    pub actions: (Action, Action),
+    /// Symbols of the shortest path from state 0 to the state.
+    pub path: Vec<String>,
+    /// Tokens read before the conflict, if some input reaches it.
+    pub prefix: Option<Vec<String>>,
+    pub kind: ExampleKind,
+}
+
+pub enum ExampleKind {
+    /// One input with a parse for each action: the grammar is ambiguous.
+    Unifying {
+        tokens: Vec<String>,
+        trees: (String, String),
+    },
+    /// A different input for each action (`None` if that action can't lead to accepting).
+    Nonunifying {
+        first: Option<(Vec<String>, String)>,
+        second: Option<(Vec<String>, String)>,
+    },
+    /// No input reaches the conflict within the search bounds.
+    NotFound,
+}
+
+/// Actions of a cell, with the proposed ones for a conflict.
+fn cell_actions(row: &SlrRow, terminal: usize) -> Vec<Action> {
+    match row.actions.get(&terminal) {
+        Some(Action::Err) => row.conflicts.get(&terminal).cloned().unwrap_or_default(),
+        Some(action) => vec![action.clone()],
+        None => Vec::new(),
+    }
+}
+
+/// Applies the reduction by `rule`, if the goto exists.
+fn reduce(table: &[SlrRow], grammar: &InternedGrammar, run: &Run, rule: usize) -> Option<Run> {
+    let len = grammar.rules[rule].body.len();
+    let mut stack = run.stack[..run.stack.len() - len].to_vec();
+    let next = table[*stack.last()?]
+        .gotos
+        .get(&grammar.rules[rule].origin)?;
+    stack.push(*next);
+    let mut steps = run.steps.clone();
+    steps.push(Step::Reduce(rule));
+    Some(Run { stack, steps })
+}
+
+/// Every run reachable from `run` doing only reductions with `lookahead` as the next token
+/// (including `run` itself). Conflicting cells follow every proposed action.
+fn reductions(table: &[SlrRow], grammar: &InternedGrammar, run: Run, lookahead: usize) -> Vec<Run> {
+    let mut seen: HashSet<Vec<usize>> = HashSet::new();
+    let mut pending = vec![run];
+    let mut ret = Vec::new();
+    while let Some(run) = pending.pop() {
+        if run.stack.len() > MAX_STACK || !seen.insert(run.stack.clone()) {
+            continue;
+        }
+        let top = *run.stack.last().unwrap();
+        for action in cell_actions(&table[top], lookahead) {
+            if let Action::R(rule) = action {
+                if let Some(next) = reduce(table, grammar, &run, rule) {
+                    pending.push(next);
+                }
+            }
+        }
+        ret.push(run);
+    }
+    ret
+}
+
+/// Runs after reading `token`: any reductions and then its shift.
+fn advance(table: &[SlrRow], grammar: &InternedGrammar, run: Run, token: usize) -> Vec<Run> {
+    let mut ret = Vec::new();
+    for run in reductions(table, grammar, run, token) {
+        let top = *run.stack.last().unwrap();
+        for action in cell_actions(&table[top], token) {
+            if let Action::S(next) = action {
+                let mut shifted = run.clone();
+                shifted.stack.push(next);
+                shifted.steps.push(Step::Shift(token));
+                ret.push(shifted);
+            }
+        }
+    }
+    ret
+}
+
+/// The run after the reductions that end the input, if it can be accepted.
+fn accept(table: &[SlrRow], grammar: &InternedGrammar, run: &Run) -> Option<Run> {
+    reductions(table, grammar, run.clone(), grammar.eof)
+        .into_iter()
+        .find(|run| {
+            cell_actions(&table[*run.stack.last().unwrap()], grammar.eof).contains(&Action::Acc)
+        })
+}
+
+/// Runs after doing `action` on `terminal` and then reading it. A run that accepts
+/// (`terminal` is `$`) comes with true.
+fn diverge(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    run: &Run,
+    action: &Action,
+    terminal: usize,
+) -> Vec<(Run, bool)> {
+    match action {
+        Action::S(next) => {
+            let mut shifted = run.clone();
+            shifted.stack.push(*next);
+            shifted.steps.push(Step::Shift(terminal));
+            vec![(shifted, false)]
+        }
+        Action::R(rule) => match reduce(table, grammar, run, *rule) {
+            Some(reduced) if terminal == grammar.eof => accept(table, grammar, &reduced)
+                .map(|run| (run, true))
+                .into_iter()
+                .collect(),
+            Some(reduced) => advance(table, grammar, reduced, terminal)
+                .into_iter()
+                .map(|run| (run, false))
+                .collect(),
+            None => Vec::new(),
+        },
+        Action::Acc => vec![(run.clone(), true)],
+        Action::Err => Vec::new(),
+    }
+}
+
+/// Shortest inputs that take the parser to `state` with `terminal` as the next token, with
+/// different stacks: the tokens read and the run just before acting on `terminal`.
+fn reach_conflict(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    terminals: &[usize],
+    state: usize,
+    terminal: usize,
+) -> Vec<(Vec<usize>, Run)> {
+    let mut ret = Vec::new();
+    let mut found: HashSet<Vec<usize>> = HashSet::new();
+    let start = Run {
+        stack: vec![0],
+        steps: Vec::new(),
+    };
+    let mut queue = VecDeque::from([(Vec::new(), start)]);
+    let mut seen: HashSet<Vec<usize>> = HashSet::new();
+    while let Some((tokens, run)) = queue.pop_front() {
+        if seen.len() > MAX_NODES {
+            break;
+        }
+        if !seen.insert(run.stack.clone()) {
+            continue;
+        }
+        for reduced in reductions(table, grammar, run.clone(), terminal) {
+            if *reduced.stack.last().unwrap() == state && found.insert(reduced.stack.clone()) {
+                ret.push((tokens.clone(), reduced));
+                if ret.len() == MAX_PREFIXES {
+                    return ret;
+                }
+            }
+        }
+        for token in terminals.iter().copied() {
+            for next in advance(table, grammar, run.clone(), token) {
+                if queue.len() < MAX_NODES && !seen.contains(&next.stack) {
+                    let mut next_tokens = tokens.clone();
+                    next_tokens.push(token);
+                    queue.push_back((next_tokens, next));
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Shortest continuation that both runs parse, reading the same tokens.
+fn unify(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    terminals: &[usize],
+    first: &Run,
+    second: &Run,
+) -> Option<(Vec<usize>, Run, Run)> {
+    let mut queue = VecDeque::from([(Vec::new(), first.clone(), second.clone())]);
+    let mut seen: HashSet<(Vec<usize>, Vec<usize>)> = HashSet::new();
+    while let Some((tokens, first, second)) = queue.pop_front() {
+        if seen.len() > MAX_NODES {
+            break;
+        }
+        if !seen.insert((first.stack.clone(), second.stack.clone())) {
+            continue;
+        }
+        if let (Some(first), Some(second)) = (
+            accept(table, grammar, &first),
+            accept(table, grammar, &second),
+        ) {
+            return Some((tokens, first, second));
+        }
+        for token in terminals.iter().copied() {
+            let firsts = advance(table, grammar, first.clone(), token);
+            let seconds = advance(table, grammar, second.clone(), token);
+            for next_first in firsts.iter() {
+                for next_second in seconds.iter() {
+                    let key = (next_first.stack.clone(), next_second.stack.clone());
+                    if queue.len() < MAX_NODES && !seen.contains(&key) {
+                        let mut next_tokens = tokens.clone();
+                        next_tokens.push(token);
+                        queue.push_back((next_tokens, next_first.clone(), next_second.clone()));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Shortest continuation that the run parses.
+fn complete(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    terminals: &[usize],
+    run: &Run,
+) -> Option<(Vec<usize>, Run)> {
+    let mut queue = VecDeque::from([(Vec::new(), run.clone())]);
+    let mut seen: HashSet<Vec<usize>> = HashSet::new();
+    while let Some((tokens, run)) = queue.pop_front() {
+        if seen.len() > MAX_NODES {
+            break;
+        }
+        if !seen.insert(run.stack.clone()) {
+            continue;
+        }
+        if let Some(accepted) = accept(table, grammar, &run) {
+            return Some((tokens, accepted));
+        }
+        for token in terminals.iter().copied() {
+            for next in advance(table, grammar, run.clone(), token) {
+                if queue.len() < MAX_NODES && !seen.contains(&next.stack) {
+                    let mut next_tokens = tokens.clone();
+                    next_tokens.push(token);
+                    queue.push_back((next_tokens, next));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Parse tree of a run, in brackets (`[E [E id] + [T id]]`), with a `•` before the token
+/// at `dot`.
+fn tree_string(steps: &[Step], grammar: &InternedGrammar, dot: usize) -> String {
+    let mut trees: Vec<String> = Vec::new();
+    let mut tokens = 0;
+    for step in steps.iter() {
+        match step {
+            Step::Shift(token) => {
+                let token = grammar.name(*token);
+                trees.push(if tokens == dot {
+                    format!("• {}", token)
+                } else {
+                    token.clone()
+                });
+                tokens += 1;
+            }
+            Step::Reduce(rule) => {
+                let rule = &grammar.rules[*rule];
+                let children = trees.split_off(trees.len() - rule.body.len());
+                let mut node = format!("[{}", grammar.name(rule.origin));
+                for child in children {
+                    node += " ";
+                    node += &child;
+                }
+                trees.push(node + "]");
+            }
+        }
+    }
+    let mut ret = trees.join(" ");
+    if tokens == dot {
+        ret += " •";
+    }
+    ret
+}
+
+/// Input with a `•` before the token at `dot`.
+pub fn sentence_string(tokens: &[String], dot: usize) -> String {
+    let mut words: Vec<&str> = tokens.iter().map(|token| token.as_str()).collect();
+    words.insert(dot.min(words.len()), "•");
+    words.join(" ")
+}
+
+/// Symbols of the shortest path of transitions from state 0 to `state`.
+fn shortest_path(slr: &[SlrState], grammar: &InternedGrammar, state: usize) -> Vec<String> {
+    let mut parent: Vec<Option<(usize, String)>> = vec![None; slr.len()];
+    let mut seen = vec![false; slr.len()];
+    seen[0] = true;
+    let mut queue = VecDeque::from([0]);
+    while let Some(current) = queue.pop_front() {
+        let mut transitions: Vec<(&String, usize)> = slr[current]
+            .transitions
+            .iter()
+            .map(|(symbol, next)| (grammar.name(*symbol), *next))
+            .collect();
+        transitions.sort();
+        for (symbol, next) in transitions {
+            if !seen[next] {
+                seen[next] = true;
+                parent[next] = Some((current, symbol.clone()));
+                queue.push_back(next);
+            }
+        }
+    }
+    let mut path = Vec::new();
+    let mut current = state;
+    while let Some((previous, symbol)) = &parent[current] {
+        path.push(symbol.clone());
+        current = *previous;
+    }
+    path.reverse();
+    path
+}
+
+/// Counterexample for every pair of actions of every conflicting cell, in state and
+/// terminal order. It looks for the shortest inputs reaching the conflict and, after each,
+/// for a continuation that can be parsed both ways (proving the grammar ambiguous). When
+/// there is none within the bounds it gives the shortest continuation of each action,
+/// after the first prefix where both have one.
+pub fn counterexamples(
+    table: &[SlrRow],
+    slr: &[SlrState],
+    grammar: &InternedGrammar,
+) -> Vec<Counterexample> {
+    // tokens the searches try, in name order
+    let terminals: Vec<usize> = (0..grammar.eof).collect();
+    let terminals = terminals.as_slice();
+    let mut ret = Vec::new();
+    for (state, row) in table.iter().enumerate() {
+        let mut cells: Vec<(usize, &Vec<Action>)> = row
+            .conflicts
+            .iter()
+            .map(|(terminal, actions)| (*terminal, actions))
+            .collect();
+        cells.sort_by_key(|(terminal, _)| *terminal);
+        for (terminal, actions) in cells {
+            let reached = reach_conflict(table, grammar, terminals, state, terminal);
+            for (i, first) in actions.iter().enumerate() {
+                for second in actions[i + 1..].iter() {
+                    let search = ExampleSearch {
+                        table,
+                        grammar,
+                        terminals,
+                        actions: (first, second),
+                        terminal,
+                    };
+                    let unifying = reached.iter().find_map(|(prefix, run)| {
+                        search.unifying(prefix, run).map(|kind| (prefix, kind))
+                    });
+                    let chosen = unifying.or_else(|| {
+                        let examples: Vec<(&Vec<usize>, ExampleKind)> = reached
+                            .iter()
+                            .map(|(prefix, run)| (prefix, search.nonunifying(prefix, run)))
+                            .collect();
+                        let both = examples.iter().position(|(_, kind)| {
+                            matches!(
+                                kind,
+                                ExampleKind::Nonunifying {
+                                    first: Some(_),
+                                    second: Some(_)
+                                }
+                            )
+                        });
+                        examples.into_iter().nth(both.unwrap_or(0))
+                    });
+                    let (prefix, kind) = match chosen {
+                        Some((prefix, kind)) => (Some(interned_names(grammar, prefix)), kind),
+                        None => (None, ExampleKind::NotFound),
+                    };
+                    ret.push(Counterexample {
+                        state,
+                        terminal: grammar.name(terminal).clone(),
+                        actions: (first.clone(), second.clone()),
+                        path: shortest_path(slr, grammar, state),
+                        prefix,
+                        kind,
+                    });
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Runs after the first action, runs after the second one, and the tokens read.
+type Diverged = (Vec<(Run, bool)>, Vec<(Run, bool)>, Vec<usize>);
+
+/// What is fixed while looking for the examples of a pair of conflicting actions.
+struct ExampleSearch<'a> {
+    table: &'a [SlrRow],
+    grammar: &'a InternedGrammar,
+    terminals: &'a [usize],
+    actions: (&'a Action, &'a Action),
+    terminal: usize,
+}
+
+impl ExampleSearch<'_> {
+    /// Runs after each action, and the tokens read up to then.
+    fn diverge(&self, prefix: &[usize], run: &Run) -> Diverged {
+        let firsts = diverge(self.table, self.grammar, run, self.actions.0, self.terminal);
+        let seconds = diverge(self.table, self.grammar, run, self.actions.1, self.terminal);
+        let mut read = prefix.to_vec();
+        if self.terminal != self.grammar.eof {
+            read.push(self.terminal);
+        }
+        (firsts, seconds, read)
+    }
+
+    /// The same input parsed with each action, after `prefix`.
+    fn unifying(&self, prefix: &[usize], run: &Run) -> Option<ExampleKind> {
+        let dot = prefix.len();
+        let (firsts, seconds, read) = self.diverge(prefix, run);
+        for (first, first_accepted) in firsts.iter() {
+            for (second, second_accepted) in seconds.iter() {
+                let unified = match (first_accepted, second_accepted) {
+                    (true, true) => Some((Vec::new(), first.clone(), second.clone())),
+                    (false, false) => {
+                        unify(self.table, self.grammar, self.terminals, first, second)
+                    }
+                    _ => None,
+                };
+                if let Some((rest, first, second)) = unified {
+                    let mut tokens = read.clone();
+                    tokens.extend(rest);
+                    return Some(ExampleKind::Unifying {
+                        tokens: interned_names(self.grammar, &tokens),
+                        trees: (
+                            tree_string(&first.steps, self.grammar, dot),
+                            tree_string(&second.steps, self.grammar, dot),
+                        ),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// An input for each action, after `prefix`.
+    fn nonunifying(&self, prefix: &[usize], run: &Run) -> ExampleKind {
+        let dot = prefix.len();
+        let (firsts, seconds, read) = self.diverge(prefix, run);
+        let example = |runs: &[(Run, bool)]| {
+            runs.iter().find_map(|(run, accepted)| {
+                let (rest, run) = if *accepted {
+                    (Vec::new(), run.clone())
+                } else {
+                    complete(self.table, self.grammar, self.terminals, run)?
+                };
+                let mut tokens = read.clone();
+                tokens.extend(rest);
+                Some((
+                    interned_names(self.grammar, &tokens),
+                    tree_string(&run.steps, self.grammar, dot),
+                ))
+            })
+        };
+        ExampleKind::Nonunifying {
+            first: example(&firsts),
+            second: example(&seconds),
+        }
+    }
+}
+
+/// Action written for people, e.g. `reduce 1. E -> E + E`.
+pub fn action_string(action: &Action, extended_grammar: &[SlrRule]) -> String {
+    match action {
+        Action::S(next) => format!("shift {}", next),
+        Action::R(rule) => format!("reduce {}", extended_grammar[*rule].to_string().trim_end()),
+        Action::Acc => "accept".to_string(),
+        Action::Err => "error".to_string(),
+    }
+}
+
+/// Report of a counterexample, one line per fact.
+pub fn counterexample_to_string(example: &Counterexample, extended_grammar: &[SlrRule]) -> String {
+    let first = action_string(&example.actions.0, extended_grammar);
+    let second = action_string(&example.actions.1, extended_grammar);
+    let mut ret = format!(
+        "State {} on {}: {} / {}\n",
+        example.state, example.terminal, first, second
+    );
+    if example.path.is_empty() {
+        ret += "  Shortest path: ε (it is the start state)\n";
+    } else {
+        ret += &format!("  Shortest path: {}\n", example.path.join(" "));
+    }
+    if let Some(prefix) = &example.prefix {
+        ret += &format!(
+            "  Input reaching it: {}\n",
+            sentence_string(prefix, prefix.len())
+        );
+    }
+    let dot = example.prefix.as_ref().map_or(0, |prefix| prefix.len());
+    match &example.kind {
+        ExampleKind::Unifying { tokens, trees } => {
+            ret += &format!("  Ambiguous input: {}\n", sentence_string(tokens, dot));
+            ret += &format!("    with {}: {}\n", first, trees.0);
+            ret += &format!("    with {}: {}\n", second, trees.1);
+        }
+        ExampleKind::Nonunifying {
+            first: first_example,
+            second: second_example,
+        } => {
+            ret += "  No input parses both ways within the search bounds; an input for each:\n";
+            for (action, found) in [(&first, first_example), (&second, second_example)] {
+                match found {
+                    Some((tokens, tree)) => {
+                        ret += &format!(
+                            "    with {}: {}\n      {}\n",
+                            action,
+                            sentence_string(tokens, dot),
+                            tree
+                        );
+                    }
+                    None => ret += &format!("    with {}: no input is accepted\n", action),
+                }
+            }
+        }
+        ExampleKind::NotFound => {
+            ret += "  No input reaches this conflict within the search bounds\n"
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(txt: &str) -> Vec<String> {
+        txt.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// Counterexamples of the conflicts in the SLR(1) table of a grammar.
+    fn examples_of(txt: &str) -> Vec<Counterexample> {
+        let grammar = InternedGrammar::new(&crate::extended_grammar_of(txt));
+        let (slr, table) = crate::slr_table_of(&grammar);
+        counterexamples(&table, &slr, &grammar)
+    }
+
+    #[test]
+    fn ambiguous_conflicts_get_one_input_parsed_both_ways() {
+        let examples = examples_of(include_str!("../test_txts/input_ambiguous.txt"));
+        assert_eq!(examples.len(), 4);
+        assert!(examples
+            .iter()
+            .all(|example| matches!(example.kind, ExampleKind::Unifying { .. })));
+
+        let example = &examples[0];
+        assert_eq!(example.terminal, "*");
+        assert_eq!(example.path, words("E * E"));
+        assert_eq!(example.prefix, Some(words("id * id")));
+        match &example.kind {
+            ExampleKind::Unifying { tokens, trees } => {
+                assert_eq!(*tokens, words("id * id * id"));
+                assert_eq!(trees.0, "[E [E id] * [E [E id] • * [E id]]]");
+                assert_eq!(trees.1, "[E [E [E id] * [E id]] • * [E id]]");
+            }
+            _ => panic!("expected an input parsed both ways"),
+        }
+    }
+
+    #[test]
+    fn other_conflicts_get_an_input_for_each_action() {
+        // `a` is read either by A or after it, but every input has a single parse
+        let examples = examples_of("3\nS -> A a\nA -> a\nA -> ' '\n");
+        assert_eq!(examples.len(), 1);
+        let example = &examples[0];
+        assert_eq!((example.state, example.terminal.as_str()), (0, "a"));
+        assert!(example.path.is_empty());
+        assert_eq!(example.prefix, Some(Vec::new()));
+        match &example.kind {
+            ExampleKind::Nonunifying {
+                first: Some((first, first_tree)),
+                second: Some((second, second_tree)),
+            } => {
+                assert_eq!(*first, words("a a"));
+                assert_eq!(first_tree, "[S [A • a] a]");
+                assert_eq!(*second, words("a"));
+                assert_eq!(second_tree, "[S [A] • a]");
+            }
+            _ => panic!("expected an input for each action"),
+        }
+    }
+
+    #[test]
+    fn grammars_without_conflicts_have_no_examples() {
+        assert!(examples_of(include_str!("../test_txts/input2.txt")).is_empty());
+        assert_eq!(sentence_string(&words("id + id"), 1), "id • + id");
+        assert_eq!(sentence_string(&words("id"), 1), "id •");
+    }
+}
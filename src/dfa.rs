@@ -0,0 +1,468 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+
+use crate::regex::{dot_escape, dot_header, state_label, CharClass, Nfa};
+use crate::table::TextTable;
+
+/// Deterministic automaton over classes of characters. State 0 is the start state and a
+/// missing transition goes to an implicit dead state.
+#[derive(Clone, Debug)]
+pub struct Dfa {
+    /// Disjoint classes of characters, the columns of the table.
+    pub symbols: Vec<CharClass>,
+    /// `transitions[state][symbol]`.
+    pub transitions: Vec<Vec<Option<usize>>>,
+    /// Expression accepted by each state; the lowest number wins when the NFA states of a
+    /// subset accept several.
+    pub accepting: Vec<Option<usize>>,
+    /// States each state was built from: NFA states after the subset construction, states
+    /// of the original DFA after minimizing.
+    pub nfa_states: Vec<Vec<usize>>,
+}
+
+/// Splits every character read by the NFA moves into disjoint classes, so each class goes to
+/// the same NFA states from any state.
+fn elementary_classes(nfa: &Nfa) -> Vec<CharClass> {
+    let mut bounds: Vec<u32> = Vec::new();
+    for state in nfa.states.iter() {
+        for (class, _) in state.moves.iter() {
+            for (lo, hi) in class.ranges.iter() {
+                bounds.push(*lo as u32);
+                bounds.push(*hi as u32 + 1);
+            }
+        }
+    }
+    bounds.sort();
+    bounds.dedup();
+
+    let mut ret = Vec::new();
+    for pair in bounds.windows(2) {
+        let lo = match char::from_u32(pair[0]) {
+            Some(chr) => chr,
+            // starts inside the surrogates, which aren't characters
+            None => continue,
+        };
+        let read = nfa
+            .states
+            .iter()
+            .any(|state| state.moves.iter().any(|(class, _)| class.contains(lo)));
+        if !read {
+            continue;
+        }
+        let mut hi = pair[1] - 1;
+        if (0xd800..0xe000).contains(&hi) {
+            hi = 0xd7ff;
+        }
+        ret.push(CharClass::new(vec![(lo, char::from_u32(hi).unwrap())]));
+    }
+    ret
+}
+
+impl Dfa {
+    /// Subset construction: each state is the epsilon closure of a set of NFA states.
+    /// States are numbered in the order they are found, breadth first.
+    pub fn from_nfa(nfa: &Nfa) -> Dfa {
+        let symbols = elementary_classes(nfa);
+        let mut dfa = Dfa {
+            symbols: symbols.clone(),
+            transitions: Vec::new(),
+            accepting: Vec::new(),
+            nfa_states: Vec::new(),
+        };
+        let mut index: HashMap<Vec<usize>, usize> = HashMap::new();
+        let start = nfa.epsilon_closure(&[nfa.start]);
+        index.insert(start.clone(), 0);
+        dfa.nfa_states.push(start);
+
+        let mut current = 0;
+        while current < dfa.nfa_states.len() {
+            let mut row = Vec::new();
+            for symbol in symbols.iter() {
+                let chr = symbol.ranges[0].0;
+                let next = nfa.epsilon_closure(&nfa.step(&dfa.nfa_states[current], chr));
+                if next.is_empty() {
+                    row.push(None);
+                    continue;
+                }
+                let num = match index.get(&next) {
+                    Some(num) => *num,
+                    None => {
+                        index.insert(next.clone(), dfa.nfa_states.len());
+                        dfa.nfa_states.push(next);
+                        dfa.nfa_states.len() - 1
+                    }
+                };
+                row.push(Some(num));
+            }
+            dfa.transitions.push(row);
+            dfa.accepting
+                .push(nfa.accepted_by(&dfa.nfa_states[current]));
+            current += 1;
+        }
+        dfa.merge_symbols();
+        dfa
+    }
+
+    /// Joins the columns that have the same transitions in every state.
+    fn merge_symbols(&mut self) {
+        let mut columns: BTreeMap<Vec<Option<usize>>, Vec<(char, char)>> = BTreeMap::new();
+        let mut order = Vec::new();
+        for (col, symbol) in self.symbols.iter().enumerate() {
+            let column: Vec<Option<usize>> = self.transitions.iter().map(|row| row[col]).collect();
+            if column.iter().all(|next| next.is_none()) {
+                continue;
+            }
+            if !columns.contains_key(&column) {
+                order.push(column.clone());
+            }
+            columns
+                .entry(column)
+                .or_default()
+                .extend(symbol.ranges.iter().copied());
+        }
+
+        let mut merged: Vec<(CharClass, Vec<Option<usize>>)> = order
+            .into_iter()
+            .map(|column| {
+                let ranges = columns.remove(&column).unwrap();
+                (CharClass::new(ranges), column)
+            })
+            .collect();
+        merged.sort();
+        self.symbols = merged.iter().map(|(class, _)| class.clone()).collect();
+        self.transitions = (0..self.transitions.len())
+            .map(|state| merged.iter().map(|(_, column)| column[state]).collect())
+            .collect();
+    }
+
+    /// Hopcroft's algorithm. States start split by what they accept, and a block is split
+    /// whenever some of its states go into a splitter block on a symbol and others don't;
+    /// only the smaller half of a split block needs to be used as a splitter later. The
+    /// resulting states are numbered breadth first from the start state, and the block of
+    /// the dead state is left out.
+    pub fn minimize(&self) -> Dfa {
+        // complete automaton, with the dead state at the end
+        let dead = self.transitions.len();
+        let n_states = dead + 1;
+        let next_of = |state: usize, symbol: usize| -> usize {
+            if state == dead {
+                dead
+            } else {
+                self.transitions[state][symbol].unwrap_or(dead)
+            }
+        };
+        let accepting_of = |state: usize| {
+            if state == dead {
+                None
+            } else {
+                self.accepting[state]
+            }
+        };
+
+        // states going into each state, per symbol
+        let mut inverse = vec![vec![Vec::new(); n_states]; self.symbols.len()];
+        for state in 0..n_states {
+            for (symbol, sources) in inverse.iter_mut().enumerate() {
+                sources[next_of(state, symbol)].push(state);
+            }
+        }
+
+        // initial partition
+        let mut by_accepting: BTreeMap<Option<usize>, Vec<usize>> = BTreeMap::new();
+        for state in 0..n_states {
+            by_accepting
+                .entry(accepting_of(state))
+                .or_default()
+                .push(state);
+        }
+        let mut blocks: Vec<Vec<usize>> = by_accepting.into_values().collect();
+        let mut block_of = vec![0; n_states];
+        for (num, block) in blocks.iter().enumerate() {
+            for state in block.iter() {
+                block_of[*state] = num;
+            }
+        }
+        let largest = (0..blocks.len())
+            .max_by_key(|num| blocks[*num].len())
+            .unwrap();
+        let mut pending: Vec<usize> = (0..blocks.len()).filter(|num| *num != largest).collect();
+        let mut is_pending = vec![false; blocks.len()];
+        for num in pending.iter() {
+            is_pending[*num] = true;
+        }
+
+        while let Some(splitter) = pending.pop() {
+            is_pending[splitter] = false;
+            // the block may be split below, but it splits the others as it was
+            let splitter = blocks[splitter].clone();
+            for sources in inverse.iter() {
+                let mut going_in = vec![false; n_states];
+                let mut touched = Vec::new();
+                for state in splitter.iter() {
+                    for source in sources[*state].iter() {
+                        if !going_in[*source] {
+                            going_in[*source] = true;
+                            touched.push(block_of[*source]);
+                        }
+                    }
+                }
+                touched.sort();
+                touched.dedup();
+
+                for block in touched {
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        blocks[block].iter().partition(|state| going_in[**state]);
+                    if outside.is_empty() {
+                        continue;
+                    }
+                    let new_block = blocks.len();
+                    let inside_smaller = inside.len() <= outside.len();
+                    for state in outside.iter() {
+                        block_of[*state] = new_block;
+                    }
+                    blocks[block] = inside;
+                    blocks.push(outside);
+                    is_pending.push(false);
+                    let to_add = if is_pending[block] || !inside_smaller {
+                        new_block
+                    } else {
+                        block
+                    };
+                    if !is_pending[to_add] {
+                        is_pending[to_add] = true;
+                        pending.push(to_add);
+                    }
+                }
+            }
+        }
+
+        // number the blocks breadth first, without the dead one
+        let dead_block = block_of[dead];
+        let mut number: Vec<Option<usize>> = vec![None; blocks.len()];
+        let mut order = vec![block_of[0]];
+        number[block_of[0]] = Some(0);
+        let mut queue = VecDeque::from([block_of[0]]);
+        while let Some(block) = queue.pop_front() {
+            let state = blocks[block][0];
+            for symbol in 0..self.symbols.len() {
+                let next = block_of[next_of(state, symbol)];
+                if next != dead_block && number[next].is_none() {
+                    number[next] = Some(order.len());
+                    order.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut ret = Dfa {
+            symbols: self.symbols.clone(),
+            transitions: Vec::new(),
+            accepting: Vec::new(),
+            nfa_states: Vec::new(),
+        };
+        for block in order.iter() {
+            let state = blocks[*block][0];
+            ret.transitions.push(
+                (0..self.symbols.len())
+                    .map(|symbol| number[block_of[next_of(state, symbol)]])
+                    .collect(),
+            );
+            ret.accepting.push(accepting_of(state));
+            let mut members: Vec<usize> = blocks[*block]
+                .iter()
+                .copied()
+                .filter(|state| *state != dead)
+                .collect();
+            members.sort();
+            ret.nfa_states.push(members);
+        }
+        ret.merge_symbols();
+        ret
+    }
+
+    /// Longest non empty prefix of `input` accepted by the automaton: its length in
+    /// characters and the expression that accepts it.
+    pub fn longest_match(&self, input: &[char]) -> Option<(usize, usize)> {
+        let mut state = 0;
+        let mut ret = None;
+        for (pos, chr) in input.iter().enumerate() {
+            let symbol = self.symbols.iter().position(|class| class.contains(*chr));
+            match symbol.and_then(|symbol| self.transitions[state][symbol]) {
+                Some(next) => state = next,
+                None => break,
+            }
+            if let Some(num) = self.accepting[state] {
+                ret = Some((pos + 1, num));
+            }
+        }
+        ret
+    }
+
+    /// Transition table, one column per class of characters. `origin` names the last column,
+    /// that lists for every state the states it was built from: NFA states for the subset
+    /// construction or DFA states for the minimization.
+    pub fn to_table(&self, accept_names: &[String], origin: &str) -> TextTable {
+        let mut headers = vec!["state".to_string()];
+        headers.extend(self.symbols.iter().map(|class| class.to_string()));
+        headers.push("accepts".to_string());
+        headers.push(origin.to_string());
+
+        let rows = self
+            .transitions
+            .iter()
+            .enumerate()
+            .map(|(num, transitions)| {
+                let mut row = vec![state_label(num, num == 0)];
+                row.extend(
+                    transitions
+                        .iter()
+                        .map(|next| next.map_or(String::new(), |next| next.to_string())),
+                );
+                row.push(
+                    self.accepting[num]
+                        .map_or(String::new(), |accepted| accept_names[accepted].clone()),
+                );
+                let states: Vec<String> = self.nfa_states[num]
+                    .iter()
+                    .map(|state| state.to_string())
+                    .collect();
+                row.push(format!("{{{}}}", states.join(", ")));
+                row
+            })
+            .collect();
+        TextTable { headers, rows }
+    }
+
+    /// Graphviz description of the automaton. The transitions between the same two states
+    /// are drawn as one edge labelled with all their characters.
+    pub fn to_dot(&self, name: &str, accept_names: &[String]) -> String {
+        let mut ret = dot_header(name, 0);
+        for (num, accepted) in self.accepting.iter().enumerate() {
+            if let Some(accepted) = accepted {
+                let _ = writeln!(
+                    ret,
+                    "    {} [shape=doublecircle, label=\"{}\\n{}\"];",
+                    num,
+                    num,
+                    dot_escape(&accept_names[*accepted])
+                );
+            }
+        }
+        for (num, transitions) in self.transitions.iter().enumerate() {
+            let mut edges: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+            for (symbol, next) in transitions.iter().enumerate() {
+                if let Some(next) = next {
+                    edges
+                        .entry(*next)
+                        .or_default()
+                        .extend(self.symbols[symbol].ranges.iter().copied());
+                }
+            }
+            for (next, ranges) in edges {
+                let _ = writeln!(
+                    ret,
+                    "    {} -> {} [label=\"{}\"];",
+                    num,
+                    next,
+                    dot_escape(&CharClass::new(ranges).to_string())
+                );
+            }
+        }
+        ret + "}\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::parse_regex;
+
+    fn nfa(patterns: &[&str]) -> Nfa {
+        let regexes: Vec<_> = patterns.iter().map(|p| parse_regex(p).unwrap()).collect();
+        Nfa::from_regexes(&regexes)
+    }
+
+    fn nfa_accepts(nfa: &Nfa, input: &str) -> Option<usize> {
+        let mut states = nfa.epsilon_closure(&[nfa.start]);
+        for chr in input.chars() {
+            states = nfa.epsilon_closure(&nfa.step(&states, chr));
+        }
+        nfa.accepted_by(&states)
+    }
+
+    fn dfa_accepts(dfa: &Dfa, input: &str) -> Option<usize> {
+        let mut state = 0;
+        for chr in input.chars() {
+            let symbol = dfa.symbols.iter().position(|class| class.contains(chr))?;
+            state = dfa.transitions[state][symbol]?;
+        }
+        dfa.accepting[state]
+    }
+
+    /// Every string over `alphabet` of up to `max_length` characters.
+    fn strings(alphabet: &[char], max_length: usize) -> Vec<String> {
+        let mut ret = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..max_length {
+            last = last
+                .iter()
+                .flat_map(|prefix| alphabet.iter().map(move |chr| format!("{}{}", prefix, chr)))
+                .collect();
+            ret.extend(last.iter().cloned());
+        }
+        ret
+    }
+
+    #[test]
+    fn every_stage_accepts_the_same_strings() {
+        for patterns in [
+            vec!["(a|b)*abb"],
+            vec!["a*b?c", "(ab)+"],
+            vec!["\"ab\"", "[a-c]+", "c|()"],
+        ] {
+            let nfa = nfa(&patterns);
+            let dfa = Dfa::from_nfa(&nfa);
+            let minimized = dfa.minimize();
+            assert!(minimized.transitions.len() <= dfa.transitions.len());
+            for input in strings(&['a', 'b', 'c'], 6) {
+                let expected = nfa_accepts(&nfa, &input);
+                assert_eq!(
+                    dfa_accepts(&dfa, &input),
+                    expected,
+                    "{:?} {}",
+                    patterns,
+                    input
+                );
+                assert_eq!(
+                    dfa_accepts(&minimized, &input),
+                    expected,
+                    "{:?} {}",
+                    patterns,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn minimizes_the_textbook_example() {
+        // the subset construction gives 5 states, the minimal automaton has 4
+        let dfa = Dfa::from_nfa(&nfa(&["(a|b)*abb"]));
+        assert_eq!(dfa.transitions.len(), 5);
+        let minimized = dfa.minimize();
+        assert_eq!(minimized.transitions.len(), 4);
+        assert_eq!(minimized.accepting, [None, None, None, Some(0)]);
+        assert_eq!(minimized.symbols.len(), 2);
+    }
+
+    #[test]
+    fn longest_match_keeps_the_last_accepting_state() {
+        let dfa = Dfa::from_nfa(&nfa(&["if", "[a-z]+", "[0-9]+(\\.[0-9]+)?"])).minimize();
+        let chars = |txt: &str| txt.chars().collect::<Vec<char>>();
+        assert_eq!(dfa.longest_match(&chars("if(")), Some((2, 0)));
+        assert_eq!(dfa.longest_match(&chars("iffy x")), Some((4, 1)));
+        assert_eq!(dfa.longest_match(&chars("3.x")), Some((1, 2)));
+        assert_eq!(dfa.longest_match(&chars("3.25")), Some((4, 2)));
+        assert_eq!(dfa.longest_match(&chars("+")), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::driver::{SyntaxError, ERROR_TERMINAL};
+use crate::symbols::InternedGrammar;
+use crate::{Action, SlrRow, SlrRule, SlrState};
+
+/// Ids of the terminals the parser can go on with from `stack`, in column order (`$` last).
+/// A terminal with an action on top of the stack may only lead to reductions and then an
+/// error (SLR reduces on all of FOLLOW), so the reductions are done on a copy of the stack
+/// and only the terminals that get to a shift or the accept are kept. `error` is left out
+/// since the input can't contain it.
+pub fn expected_terminals(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    stack: &[usize],
+) -> Vec<usize> {
+    let top = match stack.last() {
+        Some(top) => *top,
+        None => return Vec::new(),
+    };
+    let mut ret: Vec<usize> = table[top]
+        .actions
+        .keys()
+        .copied()
+        .filter(|terminal| grammar.name(*terminal) != ERROR_TERMINAL)
+        .filter(|terminal| reaches_shift(table, grammar, stack, *terminal))
+        .collect();
+    // terminal ids are in column order
+    ret.sort();
+    ret
+}
+
+/// Whether the parser, with `terminal` as lookahead, shifts it or accepts after the
+/// reductions it does from `stack`.
+fn reaches_shift(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    stack: &[usize],
+    terminal: usize,
+) -> bool {
+    let mut stack = stack.to_vec();
+    // every reduction without a shift in between takes a different rule, unless the grammar
+    // has a cycle like `A -> B`, `B -> A`
+    for _ in 0..=grammar.rules.len() {
+        let state = *stack.last().unwrap();
+        match table[state].actions.get(&terminal) {
+            Some(Action::S(_)) | Some(Action::Acc) => return true,
+            Some(Action::R(rule)) => {
+                let rule = &grammar.rules[*rule];
+                stack.truncate(stack.len() - rule.body.len());
+                let top = *stack.last().unwrap();
+                match table[top].gotos.get(&rule.origin) {
+                    Some(next) => stack.push(*next),
+                    None => return false,
+                }
+            }
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// `a`, `b` or `c`, with `$` written as the end of the input.
+fn one_of(grammar: &InternedGrammar, terminals: &[usize]) -> String {
+    let names: Vec<String> = terminals
+        .iter()
+        .map(|terminal| {
+            if *terminal == grammar.eof {
+                "the end of the input".to_string()
+            } else {
+                format!("`{}`", grammar.name(*terminal))
+            }
+        })
+        .collect();
+    match names.split_last() {
+        None => "nothing".to_string(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+/// Message for a syntax error, e.g. "after `E +` expected `(` or `id`, found `*`", followed by
+/// the kernel items of the state where it happened, one per line, to show what the parser
+/// was in the middle of.
+pub fn describe_error(
+    error: &SyntaxError,
+    table: &[SlrRow],
+    slr: &[SlrState],
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+) -> String {
+    let place = if error.symbols.is_empty() {
+        "at the start".to_string()
+    } else {
+        format!("after `{}`", error.symbols.join(" "))
+    };
+    let found = if error.at_eof {
+        "the end of the input".to_string()
+    } else {
+        format!("`{}`", error.found)
+    };
+    let mut ret = format!(
+        "{} expected {}, found {}",
+        place,
+        one_of(grammar, &expected_terminals(table, grammar, &error.stack)),
+        found
+    );
+    if let Some(state) = slr.get(error.state) {
+        ret += &format!("\n    in state {}:", error.state);
+        for item in state.kernel.iter() {
+            ret += &format!("\n      {}", item.to_string(extended_grammar));
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver;
+
+    /// Description of every syntax error of `input` for `input2.txt`, with its expected
+    /// terminals.
+    fn errors(input: &str) -> Vec<(Vec<String>, String)> {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input2.txt"));
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (slr, table) = crate::slr_table_of(&grammar);
+        let tokens: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+        let result = driver::parse_tokens(&table, &grammar, &extended_grammar, &tokens);
+        result
+            .errors
+            .iter()
+            .map(|error| {
+                let expected = expected_terminals(&table, &grammar, &error.stack);
+                (
+                    expected
+                        .iter()
+                        .map(|terminal| grammar.name(*terminal).clone())
+                        .collect(),
+                    describe_error(error, &table, &slr, &grammar, &extended_grammar),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expects_only_terminals_that_get_shifted() {
+        let errors = errors("id id");
+        // `)` follows F, but only after an open `(`
+        assert_eq!(errors[0].0, ["*", "+", "$"]);
+        assert_eq!(
+            errors[0].1,
+            "after `id` expected `*`, `+` or the end of the input, found `id`\n    \
+             in state 2:\n      3. F -> id •"
+        );
+    }
+
+    #[test]
+    fn describes_errors_at_the_end_and_after_reductions() {
+        // `id` is reduced to `E` before the `+`
+        let after_plus = errors("id +");
+        assert_eq!(after_plus[0].0, ["(", "id"]);
+        assert_eq!(
+            after_plus[0].1,
+            "after `E +` expected `(` or `id`, found the end of the input\n    \
+             in state 7:\n      1. E -> E + • T"
+        );
+
+        // the reductions to `E` happen on `$`, which is only an error afterwards
+        let unclosed = errors("( id");
+        assert_eq!(unclosed[0].0, [")", "+"]);
+        assert_eq!(
+            unclosed[0].1,
+            "after `( E` expected `)` or `+`, found the end of the input\n    \
+             in state 6:\n      1. E -> E • + T\n      4. F -> ( E • )"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{Action, SlrRow, SlrRule};
+
+/// One step of the table driven parser: the configuration before acting and what was done.
+pub struct ParseStep {
+    pub stack: Vec<usize>,
+    pub symbols: Vec<String>,
+    pub input: Vec<String>,
+    pub action: String,
+}
+
+pub struct ParseResult {
+    pub steps: Vec<ParseStep>,
+    /// True if the input reached the accept action without syntax errors.
+    pub accepted: bool,
+    /// Syntax errors found, in input order. After an error the parser recovers if the
+    /// grammar has rules with the `error` terminal, so there can be several.
+    pub errors: Vec<SyntaxError>,
+    /// True if the parser stopped on a cell with several actions, so the input may still
+    /// be in the language.
+    pub conflict: bool,
+}
+
+/// Lookahead with no action in the state on top of the stack.
+pub struct SyntaxError {
+    /// Index of the token in the input (the length of the input for `$`).
+    pub position: usize,
+    pub state: usize,
+    pub found: String,
+    /// True if the input ended here, so `found` is `$`.
+    pub at_eof: bool,
+    /// States and symbols on the stack when the error was found.
+    pub stack: Vec<usize>,
+    pub symbols: Vec<String>,
+}
+
+/// Terminal shifted by the error recovery, as yacc's `error`.
+pub const ERROR_TERMINAL: &str = "error";
+
+/// Number of tokens that must be shifted after an error before reporting another one, so a
+/// single mistake doesn't give a cascade of messages.
+const RECOVERED_SHIFTS: usize = 3;
+
+/// What the parser did in a step, for callers that compute something along the parse.
+pub enum ParseEvent {
+    /// The token at this index of the input was shifted.
+    Shift(usize),
+    /// A reduction by this rule was done.
+    Reduce(usize),
+    /// Error recovery: this many symbols were popped from the stack and then the `error`
+    /// terminal was shifted.
+    Recover(usize),
+}
+
+/// Splits an input string into terminal symbols. Symbols are separated by whitespace,
+/// the same way they are written in the grammar files. A token may be written as
+/// `kind:lexeme` (e.g. `num:42`) to give it a value; tokens without a lexeme use their own
+/// name.
+pub fn tokenize_with_lexemes(input: &str) -> Vec<(String, String)> {
+    input
+        .split_whitespace()
+        .map(|token| match token.find(':') {
+            Some(pos) if pos > 0 => (token[..pos].to_string(), token[pos + 1..].to_string()),
+            _ => (token.to_string(), token.to_string()),
+        })
+        .collect()
+}
+
+/// Number of symbols a reduction by `rule` pops from the stack.
+pub fn rule_len(rule: &SlrRule) -> usize {
+    rule.body().len()
+}
+
+/// Runs the shift/reduce parser over `tokens` (without the final `$`), recording every step.
+pub fn parse_tokens(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    tokens: &[String],
+) -> ParseResult {
+    parse_tokens_with(table, grammar, extended_grammar, tokens, |_| {})
+}
+
+/// Same as `parse_tokens`, calling `on_event` after every shift and reduction.
+///
+/// On a syntax error the parser does yacc's panic mode recovery: it pops states until one
+/// can shift `error`, shifts it, and then discards tokens until one has an action. Errors
+/// found before `RECOVERED_SHIFTS` tokens have been shifted since the last one are not
+/// reported. Without a state that shifts `error` the parse stops at the error.
+pub fn parse_tokens_with(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    tokens: &[String],
+    mut on_event: impl FnMut(ParseEvent),
+) -> ParseResult {
+    let mut input: Vec<String> = tokens.to_vec();
+    input.push("$".to_string());
+
+    let mut stack: Vec<usize> = vec![0];
+    let mut symbols: Vec<String> = Vec::new();
+    let mut pos = 0;
+    let mut steps = Vec::new();
+    let mut errors = Vec::new();
+    let mut shifts_since_error = RECOVERED_SHIFTS;
+    let error_terminal = ERROR_TERMINAL.to_string();
+    let error_id = grammar.id(ERROR_TERMINAL);
+
+    loop {
+        let state = *stack.last().unwrap();
+        let lookahead = &input[pos];
+        let mut step = ParseStep {
+            stack: stack.clone(),
+            symbols: symbols.clone(),
+            input: input[pos..].to_vec(),
+            action: String::new(),
+        };
+
+        // past the last token the lookahead is `$`; a token that isn't a terminal of the
+        // grammar has no action anywhere
+        let lookahead_id = if pos == tokens.len() {
+            Some(grammar.eof)
+        } else {
+            grammar
+                .id(lookahead)
+                .filter(|id| grammar.is_terminal(*id) && *id != grammar.eof)
+        };
+        let at_eof = lookahead_id == Some(grammar.eof);
+        let action = lookahead_id.and_then(|id| table.get(state)?.actions.get(&id));
+        match action {
+            Some(Action::S(next)) => {
+                step.action = format!("shift {}", next);
+                stack.push(*next);
+                symbols.push(lookahead.clone());
+                on_event(ParseEvent::Shift(pos));
+                pos += 1;
+                shifts_since_error += 1;
+            }
+            Some(Action::R(r)) => {
+                let rule = &extended_grammar[*r];
+                let len = rule_len(rule);
+                stack.truncate(stack.len() - len);
+                symbols.truncate(symbols.len() - len);
+                let top = *stack.last().unwrap();
+                match table[top].gotos.get(&grammar.rules[*r].origin) {
+                    Some(next) => {
+                        step.action = format!("reduce {}", rule.to_string().trim_end());
+                        stack.push(*next);
+                        symbols.push(rule.origin.clone());
+                        on_event(ParseEvent::Reduce(*r));
+                    }
+                    None => {
+                        step.action = format!("error: no goto from {} on {}", top, rule.origin);
+                        steps.push(step);
+                        return ParseResult {
+                            steps,
+                            accepted: false,
+                            errors,
+                            conflict: false,
+                        };
+                    }
+                }
+            }
+            Some(Action::Acc) => {
+                step.action = "accept".to_string();
+                steps.push(step);
+                return ParseResult {
+                    steps,
+                    accepted: errors.is_empty(),
+                    errors,
+                    conflict: false,
+                };
+            }
+            Some(Action::Err) => {
+                step.action = format!("error: conflict in state {} on {}", state, lookahead);
+                steps.push(step);
+                return ParseResult {
+                    steps,
+                    accepted: false,
+                    errors,
+                    conflict: true,
+                };
+            }
+            None if shifts_since_error == 0 && !at_eof => {
+                // still recovering from the last error
+                step.action = format!("discard {}", lookahead);
+                pos += 1;
+            }
+            None => {
+                let reported = shifts_since_error >= RECOVERED_SHIFTS;
+                if reported {
+                    errors.push(SyntaxError {
+                        position: pos,
+                        state,
+                        found: lookahead.clone(),
+                        at_eof,
+                        stack: stack.clone(),
+                        symbols: symbols.clone(),
+                    });
+                }
+                // the state closest to the top that can shift `error`
+                let recovery =
+                    stack.iter().enumerate().rev().find_map(|(at, state)| {
+                        match error_id.and_then(|id| table[*state].actions.get(&id)) {
+                            Some(Action::S(next)) => Some((at, *next)),
+                            _ => None,
+                        }
+                    });
+                let (recover_at, next) = match recovery {
+                    Some(found) if shifts_since_error > 0 || !at_eof => found,
+                    _ => {
+                        step.action = if reported {
+                            format!("error: unexpected {}", lookahead)
+                        } else {
+                            format!("error: can't recover before {}", lookahead)
+                        };
+                        steps.push(step);
+                        return ParseResult {
+                            steps,
+                            accepted: false,
+                            errors,
+                            conflict: false,
+                        };
+                    }
+                };
+                let popped = stack.len() - 1 - recover_at;
+                stack.truncate(recover_at + 1);
+                symbols.truncate(symbols.len() - popped);
+                stack.push(next);
+                symbols.push(error_terminal.clone());
+                on_event(ParseEvent::Recover(popped));
+                shifts_since_error = 0;
+                step.action = if reported {
+                    format!(
+                        "error: unexpected {} (pop {}, shift error)",
+                        lookahead, popped
+                    )
+                } else {
+                    format!("pop {}, shift error", popped)
+                };
+            }
+        }
+        steps.push(step);
+    }
+}
+
+/// Table model of a parse trace, so it can be rendered like the parse table.
+pub fn trace_to_table(result: &ParseResult) -> TextTable {
+    let headers = vec![
+        "step".to_string(),
+        "stack".to_string(),
+        "symbols".to_string(),
+        "input".to_string(),
+        "action".to_string(),
+    ];
+    let rows = result
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let stack: Vec<String> = step.stack.iter().map(|s| s.to_string()).collect();
+            vec![
+                (i + 1).to_string(),
+                stack.join(" "),
+                step.symbols.join(" "),
+                step.input.join(" "),
+                step.action.clone(),
+            ]
+        })
+        .collect();
+
+    TextTable { headers, rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Augmented grammar, interned grammar and table of a grammar file.
+    fn table_of(txt: &str) -> (Vec<SlrRule>, InternedGrammar, Vec<SlrRow>) {
+        let extended_grammar = crate::extended_grammar_of(txt);
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        (extended_grammar, grammar, table)
+    }
+
+    fn parse(
+        (extended_grammar, grammar, table): &(Vec<SlrRule>, InternedGrammar, Vec<SlrRow>),
+        input: &str,
+    ) -> ParseResult {
+        let tokens: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+        parse_tokens(table, grammar, extended_grammar, &tokens)
+    }
+
+    fn positions(result: &ParseResult) -> Vec<usize> {
+        result.errors.iter().map(|error| error.position).collect()
+    }
+
+    #[test]
+    fn splits_kind_and_lexeme() {
+        assert_eq!(
+            tokenize_with_lexemes("num:3 + :x id"),
+            [
+                ("num".to_string(), "3".to_string()),
+                ("+".to_string(), "+".to_string()),
+                (":x".to_string(), ":x".to_string()),
+                ("id".to_string(), "id".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_error_without_error_rules() {
+        let analysis = table_of(include_str!("../test_txts/input2.txt"));
+        assert!(parse(&analysis, "( id + id ) * id").accepted);
+
+        let result = parse(&analysis, "id id + id id");
+        assert!(!result.accepted);
+        assert_eq!(positions(&result), [1]);
+        assert_eq!(result.errors[0].found, "id");
+        assert!(!result.errors[0].at_eof);
+        assert_eq!(result.steps.last().unwrap().action, "error: unexpected id");
+
+        // unknown tokens have no action
+        assert_eq!(positions(&parse(&analysis, "id - id")), [1]);
+    }
+
+    #[test]
+    fn cells_with_conflicts_stop_the_parse() {
+        let analysis = table_of("2\nE -> E + E\nE -> id");
+        let result = parse(&analysis, "id + id");
+        assert!(result.accepted && !result.conflict);
+
+        // after `E + E` the `+` can be shifted or reduce by `E -> E + E`
+        let result = parse(&analysis, "id + id + id");
+        assert!(!result.accepted && result.conflict);
+        assert!(result.errors.is_empty());
+
+        let result = parse(&analysis, "id +");
+        assert!(!result.accepted && !result.conflict);
+    }
+
+    #[test]
+    fn only_the_end_of_the_input_is_eof() {
+        let analysis = table_of("2\nS -> a S\nS -> a");
+        let result = parse(&analysis, "a a");
+        assert!(result.accepted);
+        assert_eq!(result.steps[0].input, ["a", "a", "$"]);
+
+        // a `$` token is not the end of the input
+        let result = parse(&analysis, "a $");
+        assert!(!result.accepted);
+        assert_eq!(positions(&result), [1]);
+        assert!(!result.errors[0].at_eof);
+
+        let result = parse(&analysis, "");
+        assert_eq!(positions(&result), [0]);
+        assert!(result.errors[0].at_eof);
+
+        // `$` can't be a symbol of the grammar
+        assert_eq!(crate::extended_grammar_of("1\nS -> a $").len(), 1);
+    }
+
+    #[test]
+    fn recovers_and_reports_every_error() {
+        let analysis = table_of(include_str!("../test_txts/input_error.txt"));
+        assert!(parse(&analysis, "id ; id + id ;").accepted);
+
+        let result = parse(&analysis, "id id ; id ; id ; + ;");
+        assert!(!result.accepted);
+        assert_eq!(positions(&result), [1, 7]);
+        // the parse goes on to the end after recovering
+        assert_eq!(result.steps.last().unwrap().action, "accept");
+        let actions: Vec<&str> = result.steps.iter().map(|s| s.action.as_str()).collect();
+        assert!(actions.contains(&"error: unexpected id (pop 1, shift error)"));
+        assert!(actions.contains(&"discard id"));
+    }
+
+    #[test]
+    fn errors_right_after_recovering_are_not_reported() {
+        let (extended_grammar, grammar, table) =
+            table_of(include_str!("../test_txts/input_error.txt"));
+        let tokens: Vec<String> = "id + ; + ; id ;"
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        let mut recovered = Vec::new();
+        let result = parse_tokens_with(&table, &grammar, &extended_grammar, &tokens, |event| {
+            if let ParseEvent::Recover(popped) = event {
+                recovered.push(popped);
+            }
+        });
+        assert_eq!(positions(&result), [2]);
+        assert_eq!(recovered, [2, 2]);
+        assert!(!result.accepted);
+        assert_eq!(result.steps.last().unwrap().action, "accept");
+    }
+}
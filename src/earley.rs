@@ -0,0 +1,514 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{SlrItem, SlrRule};
+
+/// Item of an Earley set: an LR(0) item and the set where its rule was predicted.
+#[derive(Eq, Hash, PartialEq, Clone, Copy, PartialOrd, Ord, Debug)]
+pub struct EarleyItem {
+    pub item: SlrItem,
+    pub origin: usize,
+}
+
+/// How an item got into its set, for the chart printer.
+#[derive(Clone, Copy)]
+pub enum Reason {
+    Start,
+    Predict,
+    Scan,
+    Complete,
+    /// Advanced over a nullable non terminal when it was predicted.
+    Nullable,
+}
+
+impl Reason {
+    fn name(&self) -> &'static str {
+        match self {
+            Reason::Start => "start",
+            Reason::Predict => "predict",
+            Reason::Scan => "scan",
+            Reason::Complete => "complete",
+            Reason::Nullable => "nullable",
+        }
+    }
+}
+
+/// Earley sets of an input: `sets[i]` has the items after reading `i` tokens.
+pub struct Chart {
+    pub tokens: Vec<String>,
+    pub sets: Vec<Vec<(EarleyItem, Reason)>>,
+    index: Vec<HashSet<EarleyItem>>,
+}
+
+/// Node of the parse forest: a symbol and the tokens `start..end` it derives.
+pub type ForestNode = (usize, usize, usize);
+
+/// Shared packed parse forest: for every non terminal node, the different ways (rule and
+/// children) it derives its tokens. Terminal nodes are leaves and have no entry.
+pub type Forest = BTreeMap<ForestNode, Vec<(usize, Vec<ForestNode>)>>;
+
+impl Chart {
+    fn add(&mut self, set: usize, item: EarleyItem, reason: Reason) {
+        if self.index[set].insert(item) {
+            self.sets[set].push((item, reason));
+        }
+    }
+
+    fn contains(&self, set: usize, item: EarleyItem) -> bool {
+        self.index[set].contains(&item)
+    }
+
+    /// True if the augmented rule is complete over the whole input.
+    pub fn accepted(&self) -> bool {
+        let done = EarleyItem {
+            item: SlrItem { rule: 0, dot: 1 },
+            origin: 0,
+        };
+        self.contains(self.tokens.len(), done)
+    }
+
+    /// Number of tokens read before a set was left empty, if the parse failed that way.
+    pub fn failed_at(&self) -> Option<usize> {
+        (1..self.sets.len())
+            .find(|set| self.sets[*set].is_empty())
+            .map(|set| set - 1)
+    }
+
+    /// Every set as rows of a table: set, item, origin and how the item was added.
+    pub fn to_table(&self, extended_grammar: &[SlrRule]) -> TextTable {
+        let mut rows = Vec::new();
+        for (num, set) in self.sets.iter().enumerate() {
+            for (pos, (item, reason)) in set.iter().enumerate() {
+                let label = if pos > 0 {
+                    String::new()
+                } else if num == 0 {
+                    "0".to_string()
+                } else {
+                    format!("{} ({})", num, self.tokens[num - 1])
+                };
+                rows.push(vec![
+                    label,
+                    item.item.to_string(extended_grammar),
+                    item.origin.to_string(),
+                    reason.name().to_string(),
+                ]);
+            }
+        }
+        TextTable {
+            headers: vec![
+                "set".to_string(),
+                "item".to_string(),
+                "origin".to_string(),
+                "added by".to_string(),
+            ],
+            rows,
+        }
+    }
+}
+
+/// Earley recognizer. Each set is processed in order: complete items advance the items of
+/// their origin set waiting for the non terminal, items before a non terminal predict its
+/// rules and items before a terminal scan the next token. Predicting a nullable non terminal
+/// also advances over it (Aycock and Horspool), since its completion in the same set may
+/// come before the items that wait for it.
+pub fn recognize(grammar: &InternedGrammar, tokens: &[String]) -> Chart {
+    let ids: Vec<Option<usize>> = tokens
+        .iter()
+        .map(|token| grammar.id(token).filter(|id| grammar.is_terminal(*id)))
+        .collect();
+    let mut chart = Chart {
+        tokens: tokens.to_vec(),
+        sets: vec![Vec::new(); tokens.len() + 1],
+        index: vec![HashSet::new(); tokens.len() + 1],
+    };
+    chart.add(
+        0,
+        EarleyItem {
+            item: SlrItem::new(0),
+            origin: 0,
+        },
+        Reason::Start,
+    );
+
+    for set in 0..=tokens.len() {
+        let mut current = 0;
+        while current < chart.sets[set].len() {
+            let (EarleyItem { item, origin }, _) = chart.sets[set][current];
+            current += 1;
+            match item.get_reading_symbol(grammar) {
+                None => {
+                    let symbol = grammar.rules[item.rule].origin;
+                    let waiting: Vec<EarleyItem> = chart.sets[origin]
+                        .iter()
+                        .map(|(waiting, _)| *waiting)
+                        .filter(|waiting| waiting.item.get_reading_symbol(grammar) == Some(symbol))
+                        .collect();
+                    for waiting in waiting {
+                        let advanced = EarleyItem {
+                            item: waiting.item.advance(),
+                            origin: waiting.origin,
+                        };
+                        chart.add(set, advanced, Reason::Complete);
+                    }
+                }
+                Some(symbol) if grammar.is_terminal(symbol) => {
+                    if ids.get(set) == Some(&Some(symbol)) {
+                        let advanced = EarleyItem {
+                            item: item.advance(),
+                            origin,
+                        };
+                        chart.add(set + 1, advanced, Reason::Scan);
+                    }
+                }
+                Some(symbol) => {
+                    for rule in grammar.rules_by_origin[symbol].iter() {
+                        let predicted = EarleyItem {
+                            item: SlrItem::new(*rule),
+                            origin: set,
+                        };
+                        chart.add(set, predicted, Reason::Predict);
+                    }
+                    if grammar.nullable[symbol] {
+                        let advanced = EarleyItem {
+                            item: item.advance(),
+                            origin,
+                        };
+                        chart.add(set, advanced, Reason::Nullable);
+                    }
+                }
+            }
+        }
+    }
+    chart
+}
+
+/// Parse forest of an accepted input, from the user's start symbol over the whole input.
+///
+/// A node `(A, i, j)` gets a family for every rule of `A` complete in set `j` with origin
+/// `i` and every way of splitting `i..j` among the symbols of its body, where each split
+/// point is confirmed by the chart: the item with the dot there is in the set of that point.
+pub fn build_forest(grammar: &InternedGrammar, chart: &Chart) -> Forest {
+    let mut forest = Forest::new();
+    let root = (grammar.rules[0].body[0], 0, chart.tokens.len());
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        if forest.contains_key(&node) {
+            continue;
+        }
+        let (symbol, start, end) = node;
+        let mut families = Vec::new();
+        for rule in grammar.rules_by_origin[symbol].iter() {
+            let body_len = grammar.rules[*rule].body.len();
+            let done = EarleyItem {
+                item: SlrItem {
+                    rule: *rule,
+                    dot: body_len,
+                },
+                origin: start,
+            };
+            if !chart.contains(end, done) {
+                continue;
+            }
+            for children in splits(grammar, chart, *rule, body_len, start, end) {
+                pending.extend(
+                    children
+                        .iter()
+                        .filter(|child| !grammar.is_terminal(child.0))
+                        .copied(),
+                );
+                families.push((*rule, children));
+            }
+        }
+        forest.insert(node, families);
+    }
+    forest
+}
+
+/// Ways the first `dot` symbols of `rule`, predicted in set `start`, derive `start..end`.
+fn splits(
+    grammar: &InternedGrammar,
+    chart: &Chart,
+    rule: usize,
+    dot: usize,
+    start: usize,
+    end: usize,
+) -> Vec<Vec<ForestNode>> {
+    if dot == 0 {
+        return if start == end {
+            vec![Vec::new()]
+        } else {
+            Vec::new()
+        };
+    }
+    let symbol = grammar.rules[rule].body[dot - 1];
+    let before = EarleyItem {
+        item: SlrItem { rule, dot: dot - 1 },
+        origin: start,
+    };
+    let mut ret = Vec::new();
+    for mid in start..=end {
+        if !chart.contains(mid, before) {
+            continue;
+        }
+        let derives = if grammar.is_terminal(symbol) {
+            end == mid + 1 && chart.tokens[mid] == *grammar.name(symbol)
+        } else {
+            grammar.rules_by_origin[symbol].iter().any(|other| {
+                let done = EarleyItem {
+                    item: SlrItem {
+                        rule: *other,
+                        dot: grammar.rules[*other].body.len(),
+                    },
+                    origin: mid,
+                };
+                chart.contains(end, done)
+            })
+        };
+        if !derives {
+            continue;
+        }
+        for mut children in splits(grammar, chart, rule, dot - 1, start, mid) {
+            children.push((symbol, mid, end));
+            ret.push(children);
+        }
+    }
+    ret
+}
+
+/// `E[0,3]`: a symbol and the tokens it derives.
+pub fn node_string(grammar: &InternedGrammar, node: &ForestNode) -> String {
+    format!("{}[{},{}]", grammar.name(node.0), node.1, node.2)
+}
+
+/// One row per node of the forest, with its families separated by `|`.
+pub fn forest_table(
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    forest: &Forest,
+) -> TextTable {
+    let rows = forest
+        .iter()
+        .map(|(node, families)| {
+            let families: Vec<String> = families
+                .iter()
+                .map(|(rule, children)| {
+                    let children: Vec<String> = children
+                        .iter()
+                        .map(|child| node_string(grammar, child))
+                        .collect();
+                    if children.is_empty() {
+                        format!("{}: ' '", extended_grammar[*rule].num)
+                    } else {
+                        format!("{}: {}", extended_grammar[*rule].num, children.join(" "))
+                    }
+                })
+                .collect();
+            vec![node_string(grammar, node), families.join(" | ")]
+        })
+        .collect();
+    TextTable {
+        headers: vec!["node".to_string(), "rule: children".to_string()],
+        rows,
+    }
+}
+
+/// Number of parse trees in the forest, or `None` if there are infinitely many (the
+/// forest has a cycle, from rules like `A -> B` and `B -> A`).
+pub fn count_trees(grammar: &InternedGrammar, forest: &Forest, node: ForestNode) -> Option<u64> {
+    fn count(
+        grammar: &InternedGrammar,
+        forest: &Forest,
+        node: ForestNode,
+        memo: &mut HashMap<ForestNode, Option<u64>>,
+        on_path: &mut HashSet<ForestNode>,
+    ) -> Option<u64> {
+        if grammar.is_terminal(node.0) {
+            return Some(1);
+        }
+        if let Some(known) = memo.get(&node) {
+            return *known;
+        }
+        if !on_path.insert(node) {
+            return None;
+        }
+        let mut total: Option<u64> = Some(0);
+        for (_, children) in forest[&node].iter() {
+            let mut product = Some(1u64);
+            for child in children.iter() {
+                let child_count = count(grammar, forest, *child, memo, on_path);
+                product = product
+                    .zip(child_count)
+                    .map(|(product, child_count)| product.saturating_mul(child_count));
+            }
+            total = total
+                .zip(product)
+                .map(|(total, product)| total.saturating_add(product));
+        }
+        on_path.remove(&node);
+        memo.insert(node, total);
+        total
+    }
+    count(
+        grammar,
+        forest,
+        node,
+        &mut HashMap::new(),
+        &mut HashSet::new(),
+    )
+}
+
+/// One parse tree of the forest in brackets (`[E [E id] + [T id]]`), taking for every node
+/// the first family that doesn't go back to a node already being expanded.
+pub fn first_tree(
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    forest: &Forest,
+    node: ForestNode,
+) -> Option<String> {
+    fn tree(
+        grammar: &InternedGrammar,
+        extended_grammar: &[SlrRule],
+        forest: &Forest,
+        node: ForestNode,
+        on_path: &mut HashSet<ForestNode>,
+    ) -> Option<String> {
+        if grammar.is_terminal(node.0) {
+            return Some(grammar.name(node.0).clone());
+        }
+        if !on_path.insert(node) {
+            return None;
+        }
+        let mut ret = None;
+        for (rule, children) in forest[&node].iter() {
+            let children: Option<Vec<String>> = children
+                .iter()
+                .map(|child| tree(grammar, extended_grammar, forest, *child, on_path))
+                .collect();
+            if let Some(children) = children {
+                let mut node = format!("[{}", extended_grammar[*rule].origin);
+                for child in children {
+                    node += " ";
+                    node += &child;
+                }
+                ret = Some(node + "]");
+                break;
+            }
+        }
+        on_path.remove(&node);
+        ret
+    }
+    tree(grammar, extended_grammar, forest, node, &mut HashSet::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver, generate, interned_names};
+
+    fn words(txt: &str) -> Vec<String> {
+        txt.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// Extended and interned grammar of a text.
+    fn grammars(txt: &str) -> (Vec<SlrRule>, InternedGrammar) {
+        let extended_grammar = crate::extended_grammar_of(txt);
+        let grammar = InternedGrammar::new(&extended_grammar);
+        (extended_grammar, grammar)
+    }
+
+    /// Chart, forest and number of trees of an input.
+    fn parse(grammar: &InternedGrammar, input: &str) -> (Chart, Forest, Option<u64>) {
+        let chart = recognize(grammar, &words(input));
+        let forest = build_forest(grammar, &chart);
+        let root = (grammar.rules[0].body[0], 0, chart.tokens.len());
+        let trees = count_trees(grammar, &forest, root);
+        (chart, forest, trees)
+    }
+
+    #[test]
+    fn accepts_what_the_slr_table_accepts() {
+        let (extended_grammar, grammar) = grammars(include_str!("../test_txts/input2.txt"));
+        let (_, table) = crate::slr_table_of(&grammar);
+        let mut strings = vec![Vec::new()];
+        for _ in 0..4 {
+            strings = strings
+                .iter()
+                .flat_map(|prefix: &Vec<usize>| {
+                    (0..grammar.eof).map(move |t| [prefix.as_slice(), &[t]].concat())
+                })
+                .collect();
+            for string in strings.iter() {
+                let tokens = interned_names(&grammar, string);
+                let slr = driver::parse_tokens(&table, &grammar, &extended_grammar, &tokens);
+                assert_eq!(recognize(&grammar, &tokens).accepted(), slr.accepted);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_the_trees_of_an_ambiguous_grammar() {
+        let (extended_grammar, grammar) =
+            grammars(include_str!("../test_txts/input_ambiguous.txt"));
+        // every sentence is accepted, even though the SLR table has conflicts
+        for sentence in generate::enumerate(&grammar, 5) {
+            let tokens = interned_names(&grammar, &sentence);
+            assert!(recognize(&grammar, &tokens).accepted());
+        }
+
+        assert_eq!(parse(&grammar, "id").2, Some(1));
+        assert_eq!(parse(&grammar, "id + id * id").2, Some(2));
+        // Catalan number: the ways of grouping 4 operands
+        assert_eq!(parse(&grammar, "id + id + id + id").2, Some(5));
+
+        let (_, forest, _) = parse(&grammar, "( id )");
+        let root = (grammar.id("E").unwrap(), 0, 3);
+        assert_eq!(
+            first_tree(&grammar, &extended_grammar, &forest, root),
+            Some("[E ( [E id] )]".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_where_the_input_fails() {
+        let grammar = InternedGrammar::new(&crate::extended_grammar_of(include_str!(
+            "../test_txts/input_ambiguous.txt"
+        )));
+        let chart = recognize(&grammar, &words("id + + id"));
+        assert!(!chart.accepted());
+        assert_eq!(chart.failed_at(), Some(2));
+
+        // a prefix of a sentence fails at the end, with no empty set
+        let chart = recognize(&grammar, &words("id +"));
+        assert!(!chart.accepted());
+        assert_eq!(chart.failed_at(), None);
+    }
+
+    #[test]
+    fn nullable_symbols_are_skipped_when_predicted() {
+        let (_, grammar) = grammars(include_str!("../test_txts/input_chain2.txt"));
+        let (chart, forest, trees) = parse(&grammar, "c b a");
+        assert!(chart.accepted());
+        assert!(chart
+            .sets
+            .iter()
+            .flatten()
+            .any(|(_, reason)| matches!(reason, Reason::Nullable)));
+        assert_eq!(trees, Some(1));
+        let d = grammar.id("D").unwrap();
+        assert_eq!(forest[&(d, 0, 0)].len(), 1);
+    }
+
+    #[test]
+    fn cycles_give_infinitely_many_trees() {
+        let (extended_grammar, grammar) = grammars("3\nS -> A\nA -> S\nA -> a\n");
+        let (chart, forest, trees) = parse(&grammar, "a");
+        assert!(chart.accepted());
+        assert_eq!(trees, None);
+        let root = (grammar.id("S").unwrap(), 0, 1);
+        assert_eq!(
+            first_tree(&grammar, &extended_grammar, &forest, root),
+            Some("[S [A a]]".to_string())
+        );
+    }
+}
@@ -0,0 +1,542 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::driver;
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{SlrRow, SlrRule};
+
+/// Small xorshift64* generator, so the sentences of a seed are the same on every run.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // the state can't be 0
+        Rng {
+            state: (seed ^ 0x9e37_79b9_7f4a_7c15).max(1),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Marks a symbol that derives no terminal string.
+const UNREACHABLE: usize = usize::MAX;
+
+/// Sentences generated for a target length, keeping the closest one.
+const LENGTH_ATTEMPTS: usize = 50;
+
+/// Random derivations from the start symbol. Every symbol knows the height of its shortest
+/// derivation tree and the length of its shortest sentence, to be able to finish a
+/// derivation when it gets too deep or too long.
+pub struct Generator<'a> {
+    grammar: &'a InternedGrammar,
+    min_height: Vec<usize>,
+    min_length: Vec<usize>,
+    rule_height: Vec<usize>,
+    rule_length: Vec<usize>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(grammar: &'a InternedGrammar) -> Generator<'a> {
+        let n_symbols = grammar.names.len();
+        let mut min_height = vec![UNREACHABLE; n_symbols];
+        let mut min_length = vec![UNREACHABLE; n_symbols];
+        for id in 0..=grammar.eof {
+            min_height[id] = 0;
+            min_length[id] = 1;
+        }
+        let mut rule_height = vec![UNREACHABLE; grammar.rules.len()];
+        let mut rule_length = vec![UNREACHABLE; grammar.rules.len()];
+
+        // fixed point, both only go down
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (num, rule) in grammar.rules.iter().enumerate() {
+                if rule
+                    .body
+                    .iter()
+                    .any(|symbol| min_height[*symbol] == UNREACHABLE)
+                {
+                    continue;
+                }
+                let height = 1 + rule
+                    .body
+                    .iter()
+                    .map(|symbol| min_height[*symbol])
+                    .max()
+                    .unwrap_or(0);
+                let length: usize = rule.body.iter().map(|symbol| min_length[*symbol]).sum();
+                rule_height[num] = height;
+                rule_length[num] = length;
+                if height < min_height[rule.origin] {
+                    min_height[rule.origin] = height;
+                    changed = true;
+                }
+                if length < min_length[rule.origin] {
+                    min_length[rule.origin] = length;
+                    changed = true;
+                }
+            }
+        }
+
+        Generator {
+            grammar,
+            min_height,
+            min_length,
+            rule_height,
+            rule_length,
+        }
+    }
+
+    /// Random sentence (terminal ids) derived from the start symbol, or `None` if the start
+    /// symbol derives no terminal string.
+    ///
+    /// Rules that would make the tree deeper than `max_depth` are avoided, and so are rules
+    /// that would make the sentence longer than `target` when there is one; when every rule
+    /// does, the one with the shortest derivation is used. Without a target, rules that take
+    /// longer to finish are less likely, more so the deeper the derivation is. With a target
+    /// several sentences are generated and the one with the closest length is kept.
+    pub fn sentence(
+        &self,
+        rng: &mut Rng,
+        max_depth: usize,
+        target: Option<usize>,
+    ) -> Option<Vec<usize>> {
+        if self.min_height[self.grammar.start] == UNREACHABLE {
+            return None;
+        }
+        let target = match target {
+            Some(target) => target,
+            None => return Some(self.derive(rng, max_depth, None)),
+        };
+        let mut best: Option<Vec<usize>> = None;
+        for _ in 0..LENGTH_ATTEMPTS {
+            let sentence = self.derive(rng, max_depth, Some(target));
+            let distance = sentence.len().abs_diff(target);
+            if best
+                .as_ref()
+                .is_none_or(|best| distance < best.len().abs_diff(target))
+            {
+                best = Some(sentence);
+            }
+            if distance == 0 {
+                break;
+            }
+        }
+        best
+    }
+
+    /// One leftmost derivation, expanding the symbols from a stack.
+    fn derive(&self, rng: &mut Rng, max_depth: usize, target: Option<usize>) -> Vec<usize> {
+        let mut ret = Vec::new();
+        let mut pending: Vec<(usize, usize)> = vec![(self.grammar.start, 0)];
+        // length of the sentence if every pending symbol took its shortest sentence
+        let mut committed = self.min_length[self.grammar.start];
+
+        while let Some((symbol, depth)) = pending.pop() {
+            if self.grammar.is_terminal(symbol) {
+                ret.push(symbol);
+                continue;
+            }
+            let rules: Vec<usize> = self.grammar.rules_by_origin[symbol]
+                .iter()
+                .copied()
+                .filter(|rule| self.rule_height[*rule] != UNREACHABLE)
+                .collect();
+            let allowed: Vec<usize> = rules
+                .iter()
+                .copied()
+                .filter(|rule| {
+                    depth + self.rule_height[*rule] <= max_depth
+                        && target.is_none_or(|target| {
+                            committed - self.min_length[symbol] + self.rule_length[*rule] <= target
+                        })
+                })
+                .collect();
+            let rule = if allowed.is_empty() {
+                *rules
+                    .iter()
+                    .min_by_key(|rule| (self.rule_height[**rule], self.rule_length[**rule]))
+                    .unwrap()
+            } else {
+                let weights: Vec<f64> = allowed
+                    .iter()
+                    .map(|rule| match target {
+                        // below the target every rule is as good
+                        Some(_) => 1.0,
+                        None => {
+                            let extra = (self.rule_height[*rule] - self.min_height[symbol]) as f64;
+                            let steepness = 1.0 + 2.0 * depth as f64 / max_depth.max(1) as f64;
+                            (1.0 + extra).powf(-steepness)
+                        }
+                    })
+                    .collect();
+                allowed[weighted_choice(rng, &weights)]
+            };
+
+            committed = committed - self.min_length[symbol] + self.rule_length[rule];
+            for body_symbol in self.grammar.rules[rule].body.iter().rev() {
+                pending.push((*body_symbol, depth + 1));
+            }
+        }
+        ret
+    }
+}
+
+/// Index picked with probability proportional to its weight.
+fn weighted_choice(rng: &mut Rng, weights: &[f64]) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut point = rng.next_f64() * total;
+    for (num, weight) in weights.iter().enumerate() {
+        if point < *weight {
+            return num;
+        }
+        point -= weight;
+    }
+    weights.len() - 1
+}
+
+/// Every sentence of the language with at most `max_length` tokens, in shortlex order (by
+/// length, then by the names of the terminals).
+///
+/// The sentences of every symbol are found together by a fixed point over the rules,
+/// concatenating the sentences of the symbols of each body. A partial concatenation is
+/// dropped when it can't end within the bound, counting one token for every symbol left
+/// that isn't nullable.
+pub fn enumerate(grammar: &InternedGrammar, max_length: usize) -> Vec<Vec<usize>> {
+    let mut sentences: Vec<HashSet<Vec<usize>>> = vec![HashSet::new(); grammar.names.len()];
+    for (id, terminal) in sentences.iter_mut().enumerate().take(grammar.eof) {
+        terminal.insert(vec![id]);
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in grammar.rules.iter() {
+            let mut partial: HashSet<Vec<usize>> = HashSet::from([Vec::new()]);
+            for (pos, symbol) in rule.body.iter().enumerate() {
+                let needed = rule.body[pos + 1..]
+                    .iter()
+                    .filter(|symbol| !grammar.nullable[**symbol])
+                    .count();
+                let mut next = HashSet::new();
+                for prefix in partial.iter() {
+                    for sentence in sentences[*symbol].iter() {
+                        if prefix.len() + sentence.len() + needed <= max_length {
+                            next.insert([prefix.as_slice(), sentence.as_slice()].concat());
+                        }
+                    }
+                }
+                partial = next;
+                if partial.is_empty() {
+                    break;
+                }
+            }
+            for sentence in partial {
+                changed |= sentences[rule.origin].insert(sentence);
+            }
+        }
+    }
+
+    let mut ret: Vec<Vec<usize>> = sentences[grammar.start].drain().collect();
+    ret.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    ret
+}
+
+/// Order of two sentences in shortlex order.
+fn shortlex(a: &[String], b: &[String]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// First sentence, in shortlex order, that is in only one of two lists sorted in shortlex
+/// order, and whether it's in the first one.
+pub fn first_difference(
+    first: &[Vec<String>],
+    second: &[Vec<String>],
+) -> Option<(Vec<String>, bool)> {
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (first.get(i), second.get(j)) {
+            (None, None) => return None,
+            (Some(sentence), None) => return Some((sentence.clone(), true)),
+            (None, Some(sentence)) => return Some((sentence.clone(), false)),
+            (Some(a), Some(b)) => match shortlex(a, b) {
+                Ordering::Less => return Some((a.clone(), true)),
+                Ordering::Greater => return Some((b.clone(), false)),
+                Ordering::Equal => {
+                    i += 1;
+                    j += 1;
+                }
+            },
+        }
+    }
+}
+
+/// One row per sentence: its length, its tokens (`' '` for the empty sentence) and whether
+/// the table accepts it, or `conflict` if the parse stops on a cell with several actions.
+pub fn sentences_table(
+    sentences: &[Vec<String>],
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+) -> TextTable {
+    TextTable {
+        headers: vec![
+            "length".to_string(),
+            "sentence".to_string(),
+            "parse".to_string(),
+        ],
+        rows: sentences
+            .iter()
+            .map(|tokens| {
+                let result = driver::parse_tokens(table, grammar, extended_grammar, tokens);
+                vec![
+                    tokens.len().to_string(),
+                    if tokens.is_empty() {
+                        "' '".to_string()
+                    } else {
+                        tokens.join(" ")
+                    },
+                    if result.accepted {
+                        "ACCEPTED"
+                    } else if result.conflict {
+                        "conflict"
+                    } else {
+                        "REJECTED"
+                    }
+                    .to_string(),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interned_names;
+
+    /// Grammar of a text, with the SLR(1) table to check sentences against.
+    struct Analysis {
+        extended_grammar: Vec<SlrRule>,
+        interned: InternedGrammar,
+        table: Vec<SlrRow>,
+    }
+
+    fn analyze(txt: &str) -> Analysis {
+        let extended_grammar = crate::extended_grammar_of(txt);
+        let interned = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&interned);
+        Analysis {
+            extended_grammar,
+            interned,
+            table,
+        }
+    }
+
+    fn words_of(txt: &str) -> Vec<String> {
+        txt.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn grammar_of(txt: &str) -> InternedGrammar {
+        InternedGrammar::new(&crate::extended_grammar_of(txt))
+    }
+
+    fn accepted(analysis: &Analysis, sentence: &[usize]) -> bool {
+        let tokens = interned_names(&analysis.interned, sentence);
+        driver::parse_tokens(
+            &analysis.table,
+            &analysis.interned,
+            &analysis.extended_grammar,
+            &tokens,
+        )
+        .accepted
+    }
+
+    #[test]
+    fn rng_is_deterministic() {
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        let first: Vec<u64> = (0..5).map(|_| a.next_u64()).collect();
+        let second: Vec<u64> = (0..5).map(|_| b.next_u64()).collect();
+        assert_eq!(first, second);
+        assert_ne!(Rng::new(8).next_u64(), first[0]);
+        let mut rng = Rng::new(0);
+        assert!((0..1000)
+            .map(|_| rng.next_f64())
+            .all(|x| (0.0..1.0).contains(&x)));
+    }
+
+    #[test]
+    fn generated_sentences_are_accepted() {
+        for txt in [
+            include_str!("../test_txts/input1.txt"),
+            include_str!("../test_txts/input2.txt"),
+            include_str!("../test_txts/input4.txt"),
+        ] {
+            let analysis = analyze(txt);
+            let generator = Generator::new(&analysis.interned);
+            let mut rng = Rng::new(42);
+            for _ in 0..50 {
+                let sentence = generator.sentence(&mut rng, 8, None).unwrap();
+                assert!(accepted(&analysis, &sentence));
+            }
+        }
+    }
+
+    #[test]
+    fn target_length_is_reached_when_possible() {
+        let analysis = analyze(include_str!("../test_txts/input2.txt"));
+        let generator = Generator::new(&analysis.interned);
+        let mut rng = Rng::new(1);
+        for target in [1, 3, 5, 9] {
+            let sentence = generator.sentence(&mut rng, 20, Some(target)).unwrap();
+            assert_eq!(sentence.len(), target);
+            assert!(accepted(&analysis, &sentence));
+        }
+        // every sentence of input2 has an odd length
+        let sentence = generator.sentence(&mut rng, 20, Some(4)).unwrap();
+        assert_eq!(sentence.len().abs_diff(4), 1);
+    }
+
+    #[test]
+    fn unproductive_start_gives_no_sentence() {
+        let grammar = grammar_of("1\nS -> S a\n");
+        let generator = Generator::new(&grammar);
+        assert_eq!(generator.sentence(&mut Rng::new(1), 10, None), None);
+    }
+
+    fn names(grammar: &InternedGrammar, sentences: &[Vec<usize>]) -> Vec<String> {
+        sentences
+            .iter()
+            .map(|sentence| interned_names(grammar, sentence).join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn enumerates_in_shortlex_order() {
+        let grammar = grammar_of(include_str!("../test_txts/input2.txt"));
+        assert_eq!(
+            names(&grammar, &enumerate(&grammar, 3)),
+            ["id", "( id )", "id * id", "id + id"]
+        );
+
+        let grammar = grammar_of(include_str!("../test_txts/input3.txt"));
+        assert_eq!(
+            names(&grammar, &enumerate(&grammar, 4)),
+            ["a b b", "a a b b", "b a b b"]
+        );
+
+        // the empty sentence comes first
+        let grammar = grammar_of("2\nS -> a S\nS -> ' '\n");
+        assert_eq!(names(&grammar, &enumerate(&grammar, 2)), ["", "a", "a a"]);
+    }
+
+    #[test]
+    fn enumerates_exactly_the_accepted_strings() {
+        let analysis = analyze(include_str!("../test_txts/input4.txt"));
+        let grammar = &analysis.interned;
+        let sentences: HashSet<Vec<usize>> = enumerate(grammar, 4).into_iter().collect();
+
+        let mut strings = vec![Vec::new()];
+        for _ in 0..4 {
+            strings = strings
+                .iter()
+                .flat_map(|prefix: &Vec<usize>| {
+                    (0..grammar.eof).map(move |t| [prefix.as_slice(), &[t]].concat())
+                })
+                .collect();
+            for string in strings.iter() {
+                assert_eq!(
+                    sentences.contains(string),
+                    accepted(&analysis, string),
+                    "{:?}",
+                    interned_names(grammar, string)
+                );
+            }
+        }
+    }
+
+    fn sentences(txt: &str, bound: usize) -> Vec<Vec<String>> {
+        let grammar = grammar_of(txt);
+        enumerate(&grammar, bound)
+            .iter()
+            .map(|sentence| interned_names(&grammar, sentence))
+            .collect()
+    }
+
+    #[test]
+    fn first_difference_in_shortlex_order() {
+        let list = |sentences: &[&str]| -> Vec<Vec<String>> {
+            sentences
+                .iter()
+                .map(|s| s.split_whitespace().map(str::to_string).collect())
+                .collect()
+        };
+        let words = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        let first = list(&["a", "a b", "b b"]);
+        assert_eq!(first_difference(&first, &first), None);
+        assert_eq!(
+            first_difference(&first, &list(&["a", "b b"])),
+            Some((words("a b"), true))
+        );
+        assert_eq!(
+            first_difference(&first, &list(&["a", "a a", "a b"])),
+            Some((words("a a"), false))
+        );
+        assert_eq!(
+            first_difference(&list(&[""]), &list(&[])),
+            Some((Vec::new(), true))
+        );
+    }
+
+    #[test]
+    fn sentences_that_reach_a_conflict_are_not_rejected() {
+        let analysis = analyze("2\nE -> E + E\nE -> id\n");
+        let sentences = [
+            words_of("id + id"),
+            words_of("id + id + id"),
+            words_of("id +"),
+        ];
+        let table = sentences_table(
+            &sentences,
+            &analysis.table,
+            &analysis.interned,
+            &analysis.extended_grammar,
+        );
+        let results: Vec<&str> = table.rows.iter().map(|row| row[2].as_str()).collect();
+        assert_eq!(results, ["ACCEPTED", "conflict", "REJECTED"]);
+    }
+
+    #[test]
+    fn compares_languages_up_to_a_bound() {
+        let input1 = sentences(include_str!("../test_txts/input1.txt"), 7);
+        let input2 = sentences(include_str!("../test_txts/input2.txt"), 7);
+        let ambiguous = sentences(include_str!("../test_txts/input_ambiguous.txt"), 7);
+        // removing the left recursion or the ambiguity keeps the language
+        assert_eq!(first_difference(&input2, &input1), None);
+        assert_eq!(first_difference(&input2, &ambiguous), None);
+
+        let sums = sentences("3\nE -> E + id\nE -> id\nE -> ( E )\n", 7);
+        assert_eq!(
+            first_difference(&input2, &sums),
+            Some((
+                vec!["id".to_string(), "*".to_string(), "id".to_string()],
+                true
+            ))
+        );
+    }
+}
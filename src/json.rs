@@ -0,0 +1,984 @@
+//! JSON export and import of a full grammar analysis.
+//!
+//! The document is a single object. Keys are always written in the order shown
+//! below and every list is sorted, so two exports of the same grammar are
+//! byte for byte identical. `schema_version` is only increased when a field
+//! changes meaning or is removed; new fields may be added at any time and
+//! readers should ignore the ones they don't know.
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "start_symbol": "E",
+//!   "terminals": ["(", ")", "+", "id"],
+//!   "non_terminals": ["E", "T"],
+//!   "grammar": [
+//!     { "origin": "E", "productions": [["E", "+", "T"], ["T"]] }
+//!   ],
+//!   "augmented_rules": [
+//!     { "num": 0, "origin": "E'", "production": ["E"], "augmented": true },
+//!     { "num": 1, "origin": "E", "production": ["E", "+", "T"], "augmented": false,
+//!       "action": "$1 + $3", "ast": { "name": "Add", "drop": [], "lift": 1 } }
+//!   ],
+//!   "first_follow": [
+//!     { "symbol": "E", "first": ["(", "id"], "follow": ["$", ")", "+"] }
+//!   ],
+//!   "states": [
+//!     {
+//!       "id": 0,
+//!       "kernel": [{ "rule": 0, "dot": 0 }],
+//!       "closure": [{ "rule": 1, "dot": 0 }],
+//!       "transitions": [{ "symbol": "E", "target": 1 }]
+//!     }
+//!   ],
+//!   "table": [
+//!     {
+//!       "state": 0,
+//!       "actions": [{ "symbol": "id", "action": { "kind": "shift", "target": 5 } }],
+//!       "gotos": [{ "symbol": "E", "target": 1 }],
+//!       "conflicts": [
+//!         { "symbol": "+", "actions": [{ "kind": "shift", "target": 3 }, { "kind": "reduce", "rule": 2 }] }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! * Epsilon is written as the symbol `"' '"`, exactly as in the grammar files.
+//! * An item is a rule number of `augmented_rules` plus the position of the
+//!   pointer (`dot`), counted in symbols from the start of the production.
+//!   Epsilon productions have no symbols, so their only item has `dot` 0.
+//! * `closure` holds the items added to the kernel by the closure.
+//! * `action.kind` is one of `shift` (with `target`), `reduce` (with `rule`),
+//!   `accept` or `error`. A cell with a conflict has the `error` action and
+//!   lists every proposed action in `conflicts`.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::ast::AstShape;
+use crate::symbols::InternedGrammar;
+use crate::{get_firsts_follows, Action, SlrItem, SlrRow, SlrRule, SlrState};
+
+pub const SCHEMA_VERSION: usize = 1;
+
+/// Parsed JSON value. Objects keep their keys in the order they were written.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Writes this value with two space indentation.
+    pub fn to_pretty_string(&self) -> String {
+        let mut ret = String::new();
+        self.write_pretty(&mut ret, 0);
+        ret
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Array(items) if !items.is_empty() => {
+                // short arrays of plain values go in a single line
+                if items
+                    .iter()
+                    .all(|item| !matches!(item, Json::Array(_) | Json::Object(_)))
+                {
+                    out.push_str(&self.to_string());
+                    return;
+                }
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&"  ".repeat(indent + 1));
+                    item.write_pretty(out, indent + 1);
+                    if i + 1 < items.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Json::Object(entries) if !entries.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    out.push_str(&"  ".repeat(indent + 1));
+                    out.push_str(&quote(key));
+                    out.push_str(": ");
+                    value.write_pretty(out, indent + 1);
+                    if i + 1 < entries.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push('}');
+            }
+            _ => out.push_str(&self.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    /// Compact, single line representation.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", *n as i64)
+                } else {
+                    write!(f, "{}", n)
+                }
+            }
+            Json::String(s) => write!(f, "{}", quote(s)),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", quote(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn quote(txt: &str) -> String {
+    let mut ret = "\"".to_string();
+    for chr in txt.chars() {
+        match chr {
+            '"' => ret += "\\\"",
+            '\\' => ret += "\\\\",
+            '\n' => ret += "\\n",
+            '\r' => ret += "\\r",
+            '\t' => ret += "\\t",
+            c if (c as u32) < 0x20 => {
+                let _ = write!(ret, "\\u{:04x}", c as u32);
+            }
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// Parses a JSON document.
+pub fn parse(txt: &str) -> Result<Json, String> {
+    let chars: Vec<char> = txt.chars().collect();
+    let mut pos = 0;
+    let value = parse_value(&chars, &mut pos)?;
+    skip_whitespace(&chars, &mut pos);
+    if pos < chars.len() {
+        return Err(format!("Unexpected character '{}' at {}", chars[pos], pos));
+    }
+    Ok(value)
+}
+
+fn skip_whitespace(chars: &[char], pos: &mut usize) {
+    while *pos < chars.len() && chars[*pos].is_whitespace() {
+        *pos += 1;
+    }
+}
+
+fn expect_word(chars: &[char], pos: &mut usize, word: &str, value: Json) -> Result<Json, String> {
+    for expected in word.chars() {
+        if chars.get(*pos) != Some(&expected) {
+            return Err(format!("Invalid literal at {}", pos));
+        }
+        *pos += 1;
+    }
+    Ok(value)
+}
+
+fn parse_value(chars: &[char], pos: &mut usize) -> Result<Json, String> {
+    skip_whitespace(chars, pos);
+    match chars.get(*pos) {
+        None => Err("Unexpected end of JSON".to_string()),
+        Some('n') => expect_word(chars, pos, "null", Json::Null),
+        Some('t') => expect_word(chars, pos, "true", Json::Bool(true)),
+        Some('f') => expect_word(chars, pos, "false", Json::Bool(false)),
+        Some('"') => Ok(Json::String(parse_string(chars, pos)?)),
+        Some('[') => {
+            *pos += 1;
+            let mut items = Vec::new();
+            skip_whitespace(chars, pos);
+            if chars.get(*pos) == Some(&']') {
+                *pos += 1;
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars, pos)?);
+                skip_whitespace(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some(']') => {
+                        *pos += 1;
+                        return Ok(Json::Array(items));
+                    }
+                    _ => return Err(format!("Expected ',' or ']' at {}", pos)),
+                }
+            }
+        }
+        Some('{') => {
+            *pos += 1;
+            let mut entries = Vec::new();
+            skip_whitespace(chars, pos);
+            if chars.get(*pos) == Some(&'}') {
+                *pos += 1;
+                return Ok(Json::Object(entries));
+            }
+            loop {
+                skip_whitespace(chars, pos);
+                if chars.get(*pos) != Some(&'"') {
+                    return Err(format!("Expected object key at {}", pos));
+                }
+                let key = parse_string(chars, pos)?;
+                skip_whitespace(chars, pos);
+                if chars.get(*pos) != Some(&':') {
+                    return Err(format!("Expected ':' at {}", pos));
+                }
+                *pos += 1;
+                entries.push((key, parse_value(chars, pos)?));
+                skip_whitespace(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some('}') => {
+                        *pos += 1;
+                        return Ok(Json::Object(entries));
+                    }
+                    _ => return Err(format!("Expected ',' or '}}' at {}", pos)),
+                }
+            }
+        }
+        Some(c) if *c == '-' || c.is_ascii_digit() => {
+            let start = *pos;
+            while *pos < chars.len()
+                && (chars[*pos].is_ascii_digit() || "+-.eE".contains(chars[*pos]))
+            {
+                *pos += 1;
+            }
+            let number: String = chars[start..*pos].iter().collect();
+            number
+                .parse()
+                .map(Json::Number)
+                .map_err(|_| format!("Invalid number '{}'", number))
+        }
+        Some(c) => Err(format!("Unexpected character '{}' at {}", c, pos)),
+    }
+}
+
+fn parse_hex4(chars: &[char], pos: &mut usize) -> Result<u32, String> {
+    if *pos + 4 > chars.len() {
+        return Err("Unexpected end of JSON in \\u escape".to_string());
+    }
+    let hex: String = chars[*pos..*pos + 4].iter().collect();
+    *pos += 4;
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("Invalid \\u escape '{}'", hex))
+}
+
+fn parse_string(chars: &[char], pos: &mut usize) -> Result<String, String> {
+    // skip opening quote
+    *pos += 1;
+    let mut ret = String::new();
+    loop {
+        match chars.get(*pos) {
+            None => return Err("Unterminated string".to_string()),
+            Some('"') => {
+                *pos += 1;
+                return Ok(ret);
+            }
+            Some('\\') => {
+                *pos += 1;
+                let escaped = chars.get(*pos).copied();
+                *pos += 1;
+                match escaped {
+                    Some('"') => ret.push('"'),
+                    Some('\\') => ret.push('\\'),
+                    Some('/') => ret.push('/'),
+                    Some('b') => ret.push('\u{8}'),
+                    Some('f') => ret.push('\u{c}'),
+                    Some('n') => ret.push('\n'),
+                    Some('r') => ret.push('\r'),
+                    Some('t') => ret.push('\t'),
+                    Some('u') => {
+                        let mut code = parse_hex4(chars, pos)?;
+                        // surrogate pair; a high surrogate followed by anything but a low
+                        // one is left alone (and becomes U+FFFD) with the next escape
+                        if (0xD800..0xDC00).contains(&code)
+                            && chars.get(*pos) == Some(&'\\')
+                            && chars.get(*pos + 1) == Some(&'u')
+                        {
+                            let mut low_pos = *pos + 2;
+                            let low = parse_hex4(chars, &mut low_pos)?;
+                            if (0xDC00..0xE000).contains(&low) {
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                *pos = low_pos;
+                            }
+                        }
+                        ret.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                    }
+                    _ => return Err(format!("Invalid escape at {}", pos)),
+                }
+            }
+            Some(c) => {
+                ret.push(*c);
+                *pos += 1;
+            }
+        }
+    }
+}
+
+fn obj(entries: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+fn str_array<'a>(items: impl IntoIterator<Item = &'a String>) -> Json {
+    Json::Array(items.into_iter().map(|s| Json::String(s.clone())).collect())
+}
+
+fn sorted<'a>(items: impl IntoIterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut ret: Vec<&String> = items.into_iter().collect();
+    ret.sort();
+    ret
+}
+
+/// Cells of a table row (keyed by symbol id) with their symbol names, sorted by name.
+fn by_name<'a, T>(
+    cells: &'a HashMap<usize, T>,
+    grammar: &'a InternedGrammar,
+) -> Vec<(&'a String, &'a T)> {
+    let mut ret: Vec<(&String, &T)> = cells
+        .iter()
+        .map(|(symbol, value)| (grammar.name(*symbol), value))
+        .collect();
+    ret.sort_by(|a, b| a.0.cmp(b.0));
+    ret
+}
+
+fn ast_shape_to_json(shape: &AstShape) -> Json {
+    obj(vec![
+        ("name", shape.name.clone().map_or(Json::Null, Json::String)),
+        (
+            "drop",
+            Json::Array(
+                shape
+                    .dropped
+                    .iter()
+                    .map(|pos| Json::Number(*pos as f64))
+                    .collect(),
+            ),
+        ),
+        (
+            "lift",
+            shape
+                .lifted
+                .map_or(Json::Null, |pos| Json::Number(pos as f64)),
+        ),
+    ])
+}
+
+fn action_to_json(action: &Action) -> Json {
+    match action {
+        Action::S(s) => obj(vec![
+            ("kind", Json::String("shift".to_string())),
+            ("target", Json::Number(*s as f64)),
+        ]),
+        Action::R(r) => obj(vec![
+            ("kind", Json::String("reduce".to_string())),
+            ("rule", Json::Number(*r as f64)),
+        ]),
+        Action::Acc => obj(vec![("kind", Json::String("accept".to_string()))]),
+        Action::Err => obj(vec![("kind", Json::String("error".to_string()))]),
+    }
+}
+
+fn items_to_json(items: &BTreeSet<SlrItem>) -> Json {
+    Json::Array(
+        items
+            .iter()
+            .map(|item| {
+                obj(vec![
+                    ("rule", Json::Number(item.rule as f64)),
+                    ("dot", Json::Number(item.dot as f64)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Builds the JSON document described in the module documentation.
+pub fn analysis_to_json(
+    grammar: &HashMap<String, Vec<Vec<String>>>,
+    first_non_terminal: &str,
+    terminals: &HashSet<&String>,
+    non_terminals: &HashSet<&String>,
+    extended_grammar: &[SlrRule],
+    slr: &[SlrState],
+    table: &[SlrRow],
+) -> Json {
+    // grammar
+    let mut grammar_json = Vec::new();
+    for origin in sorted(grammar.keys()) {
+        let prods = grammar[origin].iter().map(str_array).collect();
+        grammar_json.push(obj(vec![
+            ("origin", Json::String(origin.clone())),
+            ("productions", Json::Array(prods)),
+        ]));
+    }
+
+    // augmented rules
+    let mut rules_json = Vec::new();
+    for rule in extended_grammar.iter() {
+        let mut fields = vec![
+            ("num", Json::Number(rule.num as f64)),
+            ("origin", Json::String(rule.origin.clone())),
+            ("production", str_array(rule.prod.iter())),
+            ("augmented", Json::Bool(rule.is_extended)),
+        ];
+        // only rules with a semantic action or AST annotations have these keys
+        if let Some(action) = &rule.action {
+            fields.push(("action", Json::String(action.clone())));
+        }
+        if !rule.ast.is_default() {
+            fields.push(("ast", ast_shape_to_json(&rule.ast)));
+        }
+        rules_json.push(obj(fields));
+    }
+
+    // firsts & follows
+    let interned = InternedGrammar::new(extended_grammar);
+    let mut first_follow_json = Vec::new();
+    for (nterm, firsts, follows) in get_firsts_follows(&interned) {
+        first_follow_json.push(obj(vec![
+            ("symbol", Json::String(nterm)),
+            ("first", str_array(firsts.iter())),
+            ("follow", str_array(follows.iter())),
+        ]));
+    }
+
+    // states
+    let mut states_json = Vec::new();
+    for (i, state) in slr.iter().enumerate() {
+        let mut transitions: Vec<(&String, usize)> = state
+            .transitions
+            .iter()
+            .map(|(symbol, target)| (interned.name(*symbol), *target))
+            .collect();
+        transitions.sort();
+        let transitions = transitions
+            .iter()
+            .map(|(symbol, target)| {
+                obj(vec![
+                    ("symbol", Json::String(symbol.to_string())),
+                    ("target", Json::Number(*target as f64)),
+                ])
+            })
+            .collect();
+        states_json.push(obj(vec![
+            ("id", Json::Number(i as f64)),
+            ("kernel", items_to_json(&state.kernel)),
+            ("closure", items_to_json(&state.extended_state)),
+            ("transitions", Json::Array(transitions)),
+        ]));
+    }
+
+    // table
+    let mut table_json = Vec::new();
+    for (i, row) in table.iter().enumerate() {
+        let actions = by_name(&row.actions, &interned)
+            .into_iter()
+            .map(|(symbol, action)| {
+                obj(vec![
+                    ("symbol", Json::String(symbol.clone())),
+                    ("action", action_to_json(action)),
+                ])
+            })
+            .collect();
+        let gotos = by_name(&row.gotos, &interned)
+            .into_iter()
+            .map(|(symbol, target)| {
+                obj(vec![
+                    ("symbol", Json::String(symbol.clone())),
+                    ("target", Json::Number(*target as f64)),
+                ])
+            })
+            .collect();
+        let conflicts = by_name(&row.conflicts, &interned)
+            .into_iter()
+            .map(|(symbol, actions)| {
+                obj(vec![
+                    ("symbol", Json::String(symbol.clone())),
+                    (
+                        "actions",
+                        Json::Array(actions.iter().map(action_to_json).collect()),
+                    ),
+                ])
+            })
+            .collect();
+        table_json.push(obj(vec![
+            ("state", Json::Number(i as f64)),
+            ("actions", Json::Array(actions)),
+            ("gotos", Json::Array(gotos)),
+            ("conflicts", Json::Array(conflicts)),
+        ]));
+    }
+
+    obj(vec![
+        ("schema_version", Json::Number(SCHEMA_VERSION as f64)),
+        ("start_symbol", Json::String(first_non_terminal.to_string())),
+        ("terminals", str_array(sorted(terminals.iter().copied()))),
+        (
+            "non_terminals",
+            str_array(sorted(non_terminals.iter().copied())),
+        ),
+        ("grammar", Json::Array(grammar_json)),
+        ("augmented_rules", Json::Array(rules_json)),
+        ("first_follow", Json::Array(first_follow_json)),
+        ("states", Json::Array(states_json)),
+        ("table", Json::Array(table_json)),
+    ])
+}
+
+/// Everything stored in an exported analysis, rebuilt into the structures used by `main`.
+pub struct ImportedAnalysis {
+    pub first_non_terminal: String,
+    pub terminals: Vec<String>,
+    pub non_terminals: Vec<String>,
+    pub grammar: HashMap<String, Vec<Vec<String>>>,
+    pub extended_grammar: Vec<SlrRule>,
+    /// Symbol ids of the augmented rules, the ones the states and the table are keyed by.
+    pub interned: InternedGrammar,
+    /// FIRST and FOLLOW of every non terminal, in the order of the document.
+    pub first_follow: Vec<(String, Vec<String>, Vec<String>)>,
+    pub slr: Vec<SlrState>,
+    pub table: Vec<SlrRow>,
+}
+
+fn field<'a>(value: &'a Json, key: &str) -> Result<&'a Json, String> {
+    value.get(key).ok_or(format!("Missing field '{}'", key))
+}
+
+fn array_field<'a>(value: &'a Json, key: &str) -> Result<&'a Vec<Json>, String> {
+    field(value, key)?
+        .as_array()
+        .ok_or(format!("Field '{}' should be an array", key))
+}
+
+fn str_field(value: &Json, key: &str) -> Result<String, String> {
+    field(value, key)?
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or(format!("Field '{}' should be a string", key))
+}
+
+fn usize_field(value: &Json, key: &str) -> Result<usize, String> {
+    field(value, key)?
+        .as_usize()
+        .ok_or(format!("Field '{}' should be a non negative integer", key))
+}
+
+fn strings(value: &Json) -> Result<Vec<String>, String> {
+    value
+        .as_array()
+        .ok_or("Expected an array of strings".to_string())?
+        .iter()
+        .map(|s| {
+            s.as_str()
+                .map(|s| s.to_string())
+                .ok_or("Expected a string".to_string())
+        })
+        .collect()
+}
+
+fn ast_shape_from_json(value: &Json) -> Result<AstShape, String> {
+    let dropped = array_field(value, "drop")?
+        .iter()
+        .map(|pos| pos.as_usize().ok_or("Expected a position".to_string()))
+        .collect::<Result<Vec<usize>, String>>()?;
+    Ok(AstShape {
+        name: value
+            .get("name")
+            .and_then(|name| name.as_str())
+            .map(|name| name.to_string()),
+        dropped,
+        lifted: value.get("lift").and_then(|pos| pos.as_usize()),
+    })
+}
+
+/// Field with the number of a state, which must be below `n_states`.
+fn state_field(value: &Json, key: &str, n_states: usize) -> Result<usize, String> {
+    let state = usize_field(value, key)?;
+    if state >= n_states {
+        return Err(format!(
+            "Target state {} is out of the {} states",
+            state, n_states
+        ));
+    }
+    Ok(state)
+}
+
+fn action_from_json(value: &Json, n_states: usize, n_rules: usize) -> Result<Action, String> {
+    match str_field(value, "kind")?.as_str() {
+        "shift" => Ok(Action::S(state_field(value, "target", n_states)?)),
+        "reduce" => {
+            let rule = usize_field(value, "rule")?;
+            if rule >= n_rules {
+                return Err(format!("Reduce by unknown rule {}", rule));
+            }
+            Ok(Action::R(rule))
+        }
+        "accept" => Ok(Action::Acc),
+        "error" => Ok(Action::Err),
+        kind => Err(format!("Unknown action kind '{}'", kind)),
+    }
+}
+
+fn items_from_json(
+    value: &Json,
+    extended_grammar: &[SlrRule],
+) -> Result<BTreeSet<SlrItem>, String> {
+    let mut ret = BTreeSet::new();
+    for item in value.as_array().ok_or("Items should be an array")? {
+        let rule = usize_field(item, "rule")?;
+        let dot = usize_field(item, "dot")?;
+        let base = extended_grammar
+            .get(rule)
+            .ok_or(format!("Item refers to unknown rule {}", rule))?;
+        if dot > base.body().len() {
+            return Err(format!("Item dot {} is out of rule {}", dot, rule));
+        }
+        ret.insert(SlrItem { rule, dot });
+    }
+    Ok(ret)
+}
+
+/// Reads a document written by `analysis_to_json`.
+pub fn analysis_from_json(txt: &str) -> Result<ImportedAnalysis, String> {
+    let doc = parse(txt)?;
+
+    let version = usize_field(&doc, "schema_version")?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Schema version {} is newer than the supported version {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+
+    let first_non_terminal = str_field(&doc, "start_symbol")?;
+    let terminals = strings(field(&doc, "terminals")?)?;
+    let non_terminals = strings(field(&doc, "non_terminals")?)?;
+
+    // grammar
+    let mut grammar = HashMap::new();
+    for entry in array_field(&doc, "grammar")? {
+        let mut prods = Vec::new();
+        for prod in array_field(entry, "productions")? {
+            prods.push(strings(prod)?);
+        }
+        grammar.insert(str_field(entry, "origin")?, prods);
+    }
+
+    // augmented rules
+    let mut extended_grammar = Vec::new();
+    for entry in array_field(&doc, "augmented_rules")? {
+        extended_grammar.push(SlrRule {
+            origin: str_field(entry, "origin")?,
+            prod: strings(field(entry, "production")?)?,
+            num: usize_field(entry, "num")?,
+            is_extended: field(entry, "augmented")?.as_bool().unwrap_or(false),
+            action: entry
+                .get("action")
+                .and_then(|action| action.as_str())
+                .map(|action| action.to_string()),
+            ast: match entry.get("ast") {
+                Some(shape) => ast_shape_from_json(shape)?,
+                None => AstShape::default(),
+            },
+        });
+    }
+    for (i, rule) in extended_grammar.iter().enumerate() {
+        if rule.num != i {
+            return Err(format!("Augmented rule {} is numbered {}", i, rule.num));
+        }
+    }
+    if extended_grammar.is_empty() {
+        return Err("There are no augmented rules".to_string());
+    }
+    let interned = InternedGrammar::new(&extended_grammar);
+    // symbol of a transition or a table cell, which must be a terminal for actions
+    let symbol_id = |value: &Json, terminal: Option<bool>| -> Result<usize, String> {
+        let name = str_field(value, "symbol")?;
+        match interned.id(&name) {
+            Some(id) if terminal.is_none_or(|terminal| terminal == interned.is_terminal(id)) => {
+                Ok(id)
+            }
+            Some(_) if terminal == Some(true) => Err(format!("'{}' is not a terminal", name)),
+            Some(_) => Err(format!("'{}' is not a non terminal", name)),
+            None => Err(format!("Unknown symbol '{}'", name)),
+        }
+    };
+
+    // firsts & follows
+    let mut first_follow = Vec::new();
+    for entry in array_field(&doc, "first_follow")? {
+        first_follow.push((
+            str_field(entry, "symbol")?,
+            strings(field(entry, "first")?)?,
+            strings(field(entry, "follow")?)?,
+        ));
+    }
+
+    // states
+    let states = array_field(&doc, "states")?;
+    let n_states = states.len();
+    let n_rules = extended_grammar.len();
+    let mut slr = Vec::new();
+    for entry in states {
+        let mut state = SlrState::new();
+        state.kernel = items_from_json(field(entry, "kernel")?, &extended_grammar)?;
+        state.extended_state = items_from_json(field(entry, "closure")?, &extended_grammar)?;
+        for transition in array_field(entry, "transitions")? {
+            state.transitions.insert((
+                symbol_id(transition, None)?,
+                state_field(transition, "target", n_states)?,
+            ));
+        }
+        slr.push(state);
+    }
+
+    // table
+    let mut table = Vec::new();
+    for entry in array_field(&doc, "table")? {
+        let mut row = SlrRow {
+            actions: HashMap::new(),
+            gotos: HashMap::new(),
+            conflicts: HashMap::new(),
+        };
+        for action in array_field(entry, "actions")? {
+            row.actions.insert(
+                symbol_id(action, Some(true))?,
+                action_from_json(field(action, "action")?, n_states, n_rules)?,
+            );
+        }
+        for goto in array_field(entry, "gotos")? {
+            row.gotos.insert(
+                symbol_id(goto, Some(false))?,
+                state_field(goto, "target", n_states)?,
+            );
+        }
+        for conflict in array_field(entry, "conflicts")? {
+            let actions = array_field(conflict, "actions")?
+                .iter()
+                .map(|action| action_from_json(action, n_states, n_rules))
+                .collect::<Result<Vec<Action>, String>>()?;
+            row.conflicts
+                .insert(symbol_id(conflict, Some(true))?, actions);
+        }
+        table.push(row);
+    }
+
+    Ok(ImportedAnalysis {
+        first_non_terminal,
+        terminals,
+        non_terminals,
+        grammar,
+        extended_grammar,
+        interned,
+        first_follow,
+        slr,
+        table,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(
+            parse(r#""\uD83D\uDE00""#),
+            Ok(Json::String("\u{1F600}".to_string()))
+        );
+        // a high surrogate without its low half doesn't take the next escape
+        assert_eq!(
+            parse(r#""\uD800\u0041""#),
+            Ok(Json::String("\u{FFFD}A".to_string()))
+        );
+        assert_eq!(
+            parse(r#""\uDC00x""#),
+            Ok(Json::String("\u{FFFD}x".to_string()))
+        );
+    }
+
+    /// Exports a grammar file with the analysis and symbol sets `main` builds.
+    fn export(txt: &str) -> (Json, HashMap<String, Vec<Vec<String>>>, Vec<SlrRow>) {
+        let mut grammar = HashMap::new();
+        let mut extras = HashMap::new();
+        let mut first_non_terminal = String::new();
+        crate::process_str(
+            txt.to_string(),
+            &mut grammar,
+            &mut extras,
+            &mut first_non_terminal,
+        );
+        let extended_grammar =
+            crate::build_extended_grammar(&grammar, &extras, &first_non_terminal);
+        let interned = InternedGrammar::new(&extended_grammar);
+        let (slr, table) = crate::slr_table_of(&interned);
+        let terminals: HashSet<&String> = (0..interned.eof).map(|id| interned.name(id)).collect();
+        let non_terminals: HashSet<&String> = grammar.keys().collect();
+        let doc = analysis_to_json(
+            &grammar,
+            &first_non_terminal,
+            &terminals,
+            &non_terminals,
+            &extended_grammar,
+            &slr,
+            &table,
+        );
+        (doc, grammar, table)
+    }
+
+    #[test]
+    fn round_trip_keeps_the_analysis() {
+        let (doc, grammar, table) = export(include_str!("../test_txts/input_ast.txt"));
+        let doc = doc.to_pretty_string();
+        let imported = analysis_from_json(&doc).unwrap();
+
+        assert_eq!(imported.first_non_terminal, "E");
+        assert_eq!(imported.grammar, grammar);
+        assert_eq!(imported.table.len(), table.len());
+        assert_eq!(
+            imported.first_follow[0],
+            (
+                "E".to_string(),
+                vec!["(".to_string(), "num".to_string()],
+                ["$", ")", "+", "-"].map(str::to_string).to_vec()
+            )
+        );
+
+        // exporting the imported analysis gives back the same document
+        let terminals: HashSet<&String> = imported.terminals.iter().collect();
+        let non_terminals: HashSet<&String> = imported.non_terminals.iter().collect();
+        let again = analysis_to_json(
+            &imported.grammar,
+            &imported.first_non_terminal,
+            &terminals,
+            &non_terminals,
+            &imported.extended_grammar,
+            &imported.slr,
+            &imported.table,
+        );
+        assert_eq!(again.to_pretty_string(), doc);
+    }
+
+    #[test]
+    fn epsilon_items_have_dot_zero() {
+        let (doc, _, _) = export("2\nA -> a A\nA -> ' '");
+        let closure = doc.get("states").unwrap().as_array().unwrap()[0]
+            .get("closure")
+            .unwrap()
+            .to_string();
+        assert_eq!(closure, r#"[{"rule": 1, "dot": 0}, {"rule": 2, "dot": 0}]"#);
+        assert!(analysis_from_json(&doc.to_pretty_string()).is_ok());
+    }
+
+    /// Value at `path` (object keys, or indices of arrays) inside a document.
+    fn at<'a>(doc: &'a mut Json, path: &[&str]) -> &'a mut Json {
+        path.iter().fold(doc, |value, key| match value {
+            Json::Object(entries) => &mut entries.iter_mut().find(|(k, _)| k == key).unwrap().1,
+            Json::Array(items) => &mut items[key.parse::<usize>().unwrap()],
+            _ => panic!("'{}' is not inside a plain value", key),
+        })
+    }
+
+    #[test]
+    fn rejects_unknown_symbols_and_states() {
+        let (doc, _, table) = export(include_str!("../test_txts/input2.txt"));
+        let mut broken = doc.clone();
+        *at(&mut broken, &["table", "0", "actions", "0", "symbol"]) =
+            Json::String("nope".to_string());
+        assert_eq!(
+            analysis_from_json(&broken.to_string()).err(),
+            Some("Unknown symbol 'nope'".to_string())
+        );
+
+        // shift, goto and transition targets must be imported states
+        let n_states = table.len();
+        for path in [
+            ["states", "0", "transitions", "0", "target"].as_slice(),
+            &["table", "0", "actions", "0", "action", "target"],
+            &["table", "0", "gotos", "0", "target"],
+        ] {
+            let mut broken = doc.clone();
+            *at(&mut broken, path) = Json::Number(n_states as f64);
+            assert_eq!(
+                analysis_from_json(&broken.to_string()).err(),
+                Some(format!(
+                    "Target state {} is out of the {} states",
+                    n_states, n_states
+                ))
+            );
+        }
+    }
+}
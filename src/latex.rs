@@ -0,0 +1,232 @@
+use std::fmt::Write;
+
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{get_firsts_follows, SlrItem, SlrRow, SlrRule, SlrState};
+
+/// Escapes the characters that have a special meaning in LaTeX text mode.
+pub fn escape_latex(txt: &str) -> String {
+    let mut ret = String::new();
+    for chr in txt.chars() {
+        match chr {
+            '\\' => ret += "\\textbackslash{}",
+            '{' => ret += "\\{",
+            '}' => ret += "\\}",
+            '$' => ret += "\\$",
+            '&' => ret += "\\&",
+            '#' => ret += "\\#",
+            '%' => ret += "\\%",
+            '_' => ret += "\\_",
+            '^' => ret += "\\textasciicircum{}",
+            '~' => ret += "\\textasciitilde{}",
+            '<' => ret += "\\textless{}",
+            '>' => ret += "\\textgreater{}",
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// A grammar symbol in typewriter font, with epsilon shown as `\varepsilon`.
+fn symbol(sym: &str) -> String {
+    if sym == "' '" {
+        "$\\varepsilon$".to_string()
+    } else {
+        format!("\\texttt{{{}}}", escape_latex(sym))
+    }
+}
+
+/// Right hand side of an item with `\bullet` at the position of the dot.
+fn item_body(item: &SlrItem, extended_grammar: &[SlrRule]) -> String {
+    let mut parts: Vec<String> = extended_grammar[item.rule]
+        .body()
+        .iter()
+        .map(|s| symbol(s))
+        .collect();
+    parts.insert(item.dot, "$\\bullet$".to_string());
+    parts.join("~")
+}
+
+/// `tabular` with a header row, the header separated by a double rule.
+pub fn table_to_latex(table: &TextTable) -> String {
+    let mut ret = String::new();
+    let _ = writeln!(
+        ret,
+        "\\begin{{tabular}}{{|{}|}}",
+        vec!["c"; table.headers.len()].join("|")
+    );
+    ret += "\\hline\n";
+    let headers: Vec<String> = table.headers.iter().map(|h| escape_latex(h)).collect();
+    let _ = writeln!(ret, "{} \\\\", headers.join(" & "));
+    ret += "\\hline\\hline\n";
+    for row in table.rows.iter() {
+        let cells: Vec<String> = row.iter().map(|c| escape_latex(c)).collect();
+        let _ = writeln!(ret, "{} \\\\", cells.join(" & "));
+        ret += "\\hline\n";
+    }
+    ret += "\\end{tabular}\n";
+    ret
+}
+
+/// Action/goto table, with the columns grouped under ACTION and GOTO.
+pub fn slr_table_to_latex(table: &[SlrRow], grammar: &InternedGrammar) -> String {
+    let model = TextTable::from_slr_table(table, grammar);
+    let n_actions = grammar.eof + 1;
+    let n_gotos = grammar.non_terminals().len();
+
+    let mut ret = String::new();
+    let _ = writeln!(
+        ret,
+        "\\begin{{tabular}}{{|c||{}||{}|}}",
+        vec!["c"; n_actions].join("|"),
+        vec!["c"; n_gotos].join("|")
+    );
+    ret += "\\hline\n";
+    let mut group = format!(" & \\multicolumn{{{}}}{{c||}}{{ACTION}}", n_actions);
+    if n_gotos > 0 {
+        let _ = write!(group, " & \\multicolumn{{{}}}{{c|}}{{GOTO}}", n_gotos);
+    }
+    let _ = writeln!(ret, "{} \\\\", group);
+    let _ = writeln!(ret, "\\cline{{2-{}}}", model.headers.len());
+    let headers: Vec<String> = model.headers.iter().map(|h| symbol(h)).collect();
+    let _ = writeln!(ret, "{} \\\\", headers.join(" & "));
+    ret += "\\hline\\hline\n";
+    for row in model.rows.iter() {
+        let cells: Vec<String> = row.iter().map(|c| escape_latex(c)).collect();
+        let _ = writeln!(ret, "{} \\\\", cells.join(" & "));
+        ret += "\\hline\n";
+    }
+    ret += "\\end{tabular}\n";
+    ret
+}
+
+/// Augmented grammar as an `align*` environment, one numbered rule per line.
+pub fn extended_grammar_to_latex(extended_grammar: &[SlrRule]) -> String {
+    let mut ret = "\\begin{align*}\n".to_string();
+    for (i, rule) in extended_grammar.iter().enumerate() {
+        let body: Vec<String> = rule.prod.iter().map(|s| symbol(s)).collect();
+        let _ = write!(
+            ret,
+            "  \\text{{{}.}} \\quad & \\text{{{}}} && \\rightarrow \\text{{{}}}",
+            rule.num,
+            symbol(&rule.origin),
+            body.join("~")
+        );
+        if i + 1 < extended_grammar.len() {
+            ret += " \\\\";
+        }
+        ret += "\n";
+    }
+    ret += "\\end{align*}\n";
+    ret
+}
+
+/// Item sets, each one as an `itemize` with the kernel first and the closure in grey.
+pub fn item_sets_to_latex(slr: &[SlrState], extended_grammar: &[SlrRule]) -> String {
+    let mut ret = String::new();
+    for (i, state) in slr.iter().enumerate() {
+        let _ = writeln!(ret, "\\paragraph{{$I_{{{}}}$}}", i);
+        ret += "\\begin{itemize}\n";
+        for item in state.kernel.iter() {
+            let _ = writeln!(
+                ret,
+                "  \\item {} $\\rightarrow$ {}",
+                symbol(&extended_grammar[item.rule].origin),
+                item_body(item, extended_grammar)
+            );
+        }
+        for item in state.extended_state.iter() {
+            let _ = writeln!(
+                ret,
+                "  \\item \\textcolor{{gray}}{{{} $\\rightarrow$ {}}}",
+                symbol(&extended_grammar[item.rule].origin),
+                item_body(item, extended_grammar)
+            );
+        }
+        ret += "\\end{itemize}\n";
+    }
+    ret
+}
+
+/// Complete LaTeX document with FIRST/FOLLOW, the augmented grammar, the item sets and the
+/// parse table.
+pub fn latex_document(
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    slr: &[SlrState],
+    table: &[SlrRow],
+) -> String {
+    let first_follow = TextTable {
+        headers: vec![
+            "non terminal".to_string(),
+            "FIRST".to_string(),
+            "FOLLOW".to_string(),
+        ],
+        rows: get_firsts_follows(grammar)
+            .into_iter()
+            .map(|(nterm, firsts, follows)| vec![nterm, firsts.join(", "), follows.join(", ")])
+            .collect(),
+    };
+
+    let mut ret = String::new();
+    ret += "\\documentclass{article}\n";
+    ret += "\\usepackage[utf8]{inputenc}\n";
+    ret += "\\usepackage{amsmath}\n";
+    ret += "\\usepackage{xcolor}\n";
+    ret += "\\begin{document}\n\n";
+
+    ret += "\\section*{FIRST \\& FOLLOW}\n";
+    ret += &table_to_latex(&first_follow);
+    ret += "\n\\section*{Augmented grammar}\n";
+    ret += &extended_grammar_to_latex(extended_grammar);
+    ret += "\n\\section*{Item sets}\n";
+    ret += &item_sets_to_latex(slr, extended_grammar);
+    ret += "\n\\section*{Parse table}\n";
+    ret += "{\\small\n";
+    ret += &slr_table_to_latex(table, grammar);
+    ret += "}\n";
+
+    ret += "\n\\end{document}\n";
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(escape_latex("a_b & $x% {y}"), "a\\_b \\& \\$x\\% \\{y\\}");
+        assert_eq!(symbol("' '"), "$\\varepsilon$");
+        assert_eq!(symbol("S'"), "\\texttt{S'}");
+    }
+
+    #[test]
+    fn table_groups_actions_and_gotos() {
+        let grammar = InternedGrammar::new(&crate::extended_grammar_of(include_str!(
+            "../test_txts/input2.txt"
+        )));
+        let (_, table) = crate::slr_table_of(&grammar);
+        let latex = slr_table_to_latex(&table, &grammar);
+        assert!(latex.starts_with("\\begin{tabular}{|c||c|c|c|c|c|c||c|c|c|}\n"));
+        assert!(latex.contains(
+            " & \\multicolumn{6}{c||}{ACTION} & \\multicolumn{3}{c|}{GOTO} \\\\\n\\cline{2-10}\n"
+        ));
+        assert!(latex.contains("\n3 &  &  &  & s7 &  & ACC &  &  &  \\\\\n"));
+    }
+
+    #[test]
+    fn item_sets_put_the_bullet_at_the_dot() {
+        let extended_grammar = crate::extended_grammar_of("2\nA -> a A\nA -> ' '");
+        let (slr, _) = crate::slr_table_of(&InternedGrammar::new(&extended_grammar));
+        let latex = item_sets_to_latex(&slr, &extended_grammar);
+        assert!(latex.starts_with(
+            "\\paragraph{$I_{0}$}\n\\begin{itemize}\n  \\item \\texttt{A'} $\\rightarrow$ $\\bullet$~\\texttt{A}\n"
+        ));
+        // the epsilon item has nothing but the dot
+        assert!(
+            latex.contains("  \\item \\textcolor{gray}{\\texttt{A} $\\rightarrow$ $\\bullet$}\n")
+        );
+        assert_eq!(latex.matches("\\paragraph").count(), slr.len());
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::dfa::Dfa;
+use crate::driver;
+use crate::regex::{parse_regex, Nfa, Regex};
+use crate::table::TextTable;
+
+/// Pattern of the `%lex` section: the terminal it produces, or `None` for a `%skip` pattern.
+pub struct LexRule {
+    pub terminal: Option<String>,
+    pub pattern: String,
+    pub regex: Regex,
+}
+
+/// Lexer defined in the grammar file after the rules, for example:
+///
+/// ```text
+/// %lex
+/// num [0-9]+
+/// id [a-zA-Z_][a-zA-Z0-9_]*
+/// + "+"
+/// %skip [ \t\n]+
+/// %skip //[^\n]*
+/// ```
+///
+/// Each line is a terminal and its regular expression (see `regex::parse_regex`). The input
+/// is split taking the longest match at each position; if several patterns match the same
+/// length the one written first wins. The patterns are compiled into one NFA and then into
+/// a minimal DFA, which does the matching.
+pub struct Lexer {
+    pub rules: Vec<LexRule>,
+    pub nfa: Nfa,
+    pub dfa: Dfa,
+}
+
+type TokensWithPositions = (Vec<(String, String)>, Vec<(usize, usize)>);
+
+/// Error in the input: nothing matches at `line`:`column` (both from 1).
+#[derive(Debug)]
+pub struct LexError {
+    pub line: usize,
+    pub column: usize,
+    pub found: char,
+}
+
+impl Lexer {
+    /// Reads the `%lex` section of a grammar file, if it has one. It starts at a line with
+    /// just `%lex` and goes to the end of the file; empty lines are ignored.
+    pub fn from_grammar_file(txt: &str) -> Result<Option<Lexer>, String> {
+        let mut lines = txt
+            .lines()
+            .enumerate()
+            .skip_while(|(_, line)| line.trim() != "%lex");
+        if lines.next().is_none() {
+            return Ok(None);
+        }
+
+        let mut rules = Vec::new();
+        for (num, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, pattern) = match line.split_once(char::is_whitespace) {
+                Some((name, pattern)) => (name, pattern.trim()),
+                None => return Err(format!("line {}: missing pattern for {}", num + 1, line)),
+            };
+            let regex = parse_regex(pattern).map_err(|err| format!("line {}: {}", num + 1, err))?;
+            rules.push(LexRule {
+                terminal: if name == "%skip" {
+                    None
+                } else {
+                    Some(name.to_string())
+                },
+                pattern: pattern.to_string(),
+                regex,
+            });
+        }
+        let regexes: Vec<Regex> = rules.iter().map(|rule| rule.regex.clone()).collect();
+        let nfa = Nfa::from_regexes(&regexes);
+        let dfa = Dfa::from_nfa(&nfa).minimize();
+        Ok(Some(Lexer { rules, nfa, dfa }))
+    }
+
+    /// Terminals of the grammar that no pattern produces, besides `error`.
+    pub fn missing_terminals(&self, terminals: &HashSet<&String>) -> Vec<String> {
+        let defined: HashSet<&String> = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.terminal.as_ref())
+            .collect();
+        let mut ret: Vec<String> = terminals
+            .iter()
+            .filter(|terminal| {
+                !defined.contains(*terminal) && terminal.as_str() != driver::ERROR_TERMINAL
+            })
+            .map(|terminal| terminal.to_string())
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    /// Splits `input` into tokens (terminal and lexeme), dropping the `%skip` matches. Also
+    /// returns the line and column where each token starts, and then where the input ends.
+    pub fn tokenize(&self, input: &str) -> Result<TokensWithPositions, LexError> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut ret = Vec::new();
+        let mut positions = Vec::new();
+        let mut pos = 0;
+        let (mut line, mut column) = (1, 1);
+        while pos < chars.len() {
+            let (len, rule) = match self.dfa.longest_match(&chars[pos..]) {
+                Some(found) => found,
+                None => {
+                    return Err(LexError {
+                        line,
+                        column,
+                        found: chars[pos],
+                    })
+                }
+            };
+            let lexeme: String = chars[pos..pos + len].iter().collect();
+            if let Some(terminal) = &self.rules[rule].terminal {
+                ret.push((terminal.clone(), lexeme.clone()));
+                positions.push((line, column));
+            }
+            for chr in lexeme.chars() {
+                if chr == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+            }
+            pos += len;
+        }
+        positions.push((line, column));
+        Ok((ret, positions))
+    }
+
+    /// Name of what each pattern accepts, for the automata tables.
+    pub fn accept_names(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                rule.terminal
+                    .clone()
+                    .unwrap_or_else(|| "(skip)".to_string())
+            })
+            .collect()
+    }
+
+    /// Patterns in priority order.
+    pub fn rules_table(&self) -> TextTable {
+        TextTable {
+            headers: vec![
+                "priority".to_string(),
+                "terminal".to_string(),
+                "pattern".to_string(),
+            ],
+            rows: self
+                .rules
+                .iter()
+                .enumerate()
+                .map(|(num, rule)| {
+                    vec![
+                        num.to_string(),
+                        rule.terminal
+                            .clone()
+                            .unwrap_or_else(|| "(skip)".to_string()),
+                        rule.pattern.clone(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unexpected {:?} at line {}, column {}",
+            self.found, self.line, self.column
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexer(txt: &str) -> Lexer {
+        Lexer::from_grammar_file(txt).ok().flatten().unwrap()
+    }
+
+    fn terminals(tokens: &[(String, String)]) -> Vec<&str> {
+        tokens
+            .iter()
+            .map(|(terminal, _)| terminal.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_with_longest_match_and_skips() {
+        let lexer = lexer(include_str!("../test_txts/input_lex.txt"));
+        let (tokens, positions) = lexer.tokenize("3.5 + 2*(4) // two\n+ 10").unwrap();
+        assert_eq!(
+            terminals(&tokens),
+            ["num", "+", "num", "*", "(", "num", ")", "+", "num"]
+        );
+        assert_eq!(tokens[0].1, "3.5");
+        assert_eq!(tokens[8].1, "10");
+        assert_eq!(positions[0], (1, 1));
+        assert_eq!(positions[7], (2, 1));
+        // the end of the input
+        assert_eq!(positions[9], (2, 5));
+    }
+
+    #[test]
+    fn ties_go_to_the_first_pattern() {
+        let lexer = lexer("1\nS -> if id\n%lex\nif \"if\"\nid [a-z]+\n%skip \" \"+\n");
+        let (tokens, _) = lexer.tokenize("if iffy").unwrap();
+        assert_eq!(terminals(&tokens), ["if", "id"]);
+
+        let err = lexer.tokenize("if  i9").err().unwrap();
+        assert_eq!((err.line, err.column, err.found), (1, 6, '9'));
+    }
+
+    #[test]
+    fn reads_the_lex_section() {
+        assert!(matches!(Lexer::from_grammar_file("1\nS -> a\n"), Ok(None)));
+        assert_eq!(
+            Lexer::from_grammar_file("1\nS -> a\n%lex\na\n")
+                .err()
+                .unwrap(),
+            "line 4: missing pattern for a"
+        );
+
+        let lexer = lexer(include_str!("../test_txts/input_error.txt"));
+        assert_eq!(lexer.accept_names(), ["id", "+", ";", "(skip)"]);
+        let names = [
+            "id".to_string(),
+            ";".to_string(),
+            "error".to_string(),
+            "-".to_string(),
+        ];
+        let grammar_terminals: HashSet<&String> = names.iter().collect();
+        assert_eq!(lexer.missing_terminals(&grammar_terminals), ["-"]);
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt::Write;
+
+use crate::codegen::{camel_case, comment, symbol_words_by_case, unique_idents_with};
+use crate::symbols::{BitSet, InternedGrammar};
+use crate::table::TextTable;
+use crate::SlrRule;
+
+/// Two rules of the same non terminal that an LL(1) parser can't choose between, because
+/// their predict sets share `tokens`.
+pub struct LlConflict {
+    pub non_terminal: String,
+    pub rules: (usize, usize),
+    pub tokens: Vec<String>,
+}
+
+/// Terminals that select each rule: FIRST of its body, plus FOLLOW of its origin when the
+/// body can derive epsilon.
+pub fn predict_sets(grammar: &InternedGrammar) -> Vec<BitSet> {
+    grammar
+        .rules
+        .iter()
+        .map(|rule| {
+            let (mut predict, nullable) = grammar.first_of_sequence(&rule.body);
+            if nullable {
+                predict.union_with(&grammar.follows[rule.origin]);
+            }
+            predict
+        })
+        .collect()
+}
+
+/// Every pair of rules with overlapping predict sets. The grammar is LL(1) if there are none.
+pub fn ll1_conflicts(grammar: &InternedGrammar) -> Vec<LlConflict> {
+    let predict = predict_sets(grammar);
+    let mut ret = Vec::new();
+    for nterm in grammar.non_terminals() {
+        let rules = &grammar.rules_by_origin[nterm];
+        for (i, first) in rules.iter().enumerate() {
+            for second in rules[i + 1..].iter() {
+                let overlap = predict[*first].intersection(&predict[*second]);
+                if !overlap.is_empty() {
+                    ret.push(LlConflict {
+                        non_terminal: grammar.name(nterm).clone(),
+                        rules: (*first, *second),
+                        tokens: grammar.names_of(&overlap),
+                    });
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// LL(1) table: one row per non terminal, one column per terminal and `$`, with the number
+/// of the rule to expand (several numbers where there is a conflict).
+pub fn ll1_table(grammar: &InternedGrammar) -> TextTable {
+    let predict = predict_sets(grammar);
+    let mut headers = vec!["non terminal".to_string()];
+    headers.extend((0..=grammar.eof).map(|id| grammar.name(id).clone()));
+
+    let mut rows = Vec::new();
+    for nterm in grammar.non_terminals() {
+        let mut row = vec![grammar.name(nterm).clone()];
+        for term in 0..=grammar.eof {
+            let cell: Vec<String> = grammar.rules_by_origin[nterm]
+                .iter()
+                .filter(|rule| predict[**rule].iter().any(|t| t == term))
+                .map(|rule| rule.to_string())
+                .collect();
+            row.push(cell.join(", "));
+        }
+        rows.push(row);
+    }
+    TextTable { headers, rows }
+}
+
+pub fn conflict_to_string(conflict: &LlConflict, extended_grammar: &[SlrRule]) -> String {
+    format!(
+        "{}: rules {} and {} are both predicted by {}",
+        conflict.non_terminal,
+        extended_grammar[conflict.rules.0].to_string().trim_end(),
+        extended_grammar[conflict.rules.1].to_string().trim_end(),
+        conflict.tokens.join(", ")
+    )
+}
+
+/// Recursive descent parser in Rust for an LL(1) grammar: one function per non terminal with
+/// a `match` on the lookahead, whose arms are the predict sets of its rules. The parser
+/// builds a `Tree` with the rule number (of the augmented grammar) of every node.
+pub fn descent_parser(grammar: &InternedGrammar, extended_grammar: &[SlrRule]) -> String {
+    let predict = predict_sets(grammar);
+    let terminal_names: Vec<String> = (0..grammar.eof)
+        .map(|id| grammar.name(id).clone())
+        .collect();
+    let mut tokens =
+        unique_idents_with(&terminal_names, &["Eof"], symbol_words_by_case, camel_case);
+    tokens.push("Eof".to_string());
+    let non_terminal_names: Vec<String> = grammar
+        .non_terminals()
+        .map(|id| grammar.name(id).clone())
+        .collect();
+    let non_terminals =
+        unique_idents_with(&non_terminal_names, &[], symbol_words_by_case, camel_case);
+    let functions = unique_idents_with(&non_terminal_names, &[], symbol_words_by_case, |words| {
+        format!("parse_{}", words.join("_"))
+    });
+    let first_nterm = grammar.eof + 1;
+    let start = grammar.rules[0].body[0] - first_nterm;
+    let token_of = |id: usize| format!("Token::{}", tokens[id]);
+
+    let mut ret = String::new();
+    ret += "// Recursive descent parser generated by proyecto_compiladores, do not edit.\n\n";
+    ret += "#![allow(dead_code)]\n\n";
+
+    ret += "/// Terminals of the grammar.\n";
+    ret += "#[derive(Clone, Copy, PartialEq, Eq, Debug)]\n";
+    ret += "pub enum Token {\n";
+    for (id, ident) in tokens.iter().enumerate() {
+        let _ = writeln!(
+            ret,
+            "    /// `{}`\n    {},",
+            comment(grammar.name(id)),
+            ident
+        );
+    }
+    ret += "}\n\n";
+    ret += "/// Non terminals of the grammar.\n";
+    ret += "#[derive(Clone, Copy, PartialEq, Eq, Debug)]\n";
+    ret += "pub enum NonTerminal {\n";
+    for (idx, ident) in non_terminals.iter().enumerate() {
+        let name = grammar.name(first_nterm + idx);
+        let _ = writeln!(ret, "    /// `{}`\n    {},", comment(name), ident);
+    }
+    ret += "}\n\n";
+    ret += PARSER_STRUCT;
+
+    ret += "impl<I: Iterator<Item = Token>> Parser<I> {\n";
+    for nterm in grammar.non_terminals() {
+        let idx = nterm - first_nterm;
+        let rules = &grammar.rules_by_origin[nterm];
+        if idx > 0 {
+            ret += "\n";
+        }
+        for rule in rules.iter() {
+            let _ = writeln!(
+                ret,
+                "    /// {}",
+                comment(extended_grammar[*rule].to_string().trim_end())
+            );
+        }
+        let _ = writeln!(
+            ret,
+            "    fn {}(&mut self) -> Result<Tree, ParseError> {{",
+            functions[idx]
+        );
+        ret += "        match self.lookahead {\n";
+        let mut expected = BitSet::new(grammar.names.len());
+        for rule in rules.iter() {
+            expected.union_with(&predict[*rule]);
+            let arm: Vec<String> = predict[*rule].iter().map(token_of).collect();
+            if arm.is_empty() {
+                continue;
+            }
+            let children: Vec<String> = grammar.rules[*rule]
+                .body
+                .iter()
+                .map(|symbol| {
+                    if grammar.is_terminal(*symbol) {
+                        format!("self.expect({})?", token_of(*symbol))
+                    } else {
+                        format!("self.{}()?", functions[*symbol - first_nterm])
+                    }
+                })
+                .collect();
+            let _ = writeln!(ret, "            {} => {{", arm.join(" | "));
+            if children.is_empty() {
+                let _ = writeln!(ret, "                // epsilon, chosen on FOLLOW");
+                let _ = writeln!(
+                    ret,
+                    "                Ok(Tree::Node(NonTerminal::{}, {}, Vec::new()))",
+                    non_terminals[idx], rule
+                );
+            } else {
+                let _ = writeln!(
+                    ret,
+                    "                let children = vec![{}];",
+                    children.join(", ")
+                );
+                let _ = writeln!(
+                    ret,
+                    "                Ok(Tree::Node(NonTerminal::{}, {}, children))",
+                    non_terminals[idx], rule
+                );
+            }
+            ret += "            }\n";
+        }
+        let expected: Vec<String> = expected.iter().map(token_of).collect();
+        let _ = writeln!(
+            ret,
+            "            _ => Err(self.error(&[{}])),",
+            expected.join(", ")
+        );
+        ret += "        }\n    }\n";
+    }
+    ret += "}\n\n";
+
+    ret += "/// Parses `tokens` (without `Token::Eof`) and returns the parse tree.\n";
+    ret += "pub fn parse(tokens: impl IntoIterator<Item = Token>) -> Result<Tree, ParseError> {\n";
+    ret += "    let mut parser = Parser::new(tokens.into_iter());\n";
+    let _ = writeln!(ret, "    let tree = parser.{}()?;", functions[start]);
+    ret += "    if parser.lookahead != Token::Eof {\n";
+    ret += "        return Err(parser.error(&[Token::Eof]));\n";
+    ret += "    }\n";
+    ret += "    Ok(tree)\n";
+    ret += "}\n";
+    ret
+}
+
+const PARSER_STRUCT: &str =
+    "/// Parse tree. Nodes have the rule number of the augmented grammar they were expanded with.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Tree {
+    Token(Token),
+    Node(NonTerminal, usize, Vec<Tree>),
+}
+
+/// Position (number of tokens read before it) of the token that couldn't be parsed, and the
+/// tokens that were expected there.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub position: usize,
+    pub found: Token,
+    pub expected: Vec<Token>,
+}
+
+struct Parser<I: Iterator<Item = Token>> {
+    tokens: I,
+    lookahead: Token,
+    position: usize,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    fn new(mut tokens: I) -> Parser<I> {
+        let lookahead = tokens.next().unwrap_or(Token::Eof);
+        Parser {
+            tokens,
+            lookahead,
+            position: 0,
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<Tree, ParseError> {
+        if self.lookahead != token {
+            return Err(self.error(&[token]));
+        }
+        self.lookahead = self.tokens.next().unwrap_or(Token::Eof);
+        self.position += 1;
+        Ok(Tree::Token(token))
+    }
+
+    fn error(&self, expected: &[Token]) -> ParseError {
+        ParseError {
+            position: self.position,
+            found: self.lookahead,
+            expected: expected.to_vec(),
+        }
+    }
+}
+
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Number of the rule `origin -> body` in the augmented grammar.
+    fn rule(extended_grammar: &[SlrRule], origin: &str, body: &[&str]) -> usize {
+        extended_grammar
+            .iter()
+            .position(|rule| rule.origin == origin && rule.body() == body)
+            .unwrap()
+    }
+
+    #[test]
+    fn predict_sets_use_follow_for_epsilon_rules() {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input1.txt"));
+        let grammar = &InternedGrammar::new(&extended_grammar);
+        let predict = predict_sets(grammar);
+        let plus = rule(&extended_grammar, "EPrime", &["+", "T", "EPrime"]);
+        let epsilon = rule(&extended_grammar, "EPrime", &[]);
+        assert_eq!(grammar.names_of(&predict[plus]), ["+"]);
+        assert_eq!(grammar.names_of(&predict[epsilon]), [")", "$"]);
+        assert!(ll1_conflicts(grammar).is_empty());
+    }
+
+    #[test]
+    fn left_recursion_is_an_ll1_conflict() {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input2.txt"));
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let conflicts = ll1_conflicts(&grammar);
+        let found: Vec<(&str, Vec<String>)> = conflicts
+            .iter()
+            .map(|c| (c.non_terminal.as_str(), c.tokens.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("E", vec!["(".to_string(), "id".to_string()]),
+                ("T", vec!["(".to_string(), "id".to_string()]),
+            ]
+        );
+        let e_plus = rule(&extended_grammar, "E", &["E", "+", "T"]);
+        let e_t = rule(&extended_grammar, "E", &["T"]);
+        assert_eq!(conflicts[0].rules, (e_plus, e_t));
+        let table = ll1_table(&grammar);
+        assert_eq!(table.rows[0][5], format!("{}, {}", e_plus, e_t));
+    }
+
+    #[test]
+    fn descent_parser_has_a_function_per_non_terminal() {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input1.txt"));
+        let code = descent_parser(&InternedGrammar::new(&extended_grammar), &extended_grammar);
+        for function in [
+            "parse_e",
+            "parse_e_prime",
+            "parse_f",
+            "parse_t",
+            "parse_t_prime",
+        ] {
+            assert!(code.contains(&format!("    fn {}(&mut self)", function)));
+        }
+        assert!(code.contains("            Token::RightParen | Token::Eof => {\n                // epsilon, chosen on FOLLOW\n"));
+        assert!(code.contains("self.expect(Token::Plus)?, self.parse_t()?, self.parse_e_prime()?"));
+    }
+}
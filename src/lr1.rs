@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::symbols::{BitSet, InternedGrammar};
+use crate::{Action, SlrItem, SlrRule};
+
+/// LR(1) state: every item (kernel and closure) with its set of lookahead terminals.
+#[derive(Clone)]
+pub struct Lr1State {
+    pub items: BTreeMap<SlrItem, BitSet>,
+    /// Symbol id to the next state.
+    pub transitions: BTreeMap<usize, usize>,
+    /// For LALR(1) states, the canonical LR(1) states that were merged into this one.
+    pub merged_from: Vec<usize>,
+}
+
+impl Lr1State {
+    /// Items with the dot after some symbol, plus the initial item.
+    pub fn kernel(&self) -> impl Iterator<Item = (&SlrItem, &BitSet)> {
+        self.items
+            .iter()
+            .filter(|(item, _)| item.dot > 0 || item.rule == 0)
+    }
+
+    /// Item written as `1. E -> E • + T, {+, $}`.
+    pub fn item_string(
+        item: &SlrItem,
+        lookaheads: &BitSet,
+        grammar: &InternedGrammar,
+        extended_grammar: &[SlrRule],
+    ) -> String {
+        format!(
+            "{}, {{{}}}",
+            item.to_string(extended_grammar),
+            grammar.names_of(lookaheads).join(", ")
+        )
+    }
+}
+
+/// Adds the closure items, propagating the lookaheads: an item `A -> x • B y` with
+/// lookaheads L gives `B -> • z` the lookaheads FIRST(y), plus L when `y` is nullable.
+fn closure(
+    grammar: &InternedGrammar,
+    kernel: BTreeMap<SlrItem, BitSet>,
+) -> BTreeMap<SlrItem, BitSet> {
+    let mut items = kernel;
+    let mut pending: Vec<SlrItem> = items.keys().copied().collect();
+    while let Some(item) = pending.pop() {
+        let body = &grammar.rules[item.rule].body;
+        let symbol = match body.get(item.dot) {
+            Some(symbol) if !grammar.is_terminal(*symbol) => *symbol,
+            _ => continue,
+        };
+        let (mut lookaheads, nullable) = grammar.first_of_sequence(&body[item.dot + 1..]);
+        if nullable {
+            lookaheads.union_with(&items[&item]);
+        }
+        for rule in grammar.rules_by_origin[symbol].iter() {
+            let new_item = SlrItem::new(*rule);
+            let is_new = !items.contains_key(&new_item);
+            let entry = items
+                .entry(new_item)
+                .or_insert_with(|| BitSet::new(grammar.names.len()));
+            if entry.union_with(&lookaheads) || is_new {
+                pending.push(new_item);
+            }
+        }
+    }
+    items
+}
+
+/// Canonical LR(1) automaton. States are numbered in the order they are found, reading
+/// the symbols of each state in id order, as `build_slr` does.
+pub fn build_lr1(grammar: &InternedGrammar) -> Vec<Lr1State> {
+    let mut eof = BitSet::new(grammar.names.len());
+    eof.insert(grammar.eof);
+    let start = closure(grammar, BTreeMap::from([(SlrItem::new(0), eof)]));
+
+    let mut states = vec![Lr1State {
+        items: start,
+        transitions: BTreeMap::new(),
+        merged_from: Vec::new(),
+    }];
+    let mut index: HashMap<Vec<(SlrItem, BitSet)>, usize> = HashMap::new();
+    index.insert(kernel_key(&states[0]), 0);
+
+    let mut current = 0;
+    while current < states.len() {
+        let symbols: BTreeSet<usize> = states[current]
+            .items
+            .keys()
+            .filter_map(|item| item.get_reading_symbol(grammar))
+            .collect();
+        for symbol in symbols {
+            let kernel: BTreeMap<SlrItem, BitSet> = states[current]
+                .items
+                .iter()
+                .filter(|(item, _)| item.get_reading_symbol(grammar) == Some(symbol))
+                .map(|(item, lookaheads)| (item.advance(), lookaheads.clone()))
+                .collect();
+            let key: Vec<(SlrItem, BitSet)> = kernel.clone().into_iter().collect();
+            let target = match index.get(&key) {
+                Some(target) => *target,
+                None => {
+                    index.insert(key, states.len());
+                    states.push(Lr1State {
+                        items: closure(grammar, kernel),
+                        transitions: BTreeMap::new(),
+                        merged_from: Vec::new(),
+                    });
+                    states.len() - 1
+                }
+            };
+            states[current].transitions.insert(symbol, target);
+        }
+        current += 1;
+    }
+    states
+}
+
+fn kernel_key(state: &Lr1State) -> Vec<(SlrItem, BitSet)> {
+    state
+        .kernel()
+        .map(|(item, lookaheads)| (*item, lookaheads.clone()))
+        .collect()
+}
+
+/// LALR(1) automaton: the LR(1) states with the same items (the same LR(0) core) merged
+/// into one, with the union of their lookaheads. Merged states are numbered by their first
+/// LR(1) state.
+pub fn merge_lalr(states: &[Lr1State]) -> Vec<Lr1State> {
+    let mut group_of: Vec<usize> = Vec::with_capacity(states.len());
+    let mut groups: HashMap<Vec<SlrItem>, usize> = HashMap::new();
+    let mut merged: Vec<Lr1State> = Vec::new();
+    for (num, state) in states.iter().enumerate() {
+        let core: Vec<SlrItem> = state.items.keys().copied().collect();
+        match groups.get(&core) {
+            Some(group) => {
+                for (item, lookaheads) in state.items.iter() {
+                    merged[*group]
+                        .items
+                        .get_mut(item)
+                        .unwrap()
+                        .union_with(lookaheads);
+                }
+                merged[*group].merged_from.push(num);
+                group_of.push(*group);
+            }
+            None => {
+                groups.insert(core, merged.len());
+                group_of.push(merged.len());
+                merged.push(Lr1State {
+                    items: state.items.clone(),
+                    transitions: BTreeMap::new(),
+                    merged_from: vec![num],
+                });
+            }
+        }
+    }
+    for (num, state) in states.iter().enumerate() {
+        for (symbol, target) in state.transitions.iter() {
+            merged[group_of[num]]
+                .transitions
+                .insert(*symbol, group_of[*target]);
+        }
+    }
+    merged
+}
+
+/// Actions of a state for every terminal that has some: shifts on its transitions and
+/// reductions (accept for rule 0) on the lookaheads of its complete items.
+pub fn state_actions(grammar: &InternedGrammar, state: &Lr1State) -> BTreeMap<usize, Vec<Action>> {
+    let mut ret: BTreeMap<usize, Vec<Action>> = BTreeMap::new();
+    for (symbol, target) in state.transitions.iter() {
+        if grammar.is_terminal(*symbol) {
+            ret.entry(*symbol).or_default().push(Action::S(*target));
+        }
+    }
+    for (item, lookaheads) in state.items.iter() {
+        if !item.is_complete(grammar) {
+            continue;
+        }
+        let action = if item.rule == 0 {
+            Action::Acc
+        } else {
+            Action::R(item.rule)
+        };
+        for terminal in lookaheads.iter() {
+            let actions = ret.entry(terminal).or_default();
+            if !actions.contains(&action) {
+                actions.push(action.clone());
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The grammar of assignments that is LALR(1) but not SLR(1).
+    fn assignments() -> (Vec<SlrRule>, InternedGrammar) {
+        let extended_grammar =
+            crate::extended_grammar_of("5\nS -> L = R\nS -> R\nL -> * R\nL -> id\nR -> L\n");
+        let grammar = InternedGrammar::new(&extended_grammar);
+        (extended_grammar, grammar)
+    }
+
+    /// Items of a state as `item_string` prints them.
+    fn items(state: &Lr1State, grammar: &InternedGrammar, rules: &[SlrRule]) -> Vec<String> {
+        state
+            .items
+            .iter()
+            .map(|(item, lookaheads)| Lr1State::item_string(item, lookaheads, grammar, rules))
+            .collect()
+    }
+
+    #[test]
+    fn closure_items_get_the_first_of_what_follows() {
+        let (extended_grammar, grammar) = assignments();
+        let states = build_lr1(&grammar);
+        assert_eq!(
+            items(&states[0], &grammar, &extended_grammar),
+            [
+                "0. S' -> • S, {$}",
+                "1. S -> • L = R, {$}",
+                "2. S -> • R, {$}",
+                "3. L -> • * R, {=, $}",
+                "4. L -> • id, {=, $}",
+                "5. R -> • L, {$}",
+            ]
+        );
+        assert_eq!(states[0].kernel().count(), 1);
+    }
+
+    #[test]
+    fn lalr_merges_the_states_with_the_same_core() {
+        let (_, grammar) = assignments();
+        let states = build_lr1(&grammar);
+        let merged = merge_lalr(&states);
+        assert_eq!((states.len(), merged.len()), (14, 10));
+        let groups: Vec<&[usize]> = merged
+            .iter()
+            .map(|state| state.merged_from.as_slice())
+            .filter(|from| from.len() > 1)
+            .collect();
+        assert_eq!(groups, [[1, 9], [2, 10], [6, 11], [7, 13]]);
+        for state in merged.iter() {
+            for num in state.merged_from.iter() {
+                assert!(states[*num].items.keys().eq(state.items.keys()));
+            }
+        }
+    }
+
+    #[test]
+    fn actions_reduce_only_on_the_lookaheads() {
+        let (_, grammar) = assignments();
+        let states = build_lr1(&grammar);
+        let named = |state: &Lr1State| -> Vec<(String, Vec<Action>)> {
+            state_actions(&grammar, state)
+                .into_iter()
+                .map(|(terminal, actions)| (grammar.name(terminal).to_string(), actions))
+                .collect()
+        };
+        let shift = |terminal: &str, action: Action| (terminal.to_string(), vec![action]);
+        // `S -> L • = R` and `R -> L •`, a conflict on `=` for SLR(1)
+        assert_eq!(
+            named(&states[3]),
+            [shift("=", Action::S(8)), shift("$", Action::R(5))]
+        );
+        assert_eq!(named(&states[5]), [shift("$", Action::Acc)]);
+
+        // merging `R -> L •` from the two sides of `=` reduces on both, still without conflicts
+        let merged = merge_lalr(&states);
+        let reduce_r = merged
+            .iter()
+            .find(|state| state.merged_from == [6, 11])
+            .unwrap();
+        assert_eq!(
+            named(reduce_r),
+            [shift("=", Action::R(5)), shift("$", Action::R(5))]
+        );
+        assert!(merged.iter().all(|state| state_actions(&grammar, state)
+            .values()
+            .all(|a| a.len() == 1)));
+    }
+}
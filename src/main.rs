@@ -1,174 +1,207 @@
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::io;
 
+mod ambiguity;
+mod ast;
+mod classify;
+mod codegen;
+mod codegen_c;
+mod compress;
+mod counterexample;
+mod dfa;
+mod diagnostics;
+mod driver;
+mod earley;
+mod generate;
+mod json;
+mod latex;
+mod lexer;
+mod ll;
+mod lr1;
+mod optimize;
+mod regex;
+mod report;
+mod semantic;
+mod symbols;
+mod table;
+
+use symbols::InternedGrammar;
+use table::{TableFormat, TextTable};
+
 #[derive(Eq, Hash, PartialEq, Clone, PartialOrd, Ord)]
 struct SlrRule {
     origin: String,
     prod: Vec<String>,
     num: usize,
     is_extended: bool,
+    /// Source of the semantic action block (`{ $1 + $3 }`), without the braces.
+    action: Option<String>,
+    ast: ast::AstShape,
+}
+
+/// What a grammar line says about its rule besides the symbols.
+#[derive(Clone, Default)]
+struct RuleExtras {
+    action: Option<String>,
+    ast: ast::AstShape,
+}
+
+impl fmt::Display for SlrRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}. {} -> ", self.num, self.origin)?;
+        for prod in self.prod.iter() {
+            write!(f, "{} ", prod)?;
+        }
+        Ok(())
+    }
 }
 
 impl SlrRule {
-    fn _new(origin: String, prod: &Vec<String>, rule_number: usize) -> SlrRule {
-        let mut new_prod: Vec<String> = Vec::new();
-        // add pointer
-        new_prod.push("'*'".to_string());
-        new_prod.append(&mut prod.clone());
-        return SlrRule {
-            origin,
-            prod: new_prod,
-            num: rule_number,
-            is_extended: false,
-        };
+    /// Symbols of the production, empty for an epsilon production.
+    fn body(&self) -> &[String] {
+        if self.prod.len() == 1 && self.prod[0] == "' '" {
+            &[]
+        } else {
+            &self.prod
+        }
     }
+}
 
-    fn init(&mut self) {
-        let mut new_prod = vec!["'*'".to_string()];
-        new_prod.append(&mut self.prod.clone());
-        self.prod = new_prod;
+/// LR(0) item: a rule of the extended grammar and the position of the dot inside its
+/// production (0 is before the first symbol).
+#[derive(Eq, Hash, PartialEq, Clone, Copy, PartialOrd, Ord, Debug)]
+struct SlrItem {
+    rule: usize,
+    dot: usize,
+}
+
+impl SlrItem {
+    fn new(rule: usize) -> SlrItem {
+        SlrItem { rule, dot: 0 }
     }
 
-    fn to_string(&self) -> String {
-        let mut ret = "".to_string();
-        ret += &(self.num.to_string() + &". ".to_string() + &self.origin + " -> ");
-        for prod in self.prod.iter() {
-            ret += &(prod.to_owned() + &" ".to_string().to_owned());
-        }
-        return ret;
+    /// Id of the symbol after the dot, if any.
+    fn get_reading_symbol(&self, grammar: &InternedGrammar) -> Option<usize> {
+        grammar.rules[self.rule].body.get(self.dot).copied()
     }
 
-    fn get_reading_symbol(&self) -> Option<String> {
-        for i in 0..self.prod.len() {
-            if self.prod[i] == "'*'" && i < self.prod.len() - 1 {
-                return Some(self.prod[i + 1].clone());
-            }
+    fn advance(&self) -> SlrItem {
+        SlrItem {
+            rule: self.rule,
+            dot: self.dot + 1,
         }
+    }
 
-        return None;
+    /// True if the dot is at the end of the production.
+    fn is_complete(&self, grammar: &InternedGrammar) -> bool {
+        self.dot >= grammar.rules[self.rule].body.len()
     }
 
-    fn advance(&mut self) {
-        for i in 0..(self.prod.len() - 1) {
-            if self.prod[i] == "'*'" {
-                self.prod.swap(i, i + 1);
-                return;
+    /// Item written as its rule with a `•` at the dot position, e.g. `1. E -> E • + T`.
+    fn to_string(self, extended_grammar: &[SlrRule]) -> String {
+        let rule = &extended_grammar[self.rule];
+        let mut ret = format!("{}. {} ->", rule.num, rule.origin);
+        for (i, symbol) in rule.body().iter().enumerate() {
+            if i == self.dot {
+                ret += " •";
             }
+            ret += " ";
+            ret += symbol;
+        }
+        if self.dot >= rule.body().len() {
+            ret += " •";
         }
+        ret
     }
 }
 
 struct SlrState {
-    kernel: HashSet<SlrRule>,
-    extended_state: HashSet<SlrRule>,
-    transitions: HashSet<(String, usize)>,
+    kernel: BTreeSet<SlrItem>,
+    extended_state: BTreeSet<SlrItem>,
+    /// Symbol id and target state of every transition.
+    transitions: HashSet<(usize, usize)>,
 }
 
 impl SlrState {
     fn new() -> SlrState {
         SlrState {
-            kernel: HashSet::new(),
-            extended_state: HashSet::new(),
+            kernel: BTreeSet::new(),
+            extended_state: BTreeSet::new(),
             transitions: HashSet::new(),
         }
     }
 
-    fn get_reading_symbols(&self) -> HashSet<String> {
-        let mut symbols: HashSet<String> = HashSet::new();
+    /// Kernel and closure items together.
+    fn items(&self) -> impl Iterator<Item = &SlrItem> {
+        self.kernel.iter().chain(self.extended_state.iter())
+    }
 
-        // from kernel
-        for rule in self.kernel.iter() {
-            let symbol = rule.get_reading_symbol();
-            match symbol {
-                Some(s) => {
-                    symbols.insert(s);
-                }
-                None => {}
-            }
-        }
+    /// Ids of the symbols after the dot of every item, in id order.
+    fn get_reading_symbols(&self, grammar: &InternedGrammar) -> BTreeSet<usize> {
+        let mut symbols: BTreeSet<usize> = BTreeSet::new();
 
-        // from extended
-        for rule in self.extended_state.iter() {
-            let symbol = rule.get_reading_symbol();
-            match symbol {
-                Some(s) => {
-                    symbols.insert(s);
-                }
-                None => {}
+        for item in self.items() {
+            if let Some(s) = item.get_reading_symbol(grammar) {
+                symbols.insert(s);
             }
         }
 
-        return symbols;
+        symbols
     }
 
-    fn get_next_kernel(&self, reading_symbol: &String) -> HashSet<SlrRule> {
-        let mut new_kernel: HashSet<SlrRule> = HashSet::new();
-
-        // from kernel
-        for rule in self.kernel.iter() {
-            match rule.get_reading_symbol() {
-                Some(symbol) => {
-                    if symbol == reading_symbol.clone() {
-                        let mut new_rule = rule.clone();
-                        new_rule.advance();
-                        new_kernel.insert(new_rule);
-                    }
-                }
-                None => {}
-            }
-        }
+    fn get_next_kernel(
+        &self,
+        grammar: &InternedGrammar,
+        reading_symbol: usize,
+    ) -> BTreeSet<SlrItem> {
+        let mut new_kernel: BTreeSet<SlrItem> = BTreeSet::new();
 
-        // from extended
-        for rule in self.extended_state.iter() {
-            match rule.get_reading_symbol() {
-                Some(symbol) => {
-                    if symbol == reading_symbol.clone() {
-                        let mut new_rule = rule.clone();
-                        new_rule.advance();
-                        new_kernel.insert(new_rule);
-                    }
-                }
-                None => {}
+        for item in self.items() {
+            if item.get_reading_symbol(grammar) == Some(reading_symbol) {
+                new_kernel.insert(item.advance());
             }
         }
 
-        return new_kernel;
+        new_kernel
     }
 
-    fn _to_string(&self) -> String {
+    fn _to_string(&self, extended_grammar: &[SlrRule]) -> String {
         let mut ret = "".to_string();
 
-        // add rules from kernel
-        for rule in self.kernel.iter() {
-            ret += &(rule.to_string() + "\n");
+        // add items from kernel
+        for item in self.kernel.iter() {
+            ret += &(item.to_string(extended_grammar) + "\n");
         }
         ret += "- - - - - - -\n";
-        // add rules from extended
-        for rule in self.extended_state.iter() {
-            ret += &(rule.to_string() + "\n");
+        // add items from extended
+        for item in self.extended_state.iter() {
+            ret += &(item.to_string(extended_grammar) + "\n");
         }
 
-        return ret;
+        ret
     }
 
-    fn get_end_rules(&self) -> HashMap<String, usize> {
-        let mut ret = HashMap::new();
+    /// Rules whose items have the dot at the end, from the kernel or the closure (epsilon rules).
+    fn get_end_rules(&self, grammar: &InternedGrammar) -> BTreeSet<usize> {
+        let mut ret = BTreeSet::new();
 
-        for rule in self.kernel.iter() {
-            if rule.prod.last().unwrap() == "'*'" {
-                ret.insert(rule.origin.clone(), rule.num);
+        for item in self.items() {
+            if item.is_complete(grammar) {
+                ret.insert(item.rule);
             }
         }
 
-        return ret;
+        ret
     }
 }
 
 fn process_str(
     txt: String,
     grammar: &mut HashMap<String, Vec<Vec<String>>>,
+    extras: &mut HashMap<String, Vec<RuleExtras>>,
     first_non_terminal: &mut String,
 ) {
     let lines = txt.lines().collect::<Vec<&str>>();
@@ -180,8 +213,17 @@ fn process_str(
     for i in 1..(n_lines + 1) {
         let i: usize = i.into();
 
+        // Separate the semantic action, if any
+        let (line, action) = match semantic::split_action(lines[i]) {
+            Ok(split) => split,
+            Err(err) => {
+                println!("Wrong action in line {}: {}", i, err);
+                return;
+            }
+        };
+
         // Scan line
-        let mut iter = lines[i].trim_end().chars().peekable();
+        let mut iter = line.trim_end().chars().peekable();
         let mut peek = iter.next();
 
         let mut current = String::from("");
@@ -189,7 +231,7 @@ fn process_str(
         let mut productions: Vec<String> = Vec::new();
         let mut found_arrow = false;
 
-        while peek != None {
+        while peek.is_some() {
             let chr = peek.unwrap();
 
             match chr {
@@ -197,31 +239,27 @@ fn process_str(
                     let mut skip = false;
 
                     // check if we're reading epsilon
-                    if current == "'" {
-                        if iter.peek() != None {
-                            if iter.peek().unwrap() == &'\'' {
-                                // Epsilon found
-                                if found_arrow {
-                                    productions.push("' '".to_string());
-                                    current = "".to_string();
-                                    iter.next();
-                                    skip = true;
-                                } else {
-                                    print!("Wrong Input! Epsilon cannot be non terminal.");
-                                    return;
-                                }
-                            }
+                    if current == "'" && iter.peek().is_some() && iter.peek().unwrap() == &'\'' {
+                        // Epsilon found
+                        if found_arrow {
+                            productions.push("' '".to_string());
+                            current = "".to_string();
+                            iter.next();
+                            skip = true;
+                        } else {
+                            print!("Wrong Input! Epsilon cannot be non terminal.");
+                            return;
                         }
                     }
 
                     // assign origin if not set
-                    if current != "" && !skip {
-                        if origin == "" {
+                    if !current.is_empty() && !skip {
+                        if origin.is_empty() {
                             origin = current.clone();
                             current = "".to_string();
 
                             // assign first non terminal if not set
-                            if first_non_terminal == "" {
+                            if first_non_terminal.is_empty() {
                                 *first_non_terminal = origin.clone();
                             }
                         } else if found_arrow {
@@ -236,9 +274,9 @@ fn process_str(
                 }
                 '-' => {
                     // Check if we're finding an arrow
-                    if current.len() == 0 {
+                    if current.is_empty() {
                         // this is not part of another symbol
-                        if iter.peek() != None {
+                        if iter.peek().is_some() {
                             if iter.peek().unwrap() == &'>' {
                                 // Arrow found.
                                 found_arrow = true;
@@ -262,8 +300,8 @@ fn process_str(
         }
 
         // Add last items read
-        if current != "" && current != " " {
-            if origin == "" {
+        if !current.is_empty() && current != " " {
+            if origin.is_empty() {
                 println!("Error! Empty non terminal.");
                 return;
             } else if found_arrow {
@@ -275,21 +313,47 @@ fn process_str(
             }
         }
 
+        // Separate the AST annotations
+        let ast = match ast::split_annotations(&mut productions) {
+            Ok(ast) => ast,
+            Err(err) => {
+                println!("Wrong annotations in line {}: {}", i, err);
+                return;
+            }
+        };
+
+        // `$` is the end of the input, not a symbol of the grammar
+        if origin == "$" || productions.iter().any(|symbol| symbol == "$") {
+            println!("Wrong Line! `$` is the end of the input and can't be a symbol.");
+            return;
+        }
+
+        // Every `$n` of the action must be a symbol of the body
+        if let Some(action) = &action {
+            let len = productions.iter().filter(|symbol| *symbol != "' '").count();
+            if let Err(err) = semantic::check_args(action, len) {
+                println!("Wrong action in line {}: {}", i, err);
+                return;
+            }
+        }
+
         // add origin and production to grammar hashmap
+        extras
+            .entry(origin.clone())
+            .or_default()
+            .push(RuleExtras { action, ast });
         match grammar.entry(origin) {
             Entry::Occupied(mut prods) => {
                 prods.get_mut().push(productions);
             }
             Entry::Vacant(entry_prods) => {
-                let mut prods = Vec::new();
-                prods.push(productions);
-
-                entry_prods.insert(prods);
+                entry_prods.insert(vec![productions]);
             }
         }
     }
 }
 
+#[derive(Clone, PartialEq, Debug)]
 enum Action {
     S(usize),
     R(usize),
@@ -297,167 +361,58 @@ enum Action {
     Err,
 }
 
-struct SlrRow {
-    actions: HashMap<String, Action>,
-    gotos: HashMap<String, usize>,
-}
-
-/// Recursive function to get firsts of a non terminal
-fn get_firsts(
-    grammar: &HashMap<String, Vec<Vec<String>>>,
-    terminals: &HashSet<&String>,
-    non_terminals: &HashSet<&String>,
-    symbol: &String,
-    caller: Option<&String>,
-) -> HashSet<String> {
-    let mut returning_set: HashSet<String> = HashSet::new();
-
-    // base case: this symbol is a terminal or epsilon.
-    if terminals.contains(symbol) || symbol == "' '" {
-        returning_set.insert(symbol.to_string());
-    }
-    // this symbol is a non-terminal
-    else if non_terminals.contains(symbol) {
-        // see first elements in grammar with this symbol
-        match grammar.get(symbol) {
-            Some(prods) => {
-                // iterate over productions to see first elements.
-                for prod in prods {
-                    // we start checking at 0 (first element)
-                    let mut pos = 0;
-                    let mut should_continue = true;
-
-                    while should_continue {
-                        should_continue = false;
-
-                        // if first element at pos is the same at caller, ignore this iteration to avoid infinite loop
-                        match caller {
-                            Some(caller_symbol) => {
-                                if &prod[pos] == caller_symbol {
-                                    continue;
-                                }
-                            }
-                            None => {}
-                        }
-
-                        // get firsts of element at pos in production
-                        let mut obtained_firsts =
-                            get_firsts(grammar, terminals, non_terminals, &prod[pos], Some(symbol));
-
-                        // ***** debug ******
-                        // print analisis
-                        /*println!("analyzing firsts of {}", prod[pos]);
-                        print!("obtained: ");
-                        for it in &obtained_firsts {
-                            print!("{it}, ");
-                        }
-                        println!("\n");*/
-
-                        // check if epsilon exists in such firsts
-                        if obtained_firsts.contains(&"' '".to_string()) {
-                            // epsilon exists in set; is this not the last item?
-                            if pos + 1 == prod.len() {
-                                // it is the last item, epsilon remains.
-                                returning_set.extend(obtained_firsts);
-                            } else if pos + 1 < prod.len() {
-                                // it is not the last item, remove epsilon and get firsts of next item.
-                                obtained_firsts.remove("' '");
-                                returning_set.extend(obtained_firsts);
-                                pos += 1;
-                                should_continue = true;
-                            }
-                        } else {
-                            // epsilon doesn't exist in such firsts
-                            returning_set.extend(obtained_firsts);
-                        }
-                    }
-                }
-            }
-            None => {}
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::S(s) => write!(f, "s{}", s),
+            Action::R(r) => write!(f, "r{}", r),
+            Action::Acc => write!(f, "ACC"),
+            Action::Err => write!(f, "ERR"),
         }
     }
+}
 
-    return returning_set;
+/// Row of the parse table, keyed by symbol id (see `InternedGrammar`). Names are only
+/// looked up to print the table.
+#[derive(Clone)]
+struct SlrRow {
+    actions: HashMap<usize, Action>,
+    gotos: HashMap<usize, usize>,
+    /// Every action that was proposed for a cell marked as `Action::Err`.
+    conflicts: HashMap<usize, Vec<Action>>,
 }
 
-/// Recursive function to get follows of a non terminal
-fn get_follows(
-    grammar: &HashMap<String, Vec<Vec<String>>>,
-    terminals: &HashSet<&String>,
-    non_terminals: &HashSet<&String>,
-    symbol: &String,
-    first_non_terminal: &String,
-    caller: Option<&String>,
-) -> HashSet<String> {
-    let mut returning_set: HashSet<String> = HashSet::new();
-
-    // check if this symbol is the first non terminal.
-    if symbol == first_non_terminal {
-        returning_set.insert("$".to_string());
-    }
-
-    // go through grammar to find this item in right hand of definitions
-    for (origin, prods) in grammar {
-        for prod in prods {
-            for mut pos in 0..prod.len() {
-                // check if current item is the symbol we're looking for
-                if &prod[pos] == symbol {
-                    // we should act until we find a follow that doesn't contain epsilon or end the production.
-                    let mut should_repeat_next = true;
-                    while should_repeat_next {
-                        should_repeat_next = false;
-
-                        // is this the last item?
-                        if pos + 1 == prod.len() {
-                            // this is the last item, is the origin the caller of this follows?
-                            match caller {
-                                Some(caller_symbol) => {
-                                    if caller_symbol == &prod[pos] {
-                                        // this is the caller of this follows, we should avoid recursion. Just continue
-                                        break;
-                                    }
-                                }
-                                None => {}
-                            }
+impl SlrRow {
+    fn new() -> SlrRow {
+        SlrRow {
+            actions: HashMap::new(),
+            gotos: HashMap::new(),
+            conflicts: HashMap::new(),
+        }
+    }
 
-                            //use follow of origin
-                            let origin_follows = get_follows(
-                                grammar,
-                                terminals,
-                                non_terminals,
-                                origin,
-                                first_non_terminal,
-                                Some(symbol),
-                            );
-                            returning_set.extend(origin_follows);
-                        } else {
-                            // this is not the last item, act upon next item
-                            // check if next item is this same item; if so, just continue
-                            if &prod[pos + 1] == &prod[pos] {
-                                continue;
-                            }
-                            // if not, get firsts of next item
-                            let mut next_firsts =
-                                get_firsts(grammar, terminals, non_terminals, &prod[pos + 1], None);
-                            // check if such firsts have epsilon
-                            if next_firsts.contains("' '") {
-                                // they do, in such case we concatenate (without epsilon) and do the same for next item.
-                                next_firsts.remove("' '");
-                                returning_set.extend(next_firsts);
-                                should_repeat_next = true;
-                                pos += 1;
-                            } else {
-                                // they don't, we should just add such firsts
-                                returning_set.extend(next_firsts);
-                            }
-                        }
-                    }
+    /// Sets the action for `symbol`. If the cell already has an action it becomes
+    /// `Action::Err` and every proposed action is kept in `conflicts`.
+    fn set_action(&mut self, symbol: usize, action: Action) {
+        match self.actions.get_mut(&symbol) {
+            Some(v) => {
+                if *v == action {
+                    return;
+                }
+                let conflict = self.conflicts.entry(symbol).or_default();
+                if conflict.is_empty() {
+                    conflict.push(v.clone());
                 }
+                if !conflict.contains(&action) {
+                    conflict.push(action);
+                }
+                *v = Action::Err;
+            }
+            None => {
+                self.actions.insert(symbol, action);
             }
         }
     }
-
-    return returning_set;
 }
 
 fn _print_grammar(grammar: &HashMap<String, Vec<Vec<String>>>) {
@@ -469,47 +424,45 @@ fn _print_grammar(grammar: &HashMap<String, Vec<Vec<String>>>) {
             for s in val {
                 print!("{s} ")
             }
-            println!("");
+            println!();
         }
-        println!("");
+        println!();
     }
 }
 
-fn print_extended_grammar(extended_grammar: &Vec<SlrRule>) {
+fn print_extended_grammar(extended_grammar: &[SlrRule]) {
     println!("\n- - -");
     println!("EXTENDED GRAMMAR\n");
     for rule in extended_grammar {
-        println!("{}", rule.to_string());
+        let mut line = if rule.ast.is_default() {
+            rule.to_string()
+        } else {
+            format!(
+                "{}. {} -> {} ",
+                rule.num,
+                rule.origin,
+                ast::annotated_body(rule, &rule.ast)
+            )
+        };
+        if let Some(action) = &rule.action {
+            line += &format!("{{ {} }}", action);
+        }
+        println!("{}", line);
     }
 }
 
-fn print_firsts_follows(
-    grammar: &HashMap<String, Vec<Vec<String>>>,
-    non_terminals: &HashSet<&String>,
-    terminals: &HashSet<&String>,
-    first_non_terminal: &String,
-) {
+/// Prints rows of `get_firsts_follows`, or the same rows read from an exported analysis.
+fn print_firsts_follows(first_follow: &[(String, Vec<String>, Vec<String>)]) {
     println!("\n- - -");
     println!("FIRSTS & FOLLOWS \n");
-    for nterm in non_terminals {
-        // firsts
-        let firsts = get_firsts(&grammar, &terminals, &non_terminals, nterm, None);
+    for (nterm, firsts, follows) in first_follow {
         println!("{nterm}: ");
         print!("FIRST = ");
         for it in firsts {
             print!("{it}, ");
         }
 
-        // follows
         print!("\nFOLLOW = ");
-        let follows = get_follows(
-            &grammar,
-            &terminals,
-            &non_terminals,
-            nterm,
-            &first_non_terminal,
-            None,
-        );
         for it in follows {
             print!("{it}, ");
         }
@@ -517,199 +470,180 @@ fn print_firsts_follows(
     }
 }
 
-fn get_extended_prods(extended_grammar: &Vec<SlrRule>, key: String) -> HashSet<SlrRule> {
-    let mut prods: HashSet<SlrRule> = HashSet::new();
-    for rule in extended_grammar.iter() {
-        if rule.origin == key {
-            let mut new_rule = rule.clone();
-            new_rule.init();
-            prods.insert(new_rule);
+/// FIRST and FOLLOW sets of every non terminal as `(non terminal, firsts, follows)`, sorted by
+/// name. Epsilon is listed in FIRST as `' '` when the non terminal is nullable.
+fn get_firsts_follows(grammar: &InternedGrammar) -> Vec<(String, Vec<String>, Vec<String>)> {
+    let mut ret = Vec::new();
+    for nterm in grammar.non_terminals() {
+        let mut firsts = grammar.names_of(&grammar.firsts[nterm]);
+        if grammar.nullable[nterm] {
+            firsts.push("' '".to_string());
         }
+        firsts.sort();
+        let mut follows = grammar.names_of(&grammar.follows[nterm]);
+        follows.sort();
+        ret.push((grammar.name(nterm).clone(), firsts, follows));
     }
 
-    return prods;
+    ret
 }
 
-fn add_extender_prods(extended_grammar: &Vec<SlrRule>, state: &mut SlrState) {
-    let current_symbols = state.get_reading_symbols();
-    for symbol in current_symbols.iter() {
-        for rule in extended_grammar.iter() {
-            if rule.origin == symbol.clone() {
-                let mut new_rule = rule.clone();
-                new_rule.init();
-                state.extended_state.insert(new_rule);
-            }
+/// Builds the augmented grammar: rule 0 is `S' -> S`, followed by the rules of the start
+/// symbol and then the rest of the non terminals in alphabetical order, so rule numbers
+/// don't change between runs.
+fn build_extended_grammar(
+    grammar: &HashMap<String, Vec<Vec<String>>>,
+    extras: &HashMap<String, Vec<RuleExtras>>,
+    first_non_terminal: &String,
+) -> Vec<SlrRule> {
+    let mut extended_grammar: Vec<SlrRule> = Vec::new();
+    //      add extended grammar rule
+    extended_grammar.push(SlrRule {
+        origin: first_non_terminal.clone() + "'",
+        prod: vec![first_non_terminal.clone()],
+        num: 0,
+        is_extended: true,
+        action: None,
+        ast: ast::AstShape::default(),
+    });
+    //      add rules
+    let mut origins: Vec<&String> = grammar.keys().collect();
+    origins.sort_by_key(|origin| (*origin != first_non_terminal, origin.to_string()));
+    for origin in origins {
+        for (i, prod) in grammar[origin].iter().enumerate() {
+            let rule_extras = extras
+                .get(origin)
+                .and_then(|rule_extras| rule_extras.get(i).cloned())
+                .unwrap_or_default();
+            extended_grammar.push(SlrRule {
+                origin: origin.clone(),
+                prod: prod.clone(),
+                num: extended_grammar.len(),
+                is_extended: false,
+                action: rule_extras.action,
+                ast: rule_extras.ast,
+            })
         }
     }
+
+    extended_grammar
 }
 
-fn _print_slr(slr: &Vec<SlrState>) {
-    println!("\n- - -");
-    println!("SLR\n");
-    for i in 0..slr.len() {
-        println!("I{}:", i);
-        println!("{}\n", slr[i]._to_string());
+fn get_extended_prods(grammar: &InternedGrammar, key: usize) -> BTreeSet<SlrItem> {
+    let mut prods: BTreeSet<SlrItem> = BTreeSet::new();
+    for rule in grammar.rules_by_origin[key].iter() {
+        prods.insert(SlrItem::new(*rule));
     }
-}
 
-fn _kernel_to_string(kernel: &HashSet<SlrRule>) -> String {
-    let mut ret = "".to_string();
+    prods
+}
 
-    for rule in kernel.iter() {
-        ret += &(rule.to_string());
+/// Closure of a state: adds the initial items of every non terminal read by an item, following
+/// chains of non terminals (`A -> • B x`, `B -> • C y`, `C -> ...`) until nothing new is added.
+fn add_extender_prods(grammar: &InternedGrammar, state: &mut SlrState) {
+    // worklist of symbols read after a dot
+    let mut pending: Vec<usize> = state.get_reading_symbols(grammar).into_iter().collect();
+    let mut expanded = vec![false; grammar.names.len()];
+    while let Some(symbol) = pending.pop() {
+        if grammar.is_terminal(symbol) || expanded[symbol] {
+            continue;
+        }
+        expanded[symbol] = true;
+        for item in get_extended_prods(grammar, symbol) {
+            if let Some(next) = item.get_reading_symbol(grammar) {
+                pending.push(next);
+            }
+            if !state.kernel.contains(&item) {
+                state.extended_state.insert(item);
+            }
+        }
     }
-
-    return ret;
 }
 
-fn insert_to_kernels_hash(
-    hash: &mut HashMap<Vec<SlrRule>, usize>,
-    kernel: &HashSet<SlrRule>,
-    idx: usize,
-) {
-    let mut kernel_to_add = Vec::from_iter(kernel.clone());
-    kernel_to_add.sort();
-    hash.insert(kernel_to_add, idx);
+fn _print_slr(slr: &[SlrState], extended_grammar: &[SlrRule]) {
+    println!("\n- - -");
+    println!("SLR\n");
+    for (i, state) in slr.iter().enumerate() {
+        println!("I{}:", i);
+        println!("{}\n", state._to_string(extended_grammar));
+    }
 }
 
-fn build_slr(slr: &mut Vec<SlrState>, extended_grammar: &Vec<SlrRule>) {
+fn build_slr(slr: &mut Vec<SlrState>, grammar: &InternedGrammar) {
     let mut slr_len = 0;
-    let mut states_to_build: HashSet<usize> = HashSet::new();
-    let mut kernels: HashMap<Vec<SlrRule>, usize> = HashMap::new();
+    // states are built and numbered in order, reading symbols by id, so the
+    // automaton is the same on every run
+    let mut states_to_build: BTreeSet<usize> = BTreeSet::new();
+    let mut kernels: HashMap<BTreeSet<SlrItem>, usize> = HashMap::new();
 
     // add state 0
-    let mut rule0 = extended_grammar[0].clone();
-    rule0.init();
-    let mut kernel0: HashSet<SlrRule> = HashSet::new();
-    kernel0.insert(rule0.clone());
+    let kernel0: BTreeSet<SlrItem> = BTreeSet::from([SlrItem::new(0)]);
     // add kernel 0 to kernels hashmap
-    insert_to_kernels_hash(&mut kernels, &kernel0, 0);
-    let extended_prods = get_extended_prods(extended_grammar, rule0.get_reading_symbol().unwrap());
-    slr.push(SlrState {
-        kernel: kernel0,
-        extended_state: extended_prods,
-        transitions: HashSet::new(),
-    });
+    kernels.insert(kernel0.clone(), 0);
+    let mut state0 = SlrState::new();
+    state0.kernel = kernel0;
+    slr.push(state0);
+    states_to_build.insert(0);
     slr_len += 1;
 
-    // create state 0 transitions
-    let reading_symbols = slr[0].get_reading_symbols();
-    for symbol in reading_symbols.iter() {
-        // create new kernel advancing under such symbols
-        let new_kernel = slr[0].get_next_kernel(symbol);
-        insert_to_kernels_hash(&mut kernels, &new_kernel, slr_len);
-        let mut new_state = SlrState::new();
-        new_state.kernel = new_kernel;
-        slr[0].transitions.insert((symbol.clone(), slr_len));
-        slr.push(new_state);
-        states_to_build.insert(slr_len);
-        slr_len += 1;
-    }
-
     // create rest of the states
-    while !states_to_build.is_empty() {
-        let next_state_option = states_to_build.iter().next();
-        let mut state_idx: Option<usize> = None;
-        match next_state_option {
-            Some(idx) => {
-                state_idx = Some(idx.clone());
-            }
-            None => {}
-        }
-        if state_idx.is_some() {
-            let idx = state_idx.unwrap();
-            // create this state
-            // init extended productions
-            add_extender_prods(extended_grammar, &mut slr[idx]);
-            // transitions
-            let next_symbols = slr[idx].get_reading_symbols();
-            for symbol in next_symbols.iter() {
-                // get new kernel
-                let mut new_kernel = Vec::from_iter(slr[idx].get_next_kernel(symbol));
-                new_kernel.sort();
-                // check if kernel already exists
-                match kernels.get(&new_kernel) {
-                    Some(existing_idx) => {
-                        // just add a transition for this found state
-                        slr[idx].transitions.insert((symbol.clone(), *existing_idx));
-                    }
-                    None => {
-                        // create new state
-                        // create new kernel advancing under such symbols and add transition
-                        let new_kernel = slr[idx].get_next_kernel(symbol);
-                        insert_to_kernels_hash(&mut kernels, &new_kernel, slr_len);
-                        let mut new_state = SlrState::new();
-                        new_state.kernel = new_kernel;
-                        slr[idx].transitions.insert((symbol.clone(), slr_len));
-                        slr.push(new_state);
-                        states_to_build.insert(slr_len);
-                        slr_len += 1;
-                    }
+    while let Some(idx) = states_to_build.pop_first() {
+        // create this state
+        // init extended productions
+        add_extender_prods(grammar, &mut slr[idx]);
+        // transitions
+        let next_symbols = slr[idx].get_reading_symbols(grammar);
+        for symbol in next_symbols {
+            // get new kernel
+            let new_kernel = slr[idx].get_next_kernel(grammar, symbol);
+            // check if kernel already exists
+            match kernels.get(&new_kernel) {
+                Some(existing_idx) => {
+                    // just add a transition for this found state
+                    slr[idx].transitions.insert((symbol, *existing_idx));
+                }
+                None => {
+                    // create new state and add transition
+                    kernels.insert(new_kernel.clone(), slr_len);
+                    let mut new_state = SlrState::new();
+                    new_state.kernel = new_kernel;
+                    slr[idx].transitions.insert((symbol, slr_len));
+                    slr.push(new_state);
+                    states_to_build.insert(slr_len);
+                    slr_len += 1;
                 }
             }
-            // remove this idx from statest to build
-            states_to_build.remove(&idx);
         }
     }
 }
 
-fn build_slr_table(
-    slr: &Vec<SlrState>,
-    table: &mut Vec<SlrRow>,
-    terminals: &HashSet<&String>,
-    non_terminals: &HashSet<&String>,
-    grammar: &HashMap<String, Vec<Vec<String>>>,
-    first_non_terminal: &String,
-) {
+fn build_slr_table(slr: &[SlrState], table: &mut Vec<SlrRow>, grammar: &InternedGrammar) {
     for state in slr.iter() {
-        let mut row: SlrRow = SlrRow {
-            actions: HashMap::new(),
-            gotos: HashMap::new(),
-        };
+        let mut row = SlrRow::new();
 
         // act upong transitions
-        for transition in state.transitions.iter() {
-            // if non terminal add goto
-            if non_terminals.contains(&transition.0) {
-                row.gotos.insert(transition.0.clone(), transition.1.clone());
-            }
-            // if terminal add s
-            else if terminals.contains(&transition.0) {
-                row.actions
-                    .insert(transition.0.clone(), Action::S(transition.1.clone()));
+        for (symbol, target) in state.transitions.iter() {
+            if grammar.is_terminal(*symbol) {
+                // if terminal add s
+                row.set_action(*symbol, Action::S(*target));
+            } else {
+                // if non terminal add goto
+                row.gotos.insert(*symbol, *target);
             }
         }
 
         // act if state has end of reading (pointer at the end of production)
-        let ending_rules = state.get_end_rules();
-        if !ending_rules.is_empty() {
-            // add reduce for each follow of ending rule
-            for rule in ending_rules.iter() {
-                let follows = get_follows(
-                    grammar,
-                    terminals,
-                    non_terminals,
-                    rule.0,
-                    first_non_terminal,
-                    None,
-                );
-
-                for symbol in follows {
-                    match row.actions.get_mut(&symbol) {
-                        Some(v) => {
-                            // set error if row action already exists
-                            *v = Action::Err;
-                        }
-                        None => {
-                            // add reduce
-                            if rule.1.clone() == 0 as usize {
-                                row.actions.insert(symbol, Action::Acc);
-                            } else {
-                                row.actions.insert(symbol, Action::R(rule.1.clone()));
-                            }
-                        }
-                    }
-                }
+        let ending_rules = state.get_end_rules(grammar);
+        // add reduce for each follow of ending rule
+        for rule in ending_rules {
+            let origin = grammar.rules[rule].origin;
+            for symbol in grammar.follows[origin].iter() {
+                let action = if rule == 0 {
+                    Action::Acc
+                } else {
+                    Action::R(rule)
+                };
+                row.set_action(symbol, action);
             }
         }
 
@@ -717,105 +651,173 @@ fn build_slr_table(
     }
 }
 
-fn slr_table_to_string(
-    table: &Vec<SlrRow>,
-    non_terminals: &HashSet<&String>,
-    terminals: &HashSet<&String>,
-) -> String {
-    let mut ret = "<table>".to_string();
-
-    // add headers
-    ret += "<tr>";
-    ret += "<th>state</th>";
-    for term in terminals.iter() {
-        ret += "<th>";
-        ret += term.clone();
-        ret += "</th>";
-    }
-    ret += "<th>$</th>";
-    for nterm in non_terminals.iter() {
-        ret += "<th>";
-        ret += nterm.clone();
-        ret += "</th>";
-    }
-    ret += "</tr>";
-
-    // add rows
-    for i in 0..table.len() {
-        ret += "<tr>";
-
-        // state id
-        ret += "<td>";
-        ret += &i.to_string();
-        ret += "</td>";
-
-        // contents of rows
-        //      actions
-        for term in terminals.iter() {
-            ret += "<td>";
-            match table[i].actions.get(term.clone()) {
-                Some(action) => match action {
-                    Action::Acc => {
-                        ret += "ACC";
-                    }
-                    Action::Err => {
-                        ret += "ERR";
-                    }
-                    Action::R(r) => {
-                        ret += "r";
-                        ret += &r.to_string();
-                    }
-                    Action::S(s) => {
-                        ret += "s";
-                        ret += &s.to_string();
-                    }
-                },
-                None => {}
-            }
-            ret += "</td>";
+fn slr_table_to_string(table: &[SlrRow], grammar: &InternedGrammar, format: TableFormat) -> String {
+    TextTable::from_slr_table(table, grammar).render(format)
+}
+
+fn print_compressed_table(
+    compressed: &compress::CompressedTable,
+    table: &[SlrRow],
+    format: TableFormat,
+) {
+    let join = |values: &[usize]| {
+        let strs: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        strs.join(" ")
+    };
+
+    println!("\n- - -");
+    println!("COMPRESSED TABLE\n");
+    println!(
+        "action row of each state: {}",
+        join(&compressed.action_rows)
+    );
+    println!("goto row of each state: {}", join(&compressed.goto_rows));
+    let defaults: Vec<String> = compressed
+        .default_reductions
+        .iter()
+        .map(|d| d.map_or("-".to_string(), |r| format!("r{}", r)))
+        .collect();
+    println!("default reductions: {}", defaults.join(" "));
+    println!("action base: {}", join(&compressed.action_base));
+    let actions: Vec<String> = compressed
+        .action_value
+        .iter()
+        .map(|a| a.as_ref().map_or("-".to_string(), |a| a.to_string()))
+        .collect();
+    println!("action values: {}", actions.join(" "));
+    println!("goto base: {}", join(&compressed.goto_base));
+    println!("\n{}", compressed.stats(table).to_table().render(format));
+    println!(
+        "Lookups match the dense table: {}",
+        if compressed.matches(table) {
+            "yes"
+        } else {
+            "no"
         }
-        //      action ($)
-        ret += "<td>";
-        match table[i].actions.get("$") {
-            Some(action) => match action {
-                Action::Acc => {
-                    ret += "ACC";
-                }
-                Action::Err => {
-                    ret += "ERR";
-                }
-                Action::R(r) => {
-                    ret += "r";
-                    ret += &r.to_string();
-                }
-                Action::S(s) => {
-                    ret += "s";
-                    ret += &s.to_string();
-                }
-            },
-            None => {}
-        }
-        ret += "</td>";
-        //      gotos
-        for nterm in non_terminals.iter() {
-            ret += "<td>";
-            match table[i].gotos.get(nterm.clone()) {
-                Some(goto) => {
-                    ret += &goto.to_string();
-                }
-                None => {}
+    );
+}
+
+/// Thompson NFA, DFA from the subset construction and minimal DFA, each as a table and in
+/// DOT.
+fn print_automata(title: &str, nfa: &regex::Nfa, accept_names: &[String], format: TableFormat) {
+    let dfa = dfa::Dfa::from_nfa(nfa);
+    let minimal = dfa.minimize();
+    println!("\n- - -");
+    println!("{} NFA\n", title);
+    println!("{}\n", nfa.to_table(accept_names).render(format));
+    println!("{}", nfa.to_dot("nfa", accept_names));
+    println!("\n- - -");
+    println!("{} DFA\n", title);
+    println!(
+        "{}\n",
+        dfa.to_table(accept_names, "NFA states").render(format)
+    );
+    println!("{}", dfa.to_dot("dfa", accept_names));
+    println!("\n- - -");
+    println!("{} MINIMAL DFA\n", title);
+    println!(
+        "{}\n",
+        minimal.to_table(accept_names, "DFA states").render(format)
+    );
+    println!("{}", minimal.to_dot("minimal_dfa", accept_names));
+}
+
+fn print_imported_analysis(analysis: &json::ImportedAnalysis, format: TableFormat) {
+    println!("Start symbol: {}", analysis.first_non_terminal);
+    _print_grammar(&analysis.grammar);
+    print_firsts_follows(&analysis.first_follow);
+    print_extended_grammar(&analysis.extended_grammar);
+    _print_slr(&analysis.slr, &analysis.extended_grammar);
+
+    let table_str = slr_table_to_string(&analysis.table, &analysis.interned, format);
+    println!("\n{}\n", table_str);
+}
+
+/// Writes a JSON document to `path`, or to stdout if `path` is `-`.
+fn write_json(path: &str, doc: &json::Json) {
+    if path == "-" {
+        println!("{}", doc.to_pretty_string());
+    } else {
+        fs::write(path, doc.to_pretty_string() + "\n").expect("Error writing JSON file");
+        println!("Analysis written to {}", path);
+    }
+}
+
+/// Names of a sequence of symbol ids.
+fn interned_names(interned: &InternedGrammar, ids: &[usize]) -> Vec<String> {
+    ids.iter().map(|id| interned.name(*id).clone()).collect()
+}
+
+/// Returns every value given to a flag that can be repeated.
+fn arg_values(args: &[String], flag: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        if arg == flag {
+            if let Some(value) = args.get(i + 1) {
+                ret.push(value.clone());
             }
-            ret += "</td>";
         }
-
-        ret += "</tr>";
     }
+    ret
+}
 
-    ret += "</table>";
-    return ret;
+/// Returns the value following `flag` in the command line arguments, if any.
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    let pos = args.iter().position(|arg| arg == flag)?;
+    args.get(pos + 1).cloned()
 }
 
 fn main() {
+    // Command line options
+    let args: Vec<String> = std::env::args().collect();
+    let table_format = match arg_value(&args, "--format") {
+        Some(name) => TableFormat::from_name(&name)
+            .expect("Unknown table format (use html, markdown, csv, text or latex)"),
+        None => TableFormat::Html,
+    };
+
+    // Load a previously exported analysis instead of a grammar
+    if let Some(path) = arg_value(&args, "--import-json") {
+        let doc = fs::read_to_string(&path).expect("Error reading file (check file path)");
+        match json::analysis_from_json(&doc) {
+            Ok(analysis) => {
+                print_imported_analysis(&analysis, table_format);
+                if let Some(path) = arg_value(&args, "--json") {
+                    let terminals: HashSet<&String> = analysis.terminals.iter().collect();
+                    let non_terminals: HashSet<&String> = analysis.non_terminals.iter().collect();
+                    let doc = json::analysis_to_json(
+                        &analysis.grammar,
+                        &analysis.first_non_terminal,
+                        &terminals,
+                        &non_terminals,
+                        &analysis.extended_grammar,
+                        &analysis.slr,
+                        &analysis.table,
+                    );
+                    write_json(&path, &doc);
+                }
+            }
+            Err(err) => println!("Invalid analysis file: {}", err),
+        }
+        return;
+    }
+
+    // Automata of regular expressions, without a grammar
+    let regexes = arg_values(&args, "--regex");
+    if !regexes.is_empty() {
+        for pattern in regexes.iter() {
+            match regex::parse_regex(pattern) {
+                Ok(parsed) => {
+                    let nfa = regex::Nfa::from_regexes(&[parsed]);
+                    let title = format!("REGEX {}", pattern);
+                    print_automata(&title, &nfa, &["accept".to_string()], table_format);
+                }
+                Err(err) => println!("Invalid regular expression {}: {}", pattern, err),
+            }
+        }
+        return;
+    }
+
     // Choose whether to use a file or input the grammar
     let _use_file = true;
     let mut txt = String::new();
@@ -842,28 +844,36 @@ fn main() {
 
     // Define grammar hashmap
     let mut grammar: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+    let mut extras: HashMap<String, Vec<RuleExtras>> = HashMap::new();
+
+    // Lexer of the %lex section, if the file has one
+    let lexer = match lexer::Lexer::from_grammar_file(&txt) {
+        Ok(lexer) => lexer,
+        Err(err) => {
+            println!("Wrong lex section, {}", err);
+            None
+        }
+    };
 
     // Process contents of file and store them in the grammar hashmap
     let mut first_non_terminal = "".to_string();
-    process_str(txt, &mut grammar, &mut first_non_terminal);
+    process_str(txt, &mut grammar, &mut extras, &mut first_non_terminal);
 
     // Get terminal and non-terminal symbols
     let mut non_terminals = HashSet::new();
     // add keys to non-terminal
-    for (key, _) in &grammar {
+    for key in grammar.keys() {
         non_terminals.insert(key);
     }
 
     // add values to terminals if they aren't in non-terminals
     let mut terminals = HashSet::new();
-    for (_, value) in &grammar {
+    for value in grammar.values() {
         for prods in value {
             for item in prods {
-                if item != "' '" {
-                    if !non_terminals.contains(item) {
-                        // it's a terminal
-                        terminals.insert(item);
-                    }
+                if item != "' '" && !non_terminals.contains(item) {
+                    // it's a terminal
+                    terminals.insert(item);
                 }
             }
         }
@@ -902,48 +912,602 @@ fn main() {
 
     //print_grammar(&grammar);
 
-    print_firsts_follows(&grammar, &non_terminals, &terminals, &first_non_terminal);
-
     // = = = SLR = = =
-    let mut extended_grammar: Vec<SlrRule> = Vec::new();
-    //      add extended grammar rule
-    extended_grammar.push(SlrRule {
-        origin: first_non_terminal.clone() + "'",
-        prod: vec![first_non_terminal.clone()],
-        num: 0,
-        is_extended: true,
-    });
-    //      add rules
-    for item in grammar.iter() {
-        for prod in item.1.iter() {
-            extended_grammar.push(SlrRule {
-                origin: item.0.clone(),
-                prod: prod.clone(),
-                num: extended_grammar.len(),
-                is_extended: false,
-            })
-        }
-    }
+    let extended_grammar = build_extended_grammar(&grammar, &extras, &first_non_terminal);
+    let interned = InternedGrammar::new(&extended_grammar);
+
+    print_firsts_follows(&get_firsts_follows(&interned));
 
     print_extended_grammar(&extended_grammar);
 
     let mut slr: Vec<SlrState> = Vec::new();
-    build_slr(&mut slr, &extended_grammar);
+    build_slr(&mut slr, &interned);
 
-    _print_slr(&slr);
+    _print_slr(&slr, &extended_grammar);
 
     let mut slr_table: Vec<SlrRow> = Vec::new();
-    build_slr_table(
-        &slr,
-        &mut slr_table,
-        &terminals,
-        &non_terminals,
-        &grammar,
-        &first_non_terminal,
-    );
+    build_slr_table(&slr, &mut slr_table, &interned);
+
+    let table_str = slr_table_to_string(&slr_table, &interned, table_format);
+    println!("\n{}\n", table_str);
+
+    // which of LL(1), LR(0), SLR(1), LALR(1) and LR(1) the grammar is
+    if args.iter().any(|arg| arg == "--classify") {
+        let results = classify::classify(&interned, &extended_grammar, &slr, &slr_table);
+        println!("\n- - -");
+        println!("CLASSIFICATION\n");
+        println!(
+            "{}\n",
+            classify::classification_table(&results).render(table_format)
+        );
+        for result in results.iter() {
+            match &result.explanation {
+                Some(explanation) => println!("Not {}: {}", result.class, explanation),
+                None => println!("{}: no conflicts", result.class),
+            }
+        }
+    }
+
+    // example inputs for the conflicts
+    if args.iter().any(|arg| arg == "--counterexamples") {
+        let examples = counterexample::counterexamples(&slr_table, &slr, &interned);
+        println!("\n- - -");
+        println!("CONFLICTS\n");
+        if examples.is_empty() {
+            println!("The table has no conflicts");
+        }
+        for example in examples.iter() {
+            println!(
+                "{}",
+                counterexample::counterexample_to_string(example, &extended_grammar)
+            );
+        }
+    }
+
+    // compressed encoding of the table
+    if args.iter().any(|arg| arg == "--compress") {
+        let use_defaults = args.iter().any(|arg| arg == "--default-reductions");
+        let compressed = compress::CompressedTable::new(&slr_table, &interned, use_defaults);
+        print_compressed_table(&compressed, &slr_table, table_format);
+    }
 
-    let table_html = slr_table_to_string(&slr_table, &non_terminals, &terminals);
-    println!("\n{}\n", table_html);
+    // lexer patterns
+    if let Some(lexer) = &lexer {
+        println!("\n- - -");
+        println!("LEXER\n");
+        println!("{}", lexer.rules_table().render(table_format));
+        let missing = lexer.missing_terminals(&terminals);
+        if !missing.is_empty() {
+            println!("Terminals without a pattern: {}", missing.join(", "));
+        }
+        if args.iter().any(|arg| arg == "--lex-automata") {
+            print_automata("LEXER", &lexer.nfa, &lexer.accept_names(), table_format);
+        }
+    }
+
+    // with a lexer the inputs are raw text, otherwise symbols separated by spaces; also gives
+    // where each token is, for the error messages
+    let input_tokens = |input: &str| match &lexer {
+        Some(lexer) => match lexer.tokenize(input) {
+            Ok((tokens, positions)) => {
+                let locations: Vec<String> = positions
+                    .iter()
+                    .map(|(line, column)| format!("line {}, column {}", line, column))
+                    .collect();
+                Ok((tokens, locations))
+            }
+            Err(err) => Err(err.to_string()),
+        },
+        None => {
+            let tokens = driver::tokenize_with_lexemes(input);
+            let locations: Vec<String> = (0..=tokens.len())
+                .map(|pos| format!("token {}", pos + 1))
+                .collect();
+            Ok((tokens, locations))
+        }
+    };
+
+    // parse the input strings given on the command line
+    let mut traces = Vec::new();
+    let has_actions = extended_grammar.iter().any(|rule| rule.action.is_some());
+    let ast_format = arg_value(&args, "--ast");
+    for input in arg_values(&args, "--parse") {
+        let (tokens, locations) = match input_tokens(&input) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                println!("\n- - -");
+                println!("PARSE \"{}\": LEXICAL ERROR, {}", input, err);
+                continue;
+            }
+        };
+        let mut ast_builder = ast_format.as_ref().map(|_| ast::AstBuilder::default());
+        let (result, value) =
+            semantic::evaluate(&slr_table, &interned, &extended_grammar, &tokens, |event| {
+                if let Some(builder) = ast_builder.as_mut() {
+                    builder.event(event, &extended_grammar, &tokens);
+                }
+            });
+        println!("\n- - -");
+        println!(
+            "PARSE \"{}\": {}\n",
+            input,
+            if result.accepted {
+                "ACCEPTED"
+            } else {
+                "REJECTED"
+            }
+        );
+        if lexer.is_some() {
+            let shown: Vec<String> = tokens
+                .iter()
+                .map(|(kind, lexeme)| {
+                    if kind == lexeme {
+                        kind.clone()
+                    } else {
+                        format!("{}:{}", kind, lexeme)
+                    }
+                })
+                .collect();
+            println!("TOKENS: {}\n", shown.join(" "));
+        }
+        println!("{}", driver::trace_to_table(&result).render(table_format));
+        if !result.errors.is_empty() {
+            println!("SYNTAX ERRORS:");
+            for error in result.errors.iter() {
+                println!(
+                    "  {}: {}",
+                    locations[error.position],
+                    diagnostics::describe_error(
+                        error,
+                        &slr_table,
+                        &slr,
+                        &interned,
+                        &extended_grammar
+                    )
+                );
+            }
+        }
+        if has_actions {
+            match value {
+                Some(Ok(value)) => println!("VALUE: {}", value),
+                Some(Err(err)) => println!("VALUE: error in {}", err),
+                None => {}
+            }
+        }
+        if let (Some(ast_format), Some(builder)) = (&ast_format, ast_builder) {
+            if let Some(tree) = builder.finish(&result) {
+                match ast_format.as_str() {
+                    "json" => println!("AST:\n{}", tree.to_json().to_pretty_string()),
+                    _ => println!("AST: {}", tree),
+                }
+            }
+        }
+        traces.push((input, result));
+    }
+
+    // Earley parser, for grammars the table can't parse
+    for input in arg_values(&args, "--earley") {
+        let (tokens, locations) = match input_tokens(&input) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                println!("\n- - -");
+                println!("EARLEY \"{}\": LEXICAL ERROR, {}", input, err);
+                continue;
+            }
+        };
+        let kinds: Vec<String> = tokens.iter().map(|(kind, _)| kind.clone()).collect();
+        let chart = earley::recognize(&interned, &kinds);
+        println!("\n- - -");
+        println!(
+            "EARLEY \"{}\": {}\n",
+            input,
+            if chart.accepted() {
+                "ACCEPTED"
+            } else {
+                "REJECTED"
+            }
+        );
+        println!("{}", chart.to_table(&extended_grammar).render(table_format));
+        if !chart.accepted() {
+            match chart.failed_at() {
+                Some(pos) => println!("{}: no item can scan `{}`", locations[pos], kinds[pos]),
+                None => println!("The input ends before a sentence is complete"),
+            }
+            continue;
+        }
+        let forest = earley::build_forest(&interned, &chart);
+        println!("PARSE FOREST:\n");
+        println!(
+            "{}",
+            earley::forest_table(&interned, &extended_grammar, &forest).render(table_format)
+        );
+        let root = (interned.rules[0].body[0], 0, kinds.len());
+        match earley::count_trees(&interned, &forest, root) {
+            Some(1) => println!("1 parse tree"),
+            Some(count) => println!("{} parse trees (the input is ambiguous)", count),
+            None => println!("Infinitely many parse trees (the grammar has a cycle)"),
+        }
+        if let Some(tree) = earley::first_tree(&interned, &extended_grammar, &forest, root) {
+            println!("TREE: {}", tree);
+        }
+    }
+
+    // random sentences of the grammar, run through the parser
+    if let Some(count) = arg_value(&args, "--generate") {
+        let count: usize = count.parse().expect("Invalid number of sentences");
+        let max_depth: usize = arg_value(&args, "--max-depth")
+            .map_or(12, |depth| depth.parse().expect("Invalid depth"));
+        let target: Option<usize> =
+            arg_value(&args, "--length").map(|length| length.parse().expect("Invalid length"));
+        let seed: u64 = match arg_value(&args, "--seed") {
+            Some(seed) => seed.parse().expect("Invalid seed"),
+            None => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64),
+        };
+        let generator = generate::Generator::new(&interned);
+        let mut rng = generate::Rng::new(seed);
+        println!("\n- - -");
+        println!("RANDOM SENTENCES (seed {})\n", seed);
+        let mut sentences = Vec::new();
+        for _ in 0..count {
+            match generator.sentence(&mut rng, max_depth, target) {
+                Some(sentence) => sentences.push(interned_names(&interned, &sentence)),
+                None => break,
+            }
+        }
+        if sentences.is_empty() {
+            println!("The start symbol derives no sentence");
+        } else {
+            let sentences_table =
+                generate::sentences_table(&sentences, &slr_table, &interned, &extended_grammar);
+            println!("{}", sentences_table.render(table_format));
+        }
+    }
+
+    // every sentence up to a length, shortest first
+    if let Some(max_length) = arg_value(&args, "--enumerate") {
+        let max_length: usize = max_length.parse().expect("Invalid length");
+        let sentences: Vec<Vec<String>> = generate::enumerate(&interned, max_length)
+            .iter()
+            .map(|sentence| interned_names(&interned, sentence))
+            .collect();
+        println!("\n- - -");
+        println!("SENTENCES UP TO {} TOKENS\n", max_length);
+        println!(
+            "{}",
+            generate::sentences_table(&sentences, &slr_table, &interned, &extended_grammar)
+                .render(table_format)
+        );
+        println!("{} sentences", sentences.len());
+    }
+
+    // same language as another grammar, up to a length
+    if let Some(path) = arg_value(&args, "--compare") {
+        let bound: usize =
+            arg_value(&args, "--bound").map_or(8, |bound| bound.parse().expect("Invalid bound"));
+        let other_txt = fs::read_to_string(&path).expect("Error reading file (check file path)");
+        let mut other_grammar = HashMap::new();
+        let mut other_extras = HashMap::new();
+        let mut other_first = "".to_string();
+        process_str(
+            other_txt,
+            &mut other_grammar,
+            &mut other_extras,
+            &mut other_first,
+        );
+        let other_interned = InternedGrammar::new(&build_extended_grammar(
+            &other_grammar,
+            &other_extras,
+            &other_first,
+        ));
+        let enumerate_names = |interned: &InternedGrammar| -> Vec<Vec<String>> {
+            generate::enumerate(interned, bound)
+                .iter()
+                .map(|sentence| interned_names(interned, sentence))
+                .collect()
+        };
+        let sentences = enumerate_names(&interned);
+        let other_sentences = enumerate_names(&other_interned);
+        println!("\n- - -");
+        println!("EQUIVALENCE WITH {} UP TO {} TOKENS\n", path, bound);
+        println!(
+            "This grammar: {} sentences, {}: {} sentences",
+            sentences.len(),
+            path,
+            other_sentences.len()
+        );
+        match generate::first_difference(&sentences, &other_sentences) {
+            None => println!("Same language up to {} tokens", bound),
+            Some((sentence, in_this)) => {
+                let (generated_by, not_by) = if in_this {
+                    ("this grammar", path.as_str())
+                } else {
+                    (path.as_str(), "this grammar")
+                };
+                println!(
+                    "Different languages: \"{}\" (length {}) is generated by {} but not by {}",
+                    if sentence.is_empty() {
+                        "' '".to_string()
+                    } else {
+                        sentence.join(" ")
+                    },
+                    sentence.len(),
+                    generated_by,
+                    not_by
+                );
+            }
+        }
+    }
+
+    // a sentence with two parse trees, up to a length
+    if let Some(max_length) = arg_value(&args, "--ambiguity") {
+        let max_length: usize = max_length.parse().expect("Invalid length");
+        println!("\n- - -");
+        println!("AMBIGUITY UP TO {} TOKENS\n", max_length);
+        match ambiguity::find_ambiguity(&interned, max_length) {
+            Some((sentence, first, second)) => {
+                println!(
+                    "The grammar is ambiguous: \"{}\" has two parse trees",
+                    interned_names(&interned, &sentence).join(" ")
+                );
+                for tree in [first, second] {
+                    let rules: Vec<String> = tree
+                        .leftmost_derivation()
+                        .iter()
+                        .map(|rule| rule.to_string())
+                        .collect();
+                    println!(
+                        "  {}\n    leftmost derivation: rules {}",
+                        tree.to_string(&interned, &extended_grammar),
+                        rules.join(", ")
+                    );
+                }
+            }
+            None => {
+                println!(
+                    "No sentence up to {} tokens has two parse trees",
+                    max_length
+                );
+                if slr_table.iter().any(|row| !row.conflicts.is_empty()) {
+                    println!("The SLR(1) conflicts may only need more lookahead (see --classify)");
+                }
+            }
+        }
+    }
+
+    // LL(1) check and recursive descent parser
+    let descent_path = arg_value(&args, "--descent");
+    if args.iter().any(|arg| arg == "--ll1") || descent_path.is_some() {
+        let conflicts = ll::ll1_conflicts(&interned);
+        println!("\n- - -");
+        println!("LL(1)\n");
+        println!("{}", ll::ll1_table(&interned).render(table_format));
+        if conflicts.is_empty() {
+            println!("The grammar is LL(1)");
+        } else {
+            println!("The grammar is not LL(1):");
+            for conflict in conflicts.iter() {
+                println!("  {}", ll::conflict_to_string(conflict, &extended_grammar));
+            }
+        }
+        if let Some(path) = descent_path {
+            if conflicts.is_empty() {
+                let code = ll::descent_parser(&interned, &extended_grammar);
+                fs::write(&path, code).expect("Error writing recursive descent parser");
+                println!("Recursive descent parser written to {}", path);
+            } else {
+                println!("No recursive descent parser generated");
+            }
+        }
+    }
+
+    // table with unit reductions bypassed and equivalent states merged
+    if args.iter().any(|arg| arg == "--optimize") {
+        let optimized = optimize::optimize_table(&slr_table, &interned);
+        let optimized_traces: Vec<driver::ParseResult> = traces
+            .iter()
+            .map(|(input, _)| {
+                let tokens: Vec<String> = input_tokens(input)
+                    .map(|(tokens, _)| tokens)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(kind, _)| kind)
+                    .collect();
+                driver::parse_tokens(&optimized.table, &interned, &extended_grammar, &tokens)
+            })
+            .collect();
+        println!("\n- - -");
+        println!("OPTIMIZED TABLE\n");
+        let state_map: Vec<String> = optimized
+            .state_of
+            .iter()
+            .enumerate()
+            .map(|(old, new)| match new {
+                Some(new) => format!("{} -> {}", old, new),
+                None => format!("{} -> removed", old),
+            })
+            .collect();
+        println!("States: {}\n", state_map.join(", "));
+        println!(
+            "{}\n",
+            slr_table_to_string(&optimized.table, &interned, table_format)
+        );
+        println!(
+            "{}",
+            optimize::savings_table(&slr_table, &optimized, &traces, &optimized_traces)
+                .render(table_format)
+        );
+    }
+
+    // standalone HTML report
+    if let Some(path) = arg_value(&args, "--html") {
+        let page = report::html_report(
+            &grammar,
+            &first_non_terminal,
+            &interned,
+            &extended_grammar,
+            &slr,
+            &slr_table,
+            &traces,
+        );
+        fs::write(&path, page).expect("Error writing HTML report");
+        println!("Report written to {}", path);
+    }
+
+    // LaTeX version of the tables and item sets
+    if let Some(path) = arg_value(&args, "--latex") {
+        let doc = latex::latex_document(&interned, &extended_grammar, &slr, &slr_table);
+        fs::write(&path, doc).expect("Error writing LaTeX file");
+        println!("LaTeX written to {}", path);
+    }
+
+    // parser module generated from the table
+    if let Some(path) = arg_value(&args, "--rust") {
+        let code = codegen::rust_parser(&interned, &extended_grammar, &slr_table);
+        fs::write(&path, code).expect("Error writing Rust parser");
+        println!("Rust parser written to {}", path);
+    }
+
+    // C header and source generated from the same table, `--c dir/name` writes
+    // `dir/name.h` and `dir/name.c`
+    if let Some(path) = arg_value(&args, "--c") {
+        let path = std::path::Path::new(&path);
+        let name = path
+            .file_stem()
+            .map_or("parser".to_string(), |s| s.to_string_lossy().to_string());
+        let (header, source) = codegen_c::c_parser(&name, &interned, &extended_grammar, &slr_table);
+        let header_path = path.with_extension("h");
+        let source_path = path.with_extension("c");
+        fs::write(&header_path, header).expect("Error writing C header");
+        fs::write(&source_path, source).expect("Error writing C source");
+        println!(
+            "C parser written to {} and {}",
+            header_path.display(),
+            source_path.display()
+        );
+    }
+
+    // export the whole analysis as JSON
+    if let Some(path) = arg_value(&args, "--json") {
+        let doc = json::analysis_to_json(
+            &grammar,
+            &first_non_terminal,
+            &terminals,
+            &non_terminals,
+            &extended_grammar,
+            &slr,
+            &slr_table,
+        );
+        write_json(&path, &doc);
+    }
 
     //print_slr(&slr);
 }
+
+/// Augmented grammar of a grammar file, for the tests of the other modules.
+#[cfg(test)]
+fn extended_grammar_of(txt: &str) -> Vec<SlrRule> {
+    let mut grammar = HashMap::new();
+    let mut extras = HashMap::new();
+    let mut first_non_terminal = String::new();
+    process_str(
+        txt.to_string(),
+        &mut grammar,
+        &mut extras,
+        &mut first_non_terminal,
+    );
+    build_extended_grammar(&grammar, &extras, &first_non_terminal)
+}
+
+/// LR(0) automaton and SLR(1) table of a grammar, for the tests of the other modules.
+#[cfg(test)]
+fn slr_table_of(grammar: &InternedGrammar) -> (Vec<SlrState>, Vec<SlrRow>) {
+    let mut slr = Vec::new();
+    build_slr(&mut slr, grammar);
+    let mut table = Vec::new();
+    build_slr_table(&slr, &mut table, grammar);
+    (slr, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Items of a state as printed in the goldens, kernel first.
+    fn state_items(slr: &[SlrState], extended_grammar: &[SlrRule], state: usize) -> Vec<String> {
+        slr[state]
+            .items()
+            .map(|item| item.to_string(extended_grammar))
+            .collect()
+    }
+
+    #[test]
+    fn items_move_the_dot_by_index() {
+        let extended_grammar = extended_grammar_of("2\nE -> E + T\nE -> T");
+        let grammar = InternedGrammar::new(&extended_grammar);
+        // 1. E -> E + T
+        let item = SlrItem::new(1);
+        assert_eq!(item.get_reading_symbol(&grammar), grammar.id("E"));
+        assert_eq!(item.to_string(&extended_grammar), "1. E -> • E + T");
+        let item = item.advance().advance();
+        assert_eq!(item.get_reading_symbol(&grammar), grammar.id("T"));
+        assert_eq!(item.to_string(&extended_grammar), "1. E -> E + • T");
+        let item = item.advance();
+        assert!(item.is_complete(&grammar));
+        assert_eq!(item.get_reading_symbol(&grammar), None);
+        assert_eq!(item.to_string(&extended_grammar), "1. E -> E + T •");
+    }
+
+    #[test]
+    fn epsilon_items_are_complete_at_dot_zero() {
+        let extended_grammar = extended_grammar_of("2\nA -> a A\nA -> ' '");
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let item = SlrItem::new(2);
+        assert!(item.is_complete(&grammar));
+        assert_eq!(item.get_reading_symbol(&grammar), None);
+        assert_eq!(item.to_string(&extended_grammar), "2. A -> •");
+    }
+
+    #[test]
+    fn closure_follows_chains_of_non_terminals() {
+        // same states as test_txts/output_chain.txt
+        let extended_grammar = extended_grammar_of(include_str!("../test_txts/input_chain.txt"));
+        let (slr, _) = slr_table_of(&InternedGrammar::new(&extended_grammar));
+        assert_eq!(slr.len(), 9);
+        assert_eq!(
+            state_items(&slr, &extended_grammar, 0),
+            [
+                "0. S' -> • S",
+                "1. S -> • A x",
+                "2. A -> • B y",
+                "3. B -> • C z",
+                "4. C -> • c",
+            ]
+        );
+        assert_eq!(state_items(&slr, &extended_grammar, 3), ["2. A -> B • y"]);
+    }
+
+    #[test]
+    fn closure_adds_epsilon_items_at_the_end_of_a_chain() {
+        // same states as test_txts/output_chain2.txt
+        let extended_grammar = extended_grammar_of(include_str!("../test_txts/input_chain2.txt"));
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (slr, table) = slr_table_of(&grammar);
+        assert_eq!(slr.len(), 10);
+        assert_eq!(
+            state_items(&slr, &extended_grammar, 0),
+            [
+                "0. S' -> • S",
+                "1. S -> • A",
+                "2. A -> • B a",
+                "3. B -> • C b",
+                "4. C -> • D c",
+                "5. D -> • d",
+                "6. D -> •",
+            ]
+        );
+        // the epsilon item completes in state 0, so D reduces on its follow
+        assert_eq!(table[0].actions[&grammar.id("c").unwrap()], Action::R(6));
+    }
+}
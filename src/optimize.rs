@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::driver::ParseResult;
+use crate::symbols::InternedGrammar;
+use crate::table::TextTable;
+use crate::{Action, SlrRow};
+
+/// Parse table after the optional post-processing, with what each step saved.
+pub struct OptimizedTable {
+    pub table: Vec<SlrRow>,
+    /// New state of every original state (`None` if it was removed).
+    pub state_of: Vec<Option<usize>>,
+    /// Gotos redirected past a state that only reduces by a unit rule.
+    pub bypassed_reductions: usize,
+    /// States no longer reachable once the unit reductions are bypassed.
+    pub removed_states: usize,
+    /// States merged with an equivalent one.
+    pub merged_states: usize,
+}
+
+/// Eliminates unit reductions and then merges equivalent states.
+pub fn optimize_table(table: &[SlrRow], grammar: &InternedGrammar) -> OptimizedTable {
+    let (bypassed, bypassed_reductions) = eliminate_unit_reductions(table, grammar);
+    let (reachable, reachable_of) = remove_unreachable(&bypassed);
+    let (minimized, class_of) = merge_equivalent_states(&reachable);
+
+    let state_of = reachable_of
+        .iter()
+        .map(|state| state.map(|s| class_of[s]))
+        .collect();
+    OptimizedTable {
+        removed_states: table.len() - reachable.len(),
+        merged_states: reachable.len() - minimized.len(),
+        table: minimized,
+        state_of,
+        bypassed_reductions,
+    }
+}
+
+/// Rule `A -> B` (`B` a non terminal) that a state reduces by on every lookahead it has an
+/// action for, if that's all the state does.
+fn unit_reduction(row: &SlrRow, grammar: &InternedGrammar) -> Option<usize> {
+    if !row.gotos.is_empty() || !row.conflicts.is_empty() {
+        return None;
+    }
+    let mut rules = row.actions.values();
+    let rule = match rules.next()? {
+        Action::R(rule) => *rule,
+        _ => return None,
+    };
+    if rules.any(|action| *action != Action::R(rule)) {
+        return None;
+    }
+    let body = &grammar.rules[rule].body;
+    let is_unit = body.len() == 1 && !grammar.is_terminal(body[0]);
+    if is_unit {
+        Some(rule)
+    } else {
+        None
+    }
+}
+
+/// For a goto `p --B--> q` where `q` only reduces by `A -> B`, the parser would reduce and
+/// then take `p --A--> t`, so the goto can point to `t` directly. This is only done when `t`
+/// has no action on a lookahead `q` would have rejected, so errors are still found before
+/// anything else is shifted. Chains (`F` to `T` to `E`) are followed while this holds.
+fn eliminate_unit_reductions(table: &[SlrRow], grammar: &InternedGrammar) -> (Vec<SlrRow>, usize) {
+    let unit_rules: Vec<Option<usize>> = table
+        .iter()
+        .map(|row| unit_reduction(row, grammar))
+        .collect();
+
+    let mut ret: Vec<SlrRow> = table.to_vec();
+    let mut bypassed = 0;
+    for (state, row) in table.iter().enumerate() {
+        for (symbol, goto) in row.gotos.iter() {
+            let mut target = *goto;
+            let mut visited = HashSet::from([target]);
+            while let Some(rule) = unit_rules[target] {
+                let next = match row.gotos.get(&grammar.rules[rule].origin) {
+                    Some(next) => *next,
+                    None => break,
+                };
+                let safe = table[next]
+                    .actions
+                    .keys()
+                    .all(|lookahead| table[target].actions.contains_key(lookahead));
+                if !safe || !visited.insert(next) {
+                    break;
+                }
+                target = next;
+                bypassed += 1;
+            }
+            ret[state].gotos.insert(*symbol, target);
+        }
+    }
+    (ret, bypassed)
+}
+
+/// Keeps the states reachable from state 0, renumbered in their original order.
+fn remove_unreachable(table: &[SlrRow]) -> (Vec<SlrRow>, Vec<Option<usize>>) {
+    let mut reachable = vec![false; table.len()];
+    let mut pending = vec![0];
+    while let Some(state) = pending.pop() {
+        if reachable[state] {
+            continue;
+        }
+        reachable[state] = true;
+        let row = &table[state];
+        for action in row.actions.values().chain(row.conflicts.values().flatten()) {
+            if let Action::S(next) = action {
+                pending.push(*next);
+            }
+        }
+        pending.extend(row.gotos.values());
+    }
+
+    let mut new_of = vec![None; table.len()];
+    let mut count = 0;
+    for (state, is_reachable) in reachable.iter().enumerate() {
+        if *is_reachable {
+            new_of[state] = Some(count);
+            count += 1;
+        }
+    }
+    let ret = table
+        .iter()
+        .enumerate()
+        .filter(|(state, _)| reachable[*state])
+        .map(|(_, row)| renumber_row(row, |s| new_of[s].unwrap()))
+        .collect();
+    (ret, new_of)
+}
+
+/// Moore style refinement: states start in a single class and are split until every class
+/// only has states whose rows are equal once targets are replaced by their class. Classes
+/// are numbered by their first state, so state 0 stays 0.
+fn merge_equivalent_states(table: &[SlrRow]) -> (Vec<SlrRow>, Vec<usize>) {
+    let mut class_of = vec![0; table.len()];
+    let mut n_classes = 1;
+    loop {
+        let mut classes: HashMap<(usize, Vec<String>), usize> = HashMap::new();
+        let mut new_class_of = Vec::new();
+        for (state, row) in table.iter().enumerate() {
+            let key = (class_of[state], row_signature(row, &class_of));
+            let next = classes.len();
+            new_class_of.push(*classes.entry(key).or_insert(next));
+        }
+        class_of = new_class_of;
+        if classes.len() == n_classes {
+            break;
+        }
+        n_classes = classes.len();
+    }
+
+    let mut ret: Vec<Option<SlrRow>> = vec![None; n_classes];
+    for (state, row) in table.iter().enumerate() {
+        if ret[class_of[state]].is_none() {
+            ret[class_of[state]] = Some(renumber_row(row, |s| class_of[s]));
+        }
+    }
+    (ret.into_iter().flatten().collect(), class_of)
+}
+
+/// Sorted cells of a row with every target state replaced by its class.
+fn row_signature(row: &SlrRow, class_of: &[usize]) -> Vec<String> {
+    let renumbered = renumber_row(row, |s| class_of[s]);
+    let mut ret: Vec<String> = Vec::new();
+    for (symbol, action) in renumbered.actions.iter() {
+        ret.push(format!("{} {}", symbol, action));
+    }
+    for (symbol, goto) in renumbered.gotos.iter() {
+        ret.push(format!("{} {}", symbol, goto));
+    }
+    for (symbol, actions) in renumbered.conflicts.iter() {
+        let actions: Vec<String> = actions.iter().map(|a| a.to_string()).collect();
+        ret.push(format!("{} {}", symbol, actions.join("/")));
+    }
+    ret.sort();
+    ret
+}
+
+fn renumber_row(row: &SlrRow, new_state: impl Fn(usize) -> usize) -> SlrRow {
+    let renumber = |action: &Action| match action {
+        Action::S(next) => Action::S(new_state(*next)),
+        other => other.clone(),
+    };
+    SlrRow {
+        actions: row
+            .actions
+            .iter()
+            .map(|(symbol, action)| (*symbol, renumber(action)))
+            .collect(),
+        gotos: row
+            .gotos
+            .iter()
+            .map(|(symbol, goto)| (*symbol, new_state(*goto)))
+            .collect(),
+        conflicts: row
+            .conflicts
+            .iter()
+            .map(|(symbol, actions)| (*symbol, actions.iter().map(renumber).collect()))
+            .collect(),
+    }
+}
+
+/// Number of reductions done in a parse trace.
+pub fn reduce_steps(result: &ParseResult) -> usize {
+    result
+        .steps
+        .iter()
+        .filter(|step| step.action.starts_with("reduce"))
+        .count()
+}
+
+/// Savings of the post-processing: states, and reduce steps for every parsed input, comparing
+/// its trace with the original table and with the optimized one.
+pub fn savings_table(
+    original: &[SlrRow],
+    optimized: &OptimizedTable,
+    traces: &[(String, ParseResult)],
+    optimized_traces: &[ParseResult],
+) -> TextTable {
+    let headers = vec![
+        "".to_string(),
+        "before".to_string(),
+        "after".to_string(),
+        "saved".to_string(),
+    ];
+    let count_row = |name: &str, before: usize, after: usize| {
+        vec![
+            name.to_string(),
+            before.to_string(),
+            after.to_string(),
+            (before as i64 - after as i64).to_string(),
+        ]
+    };
+
+    let mut rows = vec![
+        count_row("states", original.len(), optimized.table.len()),
+        vec![
+            "unit reductions bypassed".to_string(),
+            "".to_string(),
+            "".to_string(),
+            optimized.bypassed_reductions.to_string(),
+        ],
+        vec![
+            "unreachable states removed".to_string(),
+            "".to_string(),
+            "".to_string(),
+            optimized.removed_states.to_string(),
+        ],
+        vec![
+            "equivalent states merged".to_string(),
+            "".to_string(),
+            "".to_string(),
+            optimized.merged_states.to_string(),
+        ],
+    ];
+    for ((input, before), after) in traces.iter().zip(optimized_traces.iter()) {
+        rows.push(count_row(
+            &format!("reduce steps for \"{}\"", input),
+            reduce_steps(before),
+            reduce_steps(after),
+        ));
+    }
+    TextTable { headers, rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver, SlrRule};
+
+    fn table_of(txt: &str) -> (Vec<SlrRule>, InternedGrammar, Vec<SlrRow>) {
+        let extended_grammar = crate::extended_grammar_of(txt);
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        (extended_grammar, grammar, table)
+    }
+
+    /// Every string of up to `max_length` terminals, accepted or not.
+    fn all_strings(grammar: &InternedGrammar, max_length: usize) -> Vec<Vec<String>> {
+        let mut ret = vec![Vec::new()];
+        let mut last = vec![Vec::new()];
+        for _ in 0..max_length {
+            last = last
+                .iter()
+                .flat_map(|prefix: &Vec<String>| {
+                    (0..grammar.eof)
+                        .map(move |t| [prefix.clone(), vec![grammar.name(t).clone()]].concat())
+                })
+                .collect();
+            ret.extend(last.iter().cloned());
+        }
+        ret
+    }
+
+    #[test]
+    fn optimized_table_accepts_the_same_inputs() {
+        for txt in [
+            include_str!("../test_txts/input2.txt"),
+            include_str!("../test_txts/input4.txt"),
+        ] {
+            let (extended_grammar, grammar, table) = table_of(txt);
+            let optimized = optimize_table(&table, &grammar);
+            assert!(optimized.bypassed_reductions > 0);
+            assert!(optimized.table.len() < table.len());
+
+            let mut accepted = 0;
+            for tokens in all_strings(&grammar, 5) {
+                let parse = |table: &[SlrRow]| {
+                    driver::parse_tokens(table, &grammar, &extended_grammar, &tokens)
+                };
+                let (before, after) = (parse(&table), parse(&optimized.table));
+                assert_eq!(before.accepted, after.accepted, "{:?}", tokens);
+                assert_eq!(before.errors.len(), after.errors.len(), "{:?}", tokens);
+                if before.accepted {
+                    accepted += 1;
+                    assert!(reduce_steps(&after) <= reduce_steps(&before));
+                }
+            }
+            assert!(accepted > 0);
+        }
+    }
+
+    #[test]
+    fn unit_reductions_are_bypassed() {
+        let (extended_grammar, grammar, table) = table_of(include_str!("../test_txts/input2.txt"));
+        let optimized = optimize_table(&table, &grammar);
+        let tokens = ["id".to_string()];
+        let before = driver::parse_tokens(&table, &grammar, &extended_grammar, &tokens);
+        let after = driver::parse_tokens(&optimized.table, &grammar, &extended_grammar, &tokens);
+        // the goto on F skips T -> F; E -> T stays, since its state also shifts `*`
+        assert_eq!(reduce_steps(&before), 3);
+        assert_eq!(reduce_steps(&after), 2);
+        assert_eq!(optimized.state_of[0], Some(0));
+    }
+}
@@ -0,0 +1,622 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+
+use crate::table::TextTable;
+
+/// Set of characters stored as sorted, disjoint and non adjacent ranges.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct CharClass {
+    pub ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    pub fn new(mut ranges: Vec<(char, char)>) -> CharClass {
+        ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::new();
+        for (lo, hi) in ranges {
+            match merged.last_mut() {
+                Some(last) if lo as u32 <= last.1 as u32 + 1 => {
+                    last.1 = last.1.max(hi);
+                }
+                _ => merged.push((lo, hi)),
+            }
+        }
+        CharClass { ranges: merged }
+    }
+
+    pub fn single(chr: char) -> CharClass {
+        CharClass {
+            ranges: vec![(chr, chr)],
+        }
+    }
+
+    /// Every character but the ones in the class.
+    pub fn complement(&self) -> CharClass {
+        let mut ranges = Vec::new();
+        let mut next = Some('\0');
+        for (lo, hi) in self.ranges.iter() {
+            if let Some(from) = next {
+                if from < *lo {
+                    ranges.push((from, prev_char(*lo)));
+                }
+            }
+            next = next_char(*hi);
+        }
+        if let Some(from) = next {
+            ranges.push((from, char::MAX));
+        }
+        CharClass { ranges }
+    }
+
+    pub fn contains(&self, chr: char) -> bool {
+        self.ranges.iter().any(|(lo, hi)| *lo <= chr && chr <= *hi)
+    }
+}
+
+fn next_char(chr: char) -> Option<char> {
+    match chr {
+        char::MAX => None,
+        '\u{d7ff}' => Some('\u{e000}'),
+        _ => char::from_u32(chr as u32 + 1),
+    }
+}
+
+fn prev_char(chr: char) -> char {
+    match chr {
+        '\u{e000}' => '\u{d7ff}',
+        _ => char::from_u32(chr as u32 - 1).unwrap(),
+    }
+}
+
+/// Character as written in tables and labels: printable characters as they are, the rest
+/// escaped.
+fn show_char(chr: char) -> String {
+    match chr {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        ' ' => "' '".to_string(),
+        '\0' => "\\0".to_string(),
+        char::MAX => "MAX".to_string(),
+        c if c.is_control() => format!("\\u{{{:x}}}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+impl fmt::Display for CharClass {
+    /// A single character is written alone, anything else as `[a-z_]`, or as `[^\n]` if
+    /// that is shorter.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let [(lo, hi)] = self.ranges[..] {
+            if lo == hi {
+                return write!(f, "{}", show_char(lo));
+            }
+        }
+        let complement = self.complement();
+        let (prefix, ranges) = if complement.ranges.len() < self.ranges.len() {
+            ("[^", &complement.ranges)
+        } else {
+            ("[", &self.ranges)
+        };
+        write!(f, "{}", prefix)?;
+        for (lo, hi) in ranges.iter() {
+            if lo == hi {
+                write!(f, "{}", show_char(*lo))?;
+            } else {
+                write!(f, "{}-{}", show_char(*lo), show_char(*hi))?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// Parsed regular expression.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Regex {
+    /// Matches the empty string.
+    Empty,
+    Class(CharClass),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Star(Box<Regex>),
+    Plus(Box<Regex>),
+    Optional(Box<Regex>),
+}
+
+/// Parses a regular expression. The syntax is the usual one: `|`, `*`, `+`, `?`,
+/// parentheses, `.` (any character but a newline), classes like `[a-z_]` or `[^"]`, escapes
+/// (`\n`, `\t`, `\d`, `\w`, `\s` and `\` before any symbol) and `"..."` for literal text.
+pub fn parse_regex(txt: &str) -> Result<Regex, String> {
+    let mut parser = RegexParser {
+        chars: txt.chars().collect(),
+        pos: 0,
+    };
+    let regex = parser.alternation()?;
+    match parser.peek() {
+        None => Ok(regex),
+        Some(')') => Err(format!("unbalanced ')' at {}", parser.pos)),
+        Some(chr) => Err(format!("unexpected '{}' at {}", chr, parser.pos)),
+    }
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl RegexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let chr = self.peek();
+        self.pos += 1;
+        chr
+    }
+
+    fn alternation(&mut self) -> Result<Regex, String> {
+        let mut options = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            options.push(self.concatenation()?);
+        }
+        Ok(if options.len() == 1 {
+            options.pop().unwrap()
+        } else {
+            Regex::Alt(options)
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Regex, String> {
+        let mut parts = Vec::new();
+        while let Some(chr) = self.peek() {
+            if chr == '|' || chr == ')' {
+                break;
+            }
+            let mut part = self.atom()?;
+            while let Some(op) = self.peek() {
+                part = match op {
+                    '*' => Regex::Star(Box::new(part)),
+                    '+' => Regex::Plus(Box::new(part)),
+                    '?' => Regex::Optional(Box::new(part)),
+                    _ => break,
+                };
+                self.pos += 1;
+            }
+            parts.push(part);
+        }
+        Ok(match parts.len() {
+            0 => Regex::Empty,
+            1 => parts.pop().unwrap(),
+            _ => Regex::Concat(parts),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Regex, String> {
+        let start = self.pos;
+        match self.next() {
+            Some('(') => {
+                let inner = self.alternation()?;
+                if self.next() != Some(')') {
+                    return Err(format!("unclosed '(' at {}", start));
+                }
+                Ok(inner)
+            }
+            Some('[') => self.class(start),
+            Some('"') => {
+                let mut parts = Vec::new();
+                loop {
+                    match self.next() {
+                        Some('"') => break,
+                        Some('\\') => parts.push(Regex::Class(self.escape()?)),
+                        Some(chr) => parts.push(Regex::Class(CharClass::single(chr))),
+                        None => return Err(format!("unclosed '\"' at {}", start)),
+                    }
+                }
+                Ok(match parts.len() {
+                    0 => Regex::Empty,
+                    1 => parts.pop().unwrap(),
+                    _ => Regex::Concat(parts),
+                })
+            }
+            Some('.') => Ok(Regex::Class(CharClass::single('\n').complement())),
+            Some('\\') => Ok(Regex::Class(self.escape()?)),
+            Some(chr @ ('*' | '+' | '?')) => {
+                Err(format!("'{}' with nothing to repeat at {}", chr, start))
+            }
+            Some(chr) => Ok(Regex::Class(CharClass::single(chr))),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    /// Character after a `\`.
+    fn escape(&mut self) -> Result<CharClass, String> {
+        let digits = CharClass::new(vec![('0', '9')]);
+        let word = CharClass::new(vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]);
+        let space = CharClass::new(vec![(' ', ' '), ('\t', '\r')]);
+        Ok(match self.next() {
+            Some('n') => CharClass::single('\n'),
+            Some('t') => CharClass::single('\t'),
+            Some('r') => CharClass::single('\r'),
+            Some('d') => digits,
+            Some('D') => digits.complement(),
+            Some('w') => word,
+            Some('W') => word.complement(),
+            Some('s') => space,
+            Some('S') => space.complement(),
+            Some(chr) => CharClass::single(chr),
+            None => return Err("escape at the end".to_string()),
+        })
+    }
+
+    /// Class after its `[`.
+    fn class(&mut self, start: usize) -> Result<Regex, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let lo = match self.next() {
+                Some(']') if !first => break,
+                Some('\\') => {
+                    let escaped = self.escape()?;
+                    match escaped.ranges[..] {
+                        [(lo, hi)] if lo == hi => lo,
+                        _ => {
+                            ranges.extend(escaped.ranges);
+                            first = false;
+                            continue;
+                        }
+                    }
+                }
+                Some(chr) => chr,
+                None => return Err(format!("unclosed '[' at {}", start)),
+            };
+            first = false;
+            let is_range = self.peek() == Some('-')
+                && !matches!(self.chars.get(self.pos + 1), Some(']') | None);
+            if is_range {
+                self.pos += 1;
+                let hi = match self.next() {
+                    Some('\\') => self.escape()?.ranges[0].0,
+                    Some(chr) => chr,
+                    None => return Err(format!("unclosed '[' at {}", start)),
+                };
+                if hi < lo {
+                    return Err(format!("empty range {}-{} at {}", lo, hi, start));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        let class = CharClass::new(ranges);
+        Ok(Regex::Class(if negated {
+            class.complement()
+        } else {
+            class
+        }))
+    }
+}
+
+/// State of a Thompson NFA: epsilon moves and moves on a class of characters.
+#[derive(Clone, Default, Debug)]
+pub struct NfaState {
+    pub epsilon: Vec<usize>,
+    pub moves: Vec<(CharClass, usize)>,
+}
+
+/// Thompson NFA. Several expressions can share one automaton: `accepting` maps each final
+/// state to the number of the expression it recognizes.
+#[derive(Clone, Debug)]
+pub struct Nfa {
+    pub states: Vec<NfaState>,
+    pub start: usize,
+    pub accepting: BTreeMap<usize, usize>,
+}
+
+impl Nfa {
+    /// NFA for a list of expressions: a new start state with an epsilon move into each of
+    /// them, whose final state accepts with the position of the expression in the list.
+    pub fn from_regexes(regexes: &[Regex]) -> Nfa {
+        let mut nfa = Nfa {
+            states: Vec::new(),
+            start: 0,
+            accepting: BTreeMap::new(),
+        };
+        if let [regex] = regexes {
+            // no need for an extra start state
+            let (_, end) = nfa.build(regex);
+            nfa.accepting.insert(end, 0);
+            return nfa;
+        }
+        nfa.add_state();
+        for (num, regex) in regexes.iter().enumerate() {
+            let (start, end) = nfa.build(regex);
+            nfa.states[0].epsilon.push(start);
+            nfa.accepting.insert(end, num);
+        }
+        nfa
+    }
+
+    fn add_state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    /// Thompson construction: returns the start and final states of the fragment for `regex`.
+    fn build(&mut self, regex: &Regex) -> (usize, usize) {
+        let start = self.add_state();
+        let end;
+        match regex {
+            Regex::Empty => {
+                end = self.add_state();
+                self.states[start].epsilon.push(end);
+            }
+            Regex::Class(class) => {
+                end = self.add_state();
+                self.states[start].moves.push((class.clone(), end));
+            }
+            Regex::Concat(parts) => {
+                let mut last = start;
+                for part in parts.iter() {
+                    let (part_start, part_end) = self.build(part);
+                    self.states[last].epsilon.push(part_start);
+                    last = part_end;
+                }
+                end = last;
+            }
+            Regex::Alt(options) => {
+                let ends: Vec<usize> = options
+                    .iter()
+                    .map(|option| {
+                        let (option_start, option_end) = self.build(option);
+                        self.states[start].epsilon.push(option_start);
+                        option_end
+                    })
+                    .collect();
+                end = self.add_state();
+                for option_end in ends {
+                    self.states[option_end].epsilon.push(end);
+                }
+            }
+            Regex::Star(inner) | Regex::Plus(inner) | Regex::Optional(inner) => {
+                let (inner_start, inner_end) = self.build(inner);
+                end = self.add_state();
+                self.states[start].epsilon.push(inner_start);
+                self.states[inner_end].epsilon.push(end);
+                if !matches!(regex, Regex::Plus(_)) {
+                    self.states[start].epsilon.push(end);
+                }
+                if !matches!(regex, Regex::Optional(_)) {
+                    self.states[inner_end].epsilon.push(inner_start);
+                }
+            }
+        }
+        (start, end)
+    }
+
+    /// States reachable from `states` with epsilon moves, sorted.
+    pub fn epsilon_closure(&self, states: &[usize]) -> Vec<usize> {
+        let mut seen = vec![false; self.states.len()];
+        let mut pending = states.to_vec();
+        let mut ret = Vec::new();
+        while let Some(state) = pending.pop() {
+            if seen[state] {
+                continue;
+            }
+            seen[state] = true;
+            ret.push(state);
+            pending.extend(self.states[state].epsilon.iter().copied());
+        }
+        ret.sort();
+        ret
+    }
+
+    /// States reached from `states` reading `chr`, before the epsilon closure.
+    pub fn step(&self, states: &[usize], chr: char) -> Vec<usize> {
+        let mut ret: Vec<usize> = states
+            .iter()
+            .flat_map(|state| self.states[*state].moves.iter())
+            .filter(|(class, _)| class.contains(chr))
+            .map(|(_, next)| *next)
+            .collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    /// Expression accepted by a set of states; the lowest number wins.
+    pub fn accepted_by(&self, states: &[usize]) -> Option<usize> {
+        states
+            .iter()
+            .filter_map(|state| self.accepting.get(state))
+            .min()
+            .copied()
+    }
+}
+
+impl Nfa {
+    /// Classes read by some move, sorted, to use as table columns.
+    fn move_classes(&self) -> Vec<CharClass> {
+        let mut classes: Vec<CharClass> = self
+            .states
+            .iter()
+            .flat_map(|state| state.moves.iter().map(|(class, _)| class.clone()))
+            .collect();
+        classes.sort();
+        classes.dedup();
+        classes
+    }
+
+    /// Transition table: one row per state, with an ε column and one column per class read by
+    /// the moves. Final states show in the last column the name of what they accept (from
+    /// `accept_names`, indexed by expression number).
+    pub fn to_table(&self, accept_names: &[String]) -> TextTable {
+        let classes = self.move_classes();
+        let mut headers = vec!["state".to_string(), "ε".to_string()];
+        headers.extend(classes.iter().map(|class| class.to_string()));
+        headers.push("accepts".to_string());
+
+        let rows = self
+            .states
+            .iter()
+            .enumerate()
+            .map(|(num, state)| {
+                let mut row = vec![state_label(num, num == self.start)];
+                row.push(join_states(&state.epsilon));
+                for class in classes.iter() {
+                    let targets: Vec<usize> = state
+                        .moves
+                        .iter()
+                        .filter(|(move_class, _)| move_class == class)
+                        .map(|(_, next)| *next)
+                        .collect();
+                    row.push(join_states(&targets));
+                }
+                row.push(
+                    self.accepting
+                        .get(&num)
+                        .map_or(String::new(), |accepted| accept_names[*accepted].clone()),
+                );
+                row
+            })
+            .collect();
+        TextTable { headers, rows }
+    }
+
+    /// Graphviz description of the automaton.
+    pub fn to_dot(&self, name: &str, accept_names: &[String]) -> String {
+        let mut ret = dot_header(name, self.start);
+        for (num, accepted) in self.accepting.iter() {
+            let _ = writeln!(
+                ret,
+                "    {} [shape=doublecircle, label=\"{}\\n{}\"];",
+                num,
+                num,
+                dot_escape(&accept_names[*accepted])
+            );
+        }
+        for (num, state) in self.states.iter().enumerate() {
+            for next in state.epsilon.iter() {
+                let _ = writeln!(ret, "    {} -> {} [label=\"ε\"];", num, next);
+            }
+            for (class, next) in state.moves.iter() {
+                let _ = writeln!(
+                    ret,
+                    "    {} -> {} [label=\"{}\"];",
+                    num,
+                    next,
+                    dot_escape(&class.to_string())
+                );
+            }
+        }
+        ret + "}\n"
+    }
+}
+
+/// State number, with an arrow if it's the start state.
+pub fn state_label(num: usize, is_start: bool) -> String {
+    if is_start {
+        format!("→ {}", num)
+    } else {
+        num.to_string()
+    }
+}
+
+fn join_states(states: &[usize]) -> String {
+    let states: Vec<String> = states.iter().map(|state| state.to_string()).collect();
+    states.join(", ")
+}
+
+/// Start of a DOT graph, with an arrow into the start state.
+pub fn dot_header(name: &str, start: usize) -> String {
+    format!(
+        "digraph {} {{\n    rankdir=LR;\n    node [shape=circle];\n    start [shape=point];\n    start -> {};\n",
+        name, start
+    )
+}
+
+/// Text for a DOT string literal.
+pub fn dot_escape(txt: &str) -> String {
+    txt.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_are_merged_and_complemented() {
+        let class = CharClass::new(vec![('d', 'f'), ('a', 'c'), ('x', 'x')]);
+        assert_eq!(class.ranges, [('a', 'f'), ('x', 'x')]);
+        assert_eq!(class.to_string(), "[a-fx]");
+        assert!(class.complement().contains('g'));
+        assert!(!class.complement().contains('x'));
+        assert_eq!(class.complement().complement(), class);
+        assert_eq!(CharClass::single('\n').complement().to_string(), "[^\\n]");
+        // the complement skips the surrogates
+        assert_eq!(
+            CharClass::new(vec![('\0', '\u{d7ff}')]).complement().ranges,
+            [('\u{e000}', char::MAX)]
+        );
+    }
+
+    #[test]
+    fn parses_the_usual_syntax() {
+        let a = || Regex::Class(CharClass::single('a'));
+        assert_eq!(
+            parse_regex("a|b*"),
+            Ok(Regex::Alt(vec![
+                a(),
+                Regex::Star(Box::new(Regex::Class(CharClass::single('b'))))
+            ]))
+        );
+        assert_eq!(
+            parse_regex("\"a+\""),
+            Ok(Regex::Concat(vec![
+                a(),
+                Regex::Class(CharClass::single('+'))
+            ]))
+        );
+        assert_eq!(
+            parse_regex("[^a-z_]"),
+            Ok(Regex::Class(
+                CharClass::new(vec![('_', '_'), ('a', 'z')]).complement()
+            ))
+        );
+        assert_eq!(parse_regex("()"), Ok(Regex::Empty));
+
+        assert_eq!(parse_regex("a)"), Err("unbalanced ')' at 1".to_string()));
+        assert_eq!(parse_regex("(a"), Err("unclosed '(' at 0".to_string()));
+        assert_eq!(
+            parse_regex("*a"),
+            Err("'*' with nothing to repeat at 0".to_string())
+        );
+        assert_eq!(
+            parse_regex("[z-a]"),
+            Err("empty range z-a at 0".to_string())
+        );
+    }
+
+    #[test]
+    fn nfa_accepts_with_the_lowest_expression() {
+        let regexes = [parse_regex("if").unwrap(), parse_regex("[a-z]+").unwrap()];
+        let nfa = Nfa::from_regexes(&regexes);
+        let run = |input: &str| {
+            let mut states = nfa.epsilon_closure(&[nfa.start]);
+            for chr in input.chars() {
+                states = nfa.epsilon_closure(&nfa.step(&states, chr));
+            }
+            nfa.accepted_by(&states)
+        };
+        assert_eq!(run("if"), Some(0));
+        assert_eq!(run("iff"), Some(1));
+        assert_eq!(run(""), None);
+        assert_eq!(run("i1"), None);
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use crate::driver::{self, ParseResult};
+use crate::symbols::InternedGrammar;
+use crate::table::{escape_html, TextTable};
+use crate::{get_firsts_follows, Action, SlrRow, SlrRule, SlrState};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { border-bottom: 2px solid #444; }
+h2 { margin-top: 2em; border-bottom: 1px solid #aaa; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #999; padding: 0.2em 0.6em; text-align: center; font-family: monospace; }
+th { background: #eee; }
+td.conflict { background: #f8c4c4; font-weight: bold; }
+.rules { font-family: monospace; white-space: pre; }
+.items { display: flex; flex-wrap: wrap; gap: 1em; }
+.item-set { border: 1px solid #999; padding: 0.5em 1em; font-family: monospace; white-space: pre; }
+.item-set h3 { margin: 0 0 0.5em 0; }
+.closure { color: #666; }
+.accepted { color: #1a7f37; }
+.rejected { color: #cf222e; }
+";
+
+/// Builds a standalone HTML page with every section of the analysis. `traces` holds the
+/// input strings that were parsed together with their results.
+#[allow(clippy::too_many_arguments)]
+pub fn html_report(
+    grammar: &HashMap<String, Vec<Vec<String>>>,
+    first_non_terminal: &String,
+    interned: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    slr: &[SlrState],
+    table: &[SlrRow],
+    traces: &[(String, ParseResult)],
+) -> String {
+    let mut ret = String::new();
+    ret += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+    ret += "<title>SLR analysis</title>\n";
+    let _ = writeln!(ret, "<style>\n{}</style>", STYLE);
+    ret += "</head>\n<body>\n";
+    let _ = writeln!(
+        ret,
+        "<h1>SLR analysis of {}</h1>",
+        escape_html(first_non_terminal)
+    );
+
+    // grammar
+    ret += "<h2>Grammar</h2>\n<div class=\"rules\">";
+    let mut origins: Vec<&String> = grammar.keys().collect();
+    origins.sort_by_key(|origin| (*origin != first_non_terminal, origin.to_string()));
+    for origin in origins {
+        for prod in grammar[origin].iter() {
+            let _ = writeln!(
+                ret,
+                "{} -> {}",
+                escape_html(origin),
+                escape_html(&prod.join(" "))
+            );
+        }
+    }
+    ret += "</div>\n";
+
+    // augmented grammar
+    ret += "<h2>Augmented grammar</h2>\n<div class=\"rules\">";
+    for rule in extended_grammar.iter() {
+        let _ = writeln!(ret, "{}", escape_html(rule.to_string().trim_end()));
+    }
+    ret += "</div>\n";
+
+    // firsts & follows
+    ret += "<h2>FIRST &amp; FOLLOW</h2>\n";
+    let first_follow = TextTable {
+        headers: vec![
+            "non terminal".to_string(),
+            "FIRST".to_string(),
+            "FOLLOW".to_string(),
+        ],
+        rows: get_firsts_follows(interned)
+            .into_iter()
+            .map(|(nterm, firsts, follows)| vec![nterm, firsts.join(", "), follows.join(", ")])
+            .collect(),
+    };
+    ret += &first_follow.to_html();
+    ret += "\n";
+
+    // item sets
+    ret += "<h2>Item sets</h2>\n<div class=\"items\">\n";
+    for (i, state) in slr.iter().enumerate() {
+        let _ = write!(ret, "<div class=\"item-set\"><h3>I{}</h3>", i);
+        for item in state.kernel.iter() {
+            let _ = writeln!(ret, "{}", escape_html(&item.to_string(extended_grammar)));
+        }
+        for item in state.extended_state.iter() {
+            let _ = writeln!(
+                ret,
+                "<span class=\"closure\">{}</span>",
+                escape_html(&item.to_string(extended_grammar))
+            );
+        }
+        ret += "</div>\n";
+    }
+    ret += "</div>\n";
+
+    // automaton
+    ret += "<h2>LR(0) automaton</h2>\n";
+    ret += &automaton_svg(slr, table, interned);
+    ret += "\n";
+
+    // parse table
+    ret += "<h2>Parse table</h2>\n";
+    ret += &parse_table_html(table, interned);
+    ret += "\n";
+
+    // traces
+    if !traces.is_empty() {
+        ret += "<h2>Parse traces</h2>\n";
+        for (input, result) in traces.iter() {
+            let (class, verdict) = if result.accepted {
+                ("accepted", "accepted")
+            } else {
+                ("rejected", "rejected")
+            };
+            let _ = writeln!(
+                ret,
+                "<h3><code>{}</code> <span class=\"{}\">{}</span></h3>",
+                escape_html(input),
+                class,
+                verdict
+            );
+            ret += &driver::trace_to_table(result).to_html();
+            ret += "\n";
+        }
+    }
+
+    ret += "</body>\n</html>\n";
+    ret
+}
+
+/// Parse table where cells with a conflict are highlighted and list every proposed action.
+fn parse_table_html(table: &[SlrRow], grammar: &InternedGrammar) -> String {
+    let model = TextTable::from_slr_table(table, grammar);
+
+    let mut ret = "<table>\n<tr>".to_string();
+    for header in model.headers.iter() {
+        let _ = write!(ret, "<th>{}</th>", escape_html(header));
+    }
+    ret += "</tr>\n";
+    for (i, row) in model.rows.iter().enumerate() {
+        ret += "<tr>";
+        for (j, cell) in row.iter().enumerate() {
+            // action columns are the terminals plus $, in id order
+            let conflict = if (1..=grammar.eof + 1).contains(&j) {
+                table[i].conflicts.get(&(j - 1))
+            } else {
+                None
+            };
+            match conflict {
+                Some(actions) => {
+                    let actions: Vec<String> = actions.iter().map(Action::to_string).collect();
+                    let _ = write!(
+                        ret,
+                        "<td class=\"conflict\" title=\"conflict\">{}</td>",
+                        escape_html(&actions.join(" / "))
+                    );
+                }
+                None => {
+                    let _ = write!(ret, "<td>{}</td>", escape_html(cell));
+                }
+            }
+        }
+        ret += "</tr>\n";
+    }
+    ret += "</table>";
+    ret
+}
+
+/// Draws the automaton as inline SVG. States are placed in columns by their distance from
+/// state 0 and accepting states get a double circle.
+fn automaton_svg(slr: &[SlrState], table: &[SlrRow], grammar: &InternedGrammar) -> String {
+    const RADIUS: f64 = 22.0;
+    const COL_WIDTH: f64 = 170.0;
+    const ROW_HEIGHT: f64 = 90.0;
+    const MARGIN: f64 = 60.0;
+
+    // transitions of each state, sorted by symbol name
+    let transitions: Vec<Vec<(String, usize)>> = slr
+        .iter()
+        .map(|state| {
+            let mut ret: Vec<(String, usize)> = state
+                .transitions
+                .iter()
+                .map(|(symbol, target)| (grammar.name(*symbol).clone(), *target))
+                .collect();
+            ret.sort();
+            ret
+        })
+        .collect();
+
+    // breadth first search to get the column of each state
+    let mut depth: Vec<Option<usize>> = vec![None; slr.len()];
+    let mut queue = VecDeque::new();
+    if !slr.is_empty() {
+        depth[0] = Some(0);
+        queue.push_back(0);
+    }
+    while let Some(idx) = queue.pop_front() {
+        for (_, target) in transitions[idx].iter() {
+            if *target < slr.len() && depth[*target].is_none() {
+                depth[*target] = Some(depth[idx].unwrap() + 1);
+                queue.push_back(*target);
+            }
+        }
+    }
+    let max_depth = depth.iter().flatten().max().copied().unwrap_or(0);
+    // unreachable states go in an extra column
+    let depth: Vec<usize> = depth.iter().map(|d| d.unwrap_or(max_depth + 1)).collect();
+
+    let mut rows_in_column: HashMap<usize, usize> = HashMap::new();
+    let mut positions = Vec::new();
+    for d in depth.iter() {
+        let row = rows_in_column.entry(*d).or_insert(0);
+        positions.push((
+            MARGIN + *d as f64 * COL_WIDTH,
+            MARGIN + *row as f64 * ROW_HEIGHT,
+        ));
+        *row += 1;
+    }
+    let width = MARGIN * 2.0 + (depth.iter().max().copied().unwrap_or(0)) as f64 * COL_WIDTH;
+    let height = MARGIN * 2.0
+        + (rows_in_column.values().max().copied().unwrap_or(1) - 1) as f64 * ROW_HEIGHT;
+
+    let mut ret = String::new();
+    let _ = writeln!(
+        ret,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" font-family=\"monospace\" font-size=\"12\">",
+        width, height
+    );
+    ret += "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto-start-reverse\"><path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"#444\"/></marker></defs>\n";
+
+    // edges
+    for (idx, state_transitions) in transitions.iter().enumerate() {
+        for (symbol, target) in state_transitions.iter() {
+            if *target >= slr.len() {
+                continue;
+            }
+            let (x1, y1) = positions[idx];
+            let (x2, y2) = positions[*target];
+            if idx == *target {
+                // loop over the state
+                let _ = writeln!(
+                    ret,
+                    "<path d=\"M {:.1} {:.1} C {:.1} {:.1}, {:.1} {:.1}, {:.1} {:.1}\" fill=\"none\" stroke=\"#444\" marker-end=\"url(#arrow)\"/>",
+                    x1 - 10.0, y1 - RADIUS + 2.0,
+                    x1 - 30.0, y1 - RADIUS - 40.0,
+                    x1 + 30.0, y1 - RADIUS - 40.0,
+                    x1 + 10.0, y1 - RADIUS + 2.0
+                );
+                let _ = writeln!(
+                    ret,
+                    "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                    x1,
+                    y1 - RADIUS - 34.0,
+                    escape_html(symbol)
+                );
+                continue;
+            }
+
+            // bend the edge to its left so edges in both directions don't overlap
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let len = (dx * dx + dy * dy).sqrt();
+            let (nx, ny) = (-dy / len, dx / len);
+            let (cx, cy) = ((x1 + x2) / 2.0 + nx * 25.0, (y1 + y2) / 2.0 + ny * 25.0);
+            // start and end on the border of the circles
+            let (sx, sy) = shorten(x1, y1, cx, cy, RADIUS);
+            let (ex, ey) = shorten(x2, y2, cx, cy, RADIUS);
+            let _ = writeln!(
+                ret,
+                "<path d=\"M {:.1} {:.1} Q {:.1} {:.1} {:.1} {:.1}\" fill=\"none\" stroke=\"#444\" marker-end=\"url(#arrow)\"/>",
+                sx, sy, cx, cy, ex, ey
+            );
+            // middle point of the curve
+            let (mx, my) = (
+                0.25 * sx + 0.5 * cx + 0.25 * ex,
+                0.25 * sy + 0.5 * cy + 0.25 * ey,
+            );
+            let _ = writeln!(
+                ret,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#06c\">{}</text>",
+                mx,
+                my - 4.0,
+                escape_html(symbol)
+            );
+        }
+    }
+
+    // states
+    for (idx, (x, y)) in positions.iter().enumerate() {
+        let accepting = table
+            .get(idx)
+            .map(|row| row.actions.values().any(|a| *a == Action::Acc))
+            .unwrap_or(false);
+        let _ = writeln!(
+            ret,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"#fff\" stroke=\"#222\"/>",
+            x, y, RADIUS
+        );
+        if accepting {
+            let _ = writeln!(
+                ret,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"none\" stroke=\"#222\"/>",
+                x,
+                y,
+                RADIUS - 4.0
+            );
+        }
+        let _ = writeln!(
+            ret,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" dominant-baseline=\"central\">I{}</text>",
+            x, y, idx
+        );
+    }
+
+    ret += "</svg>";
+    ret
+}
+
+/// Point at distance `radius` from (x, y) in the direction of (towards_x, towards_y).
+fn shorten(x: f64, y: f64, towards_x: f64, towards_y: f64, radius: f64) -> (f64, f64) {
+    let (dx, dy) = (towards_x - x, towards_y - y);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 {
+        return (x, y);
+    }
+    (x + dx / len * radius, y + dy / len * radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<String> {
+        input.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn report_has_every_section_and_trace() {
+        let mut grammar = HashMap::new();
+        let mut extras = HashMap::new();
+        let mut first_non_terminal = String::new();
+        crate::process_str(
+            include_str!("../test_txts/input2.txt").to_string(),
+            &mut grammar,
+            &mut extras,
+            &mut first_non_terminal,
+        );
+        let extended_grammar =
+            crate::build_extended_grammar(&grammar, &extras, &first_non_terminal);
+        let interned = InternedGrammar::new(&extended_grammar);
+        let (slr, table) = crate::slr_table_of(&interned);
+        let traces: Vec<(String, ParseResult)> = ["id + id", "id id"]
+            .iter()
+            .map(|input| {
+                let result =
+                    driver::parse_tokens(&table, &interned, &extended_grammar, &tokens(input));
+                (input.to_string(), result)
+            })
+            .collect();
+        let html = html_report(
+            &grammar,
+            &first_non_terminal,
+            &interned,
+            &extended_grammar,
+            &slr,
+            &table,
+            &traces,
+        );
+
+        for section in [
+            "<h2>Grammar</h2>",
+            "<h2>Augmented grammar</h2>",
+            "<h2>FIRST &amp; FOLLOW</h2>",
+            "<h2>Item sets</h2>",
+            "<h2>LR(0) automaton</h2>",
+            "<h2>Parse table</h2>",
+            "<h2>Parse traces</h2>",
+        ] {
+            assert!(html.contains(section), "missing {}", section);
+        }
+        assert!(html.contains("<code>id + id</code> <span class=\"accepted\">"));
+        assert!(html.contains("<code>id id</code> <span class=\"rejected\">"));
+        assert_eq!(html.matches("<div class=\"item-set\">").count(), slr.len());
+        assert!(!html.contains("class=\"conflict\""));
+    }
+
+    #[test]
+    fn conflicts_and_accepting_states_are_marked() {
+        let grammar = InternedGrammar::new(&crate::extended_grammar_of("2\nE -> E + E\nE -> id"));
+        let (slr, table) = crate::slr_table_of(&grammar);
+        let conflicts: usize = table.iter().map(|row| row.conflicts.len()).sum();
+        assert_eq!(conflicts, 1);
+        let table_html = parse_table_html(&table, &grammar);
+        assert_eq!(table_html.matches("class=\"conflict\"").count(), 1);
+
+        // one circle per state plus the inner circle of the accepting state
+        let svg = automaton_svg(&slr, &table, &grammar);
+        assert_eq!(svg.matches("<circle").count(), slr.len() + 1);
+    }
+}
@@ -0,0 +1,596 @@
+use std::fmt;
+
+use crate::driver::{self, ParseEvent, ParseResult};
+use crate::symbols::InternedGrammar;
+use crate::{SlrRow, SlrRule};
+
+/// Value of a grammar symbol during syntax directed translation. Terminals get their lexeme
+/// (as a number when it looks like one) and non terminals the result of their rule action.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Nil,
+    Num(f64),
+    Str(String),
+    /// Tree node built with `node(name, children...)`.
+    Node(String, Vec<Value>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Num(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Num(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Node(name, children) => {
+                write!(f, "({}", name)?;
+                for child in children.iter() {
+                    match child {
+                        Value::Str(s) if s.is_empty() || s.contains([' ', '(', ')', '"']) => {
+                            write!(f, " {:?}", s)?
+                        }
+                        other => write!(f, " {}", other)?,
+                    }
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Value {
+    fn from_lexeme(lexeme: &str) -> Value {
+        match lexeme.parse::<f64>() {
+            Ok(n) if lexeme.starts_with(|c: char| c.is_ascii_digit()) => Value::Num(n),
+            _ => Value::Str(lexeme.to_string()),
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Num(n) => *n != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Node(..) => true,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Num(_) => "number",
+            Value::Str(_) => "string",
+            Value::Node(..) => "node",
+        }
+    }
+}
+
+/// Expression of an action block. `$n` is the value of the n-th symbol of the rule body.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Num(f64),
+    Str(String),
+    Arg(usize),
+    Neg(Box<Expr>),
+    Binary(String, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+/// Parses the contents of an action block, e.g. `$1 + $3` or `node("add", $1, $3)`.
+///
+/// ```text
+/// expr    := add (("==" | "!=" | "<" | "<=" | ">" | ">=") add)?
+/// add     := mul (("+" | "-") mul)*
+/// mul     := unary (("*" | "/" | "%") unary)*
+/// unary   := "-" unary | primary
+/// primary := number | "string" | $n | name "(" (expr ("," expr)*)? ")" | "(" expr ")"
+/// ```
+pub fn parse_action(src: &str) -> Result<Expr, String> {
+    let tokens = lex_action(src)?;
+    let mut pos = 0;
+    let expr = parse_comparison(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        None => Ok(expr),
+        Some(tok) => Err(format!("unexpected `{}` in action", tok)),
+    }
+}
+
+fn lex_action(src: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let chr = chars[i];
+        if chr.is_whitespace() {
+            i += 1;
+        } else if chr == '"' {
+            // string literal, kept with its quotes
+            let mut tok = "\"".to_string();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string in action".to_string()),
+                    Some('"') => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        tok.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(c) => {
+                        tok.push(*c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(tok);
+            i += 1;
+        } else if chr == '$' || chr.is_ascii_alphanumeric() || chr == '_' || chr == '.' {
+            let start = i;
+            i += 1;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["==", "!=", "<=", ">="].contains(&two.as_str()) {
+                tokens.push(two);
+                i += 2;
+            } else if "+-*/%<>(),".contains(chr) {
+                tokens.push(chr.to_string());
+                i += 1;
+            } else {
+                return Err(format!("unexpected `{}` in action", chr));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_comparison(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+    let left = parse_binary(tokens, pos, 0)?;
+    match tokens.get(*pos).map(|t| t.as_str()) {
+        Some(op @ ("==" | "!=" | "<" | "<=" | ">" | ">=")) => {
+            *pos += 1;
+            let right = parse_binary(tokens, pos, 0)?;
+            Ok(Expr::Binary(
+                op.to_string(),
+                Box::new(left),
+                Box::new(right),
+            ))
+        }
+        _ => Ok(left),
+    }
+}
+
+/// Left associative operators by level: `+ -` (0) and `* / %` (1).
+fn parse_binary(tokens: &[String], pos: &mut usize, level: usize) -> Result<Expr, String> {
+    let ops: &[&str] = if level == 0 {
+        &["+", "-"]
+    } else {
+        &["*", "/", "%"]
+    };
+    let operand = |pos: &mut usize| {
+        if level == 0 {
+            parse_binary(tokens, pos, 1)
+        } else {
+            parse_unary(tokens, pos)
+        }
+    };
+    let mut left = operand(pos)?;
+    while let Some(op) = tokens.get(*pos).filter(|t| ops.contains(&t.as_str())) {
+        *pos += 1;
+        let right = operand(pos)?;
+        left = Expr::Binary(op.clone(), Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+    if tokens.get(*pos).map(|t| t.as_str()) == Some("-") {
+        *pos += 1;
+        return Ok(Expr::Neg(Box::new(parse_unary(tokens, pos)?)));
+    }
+    parse_primary(tokens, pos)
+}
+
+fn parse_primary(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+    let tok = match tokens.get(*pos) {
+        Some(tok) => tok.clone(),
+        None => return Err("unexpected end of action".to_string()),
+    };
+    *pos += 1;
+    if tok == "(" {
+        let expr = parse_comparison(tokens, pos)?;
+        expect(tokens, pos, ")")?;
+        return Ok(expr);
+    }
+    if let Some(s) = tok.strip_prefix('"') {
+        return Ok(Expr::Str(s.to_string()));
+    }
+    if let Some(n) = tok.strip_prefix('$') {
+        return match n.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Expr::Arg(n)),
+            _ => Err(format!("invalid symbol reference `{}`", tok)),
+        };
+    }
+    if tok.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return tok
+            .parse::<f64>()
+            .map(Expr::Num)
+            .map_err(|_| format!("invalid number `{}`", tok));
+    }
+    if tok.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        expect(tokens, pos, "(")?;
+        let mut args = Vec::new();
+        if tokens.get(*pos).map(|t| t.as_str()) != Some(")") {
+            args.push(parse_comparison(tokens, pos)?);
+            while tokens.get(*pos).map(|t| t.as_str()) == Some(",") {
+                *pos += 1;
+                args.push(parse_comparison(tokens, pos)?);
+            }
+        }
+        expect(tokens, pos, ")")?;
+        return Ok(Expr::Call(tok, args));
+    }
+    Err(format!("unexpected `{}` in action", tok))
+}
+
+fn expect(tokens: &[String], pos: &mut usize, tok: &str) -> Result<(), String> {
+    if tokens.get(*pos).map(|t| t.as_str()) == Some(tok) {
+        *pos += 1;
+        Ok(())
+    } else {
+        Err(format!("expected `{}` in action", tok))
+    }
+}
+
+impl Expr {
+    /// Highest `$n` used by the expression, 0 if it uses none.
+    fn max_arg(&self) -> usize {
+        match self {
+            Expr::Num(_) | Expr::Str(_) => 0,
+            Expr::Arg(n) => *n,
+            Expr::Neg(expr) => expr.max_arg(),
+            Expr::Binary(_, left, right) => left.max_arg().max(right.max_arg()),
+            Expr::Call(_, args) => args.iter().map(Expr::max_arg).max().unwrap_or(0),
+        }
+    }
+
+    /// Value of the expression for a rule whose body symbols have the values `args`.
+    pub fn eval(&self, args: &[Value]) -> Result<Value, String> {
+        match self {
+            Expr::Num(n) => Ok(Value::Num(*n)),
+            Expr::Str(s) => Ok(Value::Str(s.clone())),
+            Expr::Arg(n) => args.get(n - 1).cloned().ok_or_else(|| {
+                format!(
+                    "${} is out of range, the rule has {} symbols",
+                    n,
+                    args.len()
+                )
+            }),
+            Expr::Neg(expr) => match expr.eval(args)? {
+                Value::Num(n) => Ok(Value::Num(-n)),
+                other => Err(format!("cannot negate a {}", other.type_name())),
+            },
+            Expr::Binary(op, left, right) => binary(op, left.eval(args)?, right.eval(args)?),
+            // only the branch taken is evaluated, so `if($3 == 0, 0, $1 / $3)` is a guard
+            Expr::Call(name, call_args) if name == "if" => match call_args.as_slice() {
+                [condition, then, other] => {
+                    if condition.eval(args)?.is_true() {
+                        then.eval(args)
+                    } else {
+                        other.eval(args)
+                    }
+                }
+                _ => Err("`if` takes 3 arguments".to_string()),
+            },
+            Expr::Call(name, call_args) => {
+                let values = call_args
+                    .iter()
+                    .map(|arg| arg.eval(args))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                call(name, values)
+            }
+        }
+    }
+}
+
+fn binary(op: &str, left: Value, right: Value) -> Result<Value, String> {
+    let bool_value = |b: bool| Value::Num(if b { 1.0 } else { 0.0 });
+    match (op, &left, &right) {
+        ("==", _, _) => Ok(bool_value(left == right)),
+        ("!=", _, _) => Ok(bool_value(left != right)),
+        ("+", Value::Str(_), _) | ("+", _, Value::Str(_)) => {
+            Ok(Value::Str(format!("{}{}", left, right)))
+        }
+        (_, Value::Num(a), Value::Num(b)) => match op {
+            "+" => Ok(Value::Num(a + b)),
+            "-" => Ok(Value::Num(a - b)),
+            "*" => Ok(Value::Num(a * b)),
+            "/" | "%" if *b == 0.0 => Err("division by zero".to_string()),
+            "/" => Ok(Value::Num(a / b)),
+            "%" => Ok(Value::Num(a % b)),
+            "<" => Ok(bool_value(a < b)),
+            "<=" => Ok(bool_value(a <= b)),
+            ">" => Ok(bool_value(a > b)),
+            _ => Ok(bool_value(a >= b)),
+        },
+        (_, Value::Str(a), Value::Str(b)) if ["<", "<=", ">", ">="].contains(&op) => {
+            Ok(bool_value(match op {
+                "<" => a < b,
+                "<=" => a <= b,
+                ">" => a > b,
+                _ => a >= b,
+            }))
+        }
+        _ => Err(format!(
+            "cannot apply `{}` to a {} and a {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        )),
+    }
+}
+
+/// Built-in functions: `num(x)`, `str(x)` and `node(name, children...)`. `if(c, a, b)` is
+/// handled by `Expr::eval`, since its arguments can't be evaluated beforehand.
+fn call(name: &str, mut args: Vec<Value>) -> Result<Value, String> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(format!("`{}` takes {} arguments", name, n))
+        }
+    };
+    match name {
+        "num" => {
+            arity(1)?;
+            match &args[0] {
+                Value::Num(n) => Ok(Value::Num(*n)),
+                Value::Str(s) => s
+                    .trim()
+                    .parse::<f64>()
+                    .map(Value::Num)
+                    .map_err(|_| format!("`{}` is not a number", s)),
+                other => Err(format!(
+                    "cannot convert a {} to a number",
+                    other.type_name()
+                )),
+            }
+        }
+        "str" => {
+            arity(1)?;
+            Ok(Value::Str(args[0].to_string()))
+        }
+        "node" => match args.first() {
+            Some(Value::Str(node_name)) => {
+                let node_name = node_name.clone();
+                Ok(Value::Node(node_name, args.split_off(1)))
+            }
+            _ => Err("the first argument of `node` must be its name".to_string()),
+        },
+        _ => Err(format!("unknown function `{}`", name)),
+    }
+}
+
+/// Splits the action block off a grammar line: a trailing `{ ... }` is an action if its
+/// contents are a valid expression, so braces can still be used as terminals
+/// (`B -> { S }`). A block that uses `$n` but doesn't parse is reported as an error.
+pub fn split_action(line: &str) -> Result<(&str, Option<String>), String> {
+    let trimmed = line.trim_end();
+    if !trimmed.ends_with('}') {
+        return Ok((line, None));
+    }
+    let body_end = trimmed.len() - 1;
+    let mut error = None;
+    for (start, _) in trimmed.match_indices('{') {
+        let preceded_by_space = start == 0 || trimmed[..start].ends_with(char::is_whitespace);
+        if !preceded_by_space || start >= body_end {
+            continue;
+        }
+        let contents = trimmed[start + 1..body_end].trim();
+        match parse_action(contents) {
+            Ok(_) => return Ok((&trimmed[..start], Some(contents.to_string()))),
+            Err(err) if contents.contains('$') && error.is_none() => error = Some(err),
+            Err(_) => {}
+        }
+    }
+    match error {
+        Some(err) => Err(err),
+        None => Ok((line, None)),
+    }
+}
+
+/// Checks that every `$n` of an action refers to one of the `len` symbols of its rule body.
+pub fn check_args(action: &str, len: usize) -> Result<(), String> {
+    let n = parse_action(action)?.max_arg();
+    if n > len {
+        return Err(format!(
+            "${} is out of range, the rule has {} symbols",
+            n, len
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the parser over `tokens` (kind and lexeme), evaluating the action of every rule on
+/// its reduction. Rules without an action take the value of their first symbol, or nil if
+/// they are empty. Returns the trace and, if the input was accepted, the value of the start
+/// symbol or the first error found evaluating an action. Every step is also passed to
+/// `on_event`, for callers that build something else in the same pass (like the AST).
+pub fn evaluate(
+    table: &[SlrRow],
+    grammar: &InternedGrammar,
+    extended_grammar: &[SlrRule],
+    tokens: &[(String, String)],
+    mut on_event: impl FnMut(&ParseEvent),
+) -> (ParseResult, Option<Result<Value, String>>) {
+    let actions: Vec<Option<Result<Expr, String>>> = extended_grammar
+        .iter()
+        .map(|rule| rule.action.as_ref().map(|src| parse_action(src)))
+        .collect();
+    let kinds: Vec<String> = tokens.iter().map(|(kind, _)| kind.clone()).collect();
+
+    let mut values: Vec<Value> = Vec::new();
+    let mut error: Option<String> = None;
+    let result = driver::parse_tokens_with(table, grammar, extended_grammar, &kinds, |event| {
+        on_event(&event);
+        match event {
+            ParseEvent::Shift(pos) => values.push(Value::from_lexeme(&tokens[pos].1)),
+            ParseEvent::Reduce(rule) => {
+                let len = driver::rule_len(&extended_grammar[rule]);
+                let children = values.split_off(values.len() - len);
+                let value = match &actions[rule] {
+                    Some(Ok(expr)) => expr.eval(&children),
+                    Some(Err(err)) => Err(err.clone()),
+                    None => Ok(children.into_iter().next().unwrap_or(Value::Nil)),
+                };
+                match value {
+                    Ok(value) => values.push(value),
+                    Err(err) => {
+                        if error.is_none() {
+                            error = Some(format!("rule {}: {}", rule, err));
+                        }
+                        values.push(Value::Nil);
+                    }
+                }
+            }
+            ParseEvent::Recover(popped) => {
+                values.truncate(values.len() - popped);
+                values.push(Value::Nil);
+            }
+        }
+    });
+
+    let value = if result.accepted {
+        match error {
+            Some(err) => Some(Err(err)),
+            None => Some(Ok(values.pop().unwrap_or(Value::Nil))),
+        }
+    } else {
+        None
+    };
+    (result, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str, args: &[Value]) -> Result<Value, String> {
+        parse_action(src)?.eval(args)
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence_and_associativity() {
+        assert_eq!(eval("1 + 2 * 3", &[]), Ok(Value::Num(7.0)));
+        assert_eq!(eval("(1 + 2) * 3", &[]), Ok(Value::Num(9.0)));
+        assert_eq!(eval("10 - 4 - 3", &[]), Ok(Value::Num(3.0)));
+        assert_eq!(eval("12 / 2 / 3 % 4", &[]), Ok(Value::Num(2.0)));
+        assert_eq!(eval("-2 * -(1 + 2)", &[]), Ok(Value::Num(6.0)));
+        assert_eq!(eval("1 + 1 == 2", &[]), Ok(Value::Num(1.0)));
+        assert_eq!(
+            eval("\"a\" + 1 + 2", &[]),
+            Ok(Value::Str("a12".to_string()))
+        );
+        assert_eq!(eval("1 / 0", &[]), Err("division by zero".to_string()));
+        assert!(eval("1 < 2 < 3", &[]).is_err());
+    }
+
+    #[test]
+    fn args_are_the_values_of_the_body() {
+        let args = [
+            Value::Num(2.0),
+            Value::Str("+".to_string()),
+            Value::Num(5.0),
+        ];
+        assert_eq!(eval("$1 + $3", &args), Ok(Value::Num(7.0)));
+        assert_eq!(eval("str($2)", &args), Ok(Value::Str("+".to_string())));
+        assert_eq!(
+            eval("node(\"add\", $1, $3)", &args).unwrap().to_string(),
+            "(add 2 5)"
+        );
+        assert_eq!(
+            eval("$4", &args),
+            Err("$4 is out of range, the rule has 3 symbols".to_string())
+        );
+        assert!(parse_action("$0").is_err());
+    }
+
+    #[test]
+    fn action_blocks_are_split_only_when_they_parse() {
+        assert_eq!(
+            split_action("E -> E + T { $1 + $3 }"),
+            Ok(("E -> E + T ", Some("$1 + $3".to_string())))
+        );
+        // braces used as terminals
+        assert_eq!(split_action("B -> { S }"), Ok(("B -> { S }", None)));
+        assert_eq!(split_action("B -> a{ 1 }"), Ok(("B -> a{ 1 }", None)));
+        assert_eq!(
+            split_action("B -> { S } { 1 }"),
+            Ok(("B -> { S } ", Some("1".to_string())))
+        );
+    }
+
+    #[test]
+    fn malformed_actions_are_errors() {
+        assert_eq!(
+            split_action("E -> E + T { $1 + }"),
+            Err("unexpected end of action".to_string())
+        );
+        assert_eq!(
+            split_action("E -> E + T { $1 $3 }"),
+            Err("unexpected `$3` in action".to_string())
+        );
+        assert_eq!(
+            check_args("$1 + $5", 3),
+            Err("$5 is out of range, the rule has 3 symbols".to_string())
+        );
+        assert_eq!(check_args("$1 + $3", 3), Ok(()));
+        // the grammar stops loading at the wrong rule
+        assert_eq!(crate::extended_grammar_of("1\nE -> E + T { $5 }").len(), 1);
+    }
+
+    #[test]
+    fn evaluates_the_actions_on_every_reduction() {
+        let extended_grammar =
+            crate::extended_grammar_of(include_str!("../test_txts/input_calc.txt"));
+        let grammar = InternedGrammar::new(&extended_grammar);
+        let (_, table) = crate::slr_table_of(&grammar);
+        let run = |input: &str| {
+            let tokens = driver::tokenize_with_lexemes(input);
+            evaluate(&table, &grammar, &extended_grammar, &tokens, |_| {}).1
+        };
+        assert_eq!(
+            run("num:2 * ( num:3 + num:4 ) - num:6 / num:3"),
+            Some(Ok(Value::Num(12.0)))
+        );
+        assert_eq!(
+            run("num:1 / ( num:2 - num:2 )"),
+            Some(Err("rule 7: division by zero".to_string()))
+        );
+        assert_eq!(run("num:1 +"), None);
+    }
+
+    #[test]
+    fn if_only_evaluates_the_branch_taken() {
+        let expr = parse_action("if($3 == 0, 0, $1 / $3)").unwrap();
+        let args = |divisor: f64| {
+            vec![
+                Value::Num(4.0),
+                Value::Str("/".to_string()),
+                Value::Num(divisor),
+            ]
+        };
+        assert_eq!(expr.eval(&args(0.0)), Ok(Value::Num(0.0)));
+        assert_eq!(expr.eval(&args(2.0)), Ok(Value::Num(2.0)));
+    }
+
+    #[test]
+    fn if_checks_its_arity() {
+        let expr = parse_action("if($1, 2)").unwrap();
+        assert!(expr.eval(&[Value::Num(1.0)]).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::SlrRule;
+
+/// Set of symbol ids stored as bits.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    /// Empty set able to hold ids `0..size`.
+    pub fn new(size: usize) -> BitSet {
+        BitSet {
+            words: vec![0; size.div_ceil(64)],
+        }
+    }
+
+    /// Adds `id`, returning true if it wasn't in the set.
+    pub fn insert(&mut self, id: usize) -> bool {
+        let (word, bit) = (id / 64, 1u64 << (id % 64));
+        let added = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        added
+    }
+
+    /// Adds every id of `other`, returning true if the set changed.
+    pub fn union_with(&mut self, other: &BitSet) -> bool {
+        let mut changed = false;
+        for (word, other_word) in self.words.iter_mut().zip(other.words.iter()) {
+            let new_word = *word | other_word;
+            changed |= new_word != *word;
+            *word = new_word;
+        }
+        changed
+    }
+
+    /// Ids that are in both sets.
+    pub fn intersection(&self, other: &BitSet) -> BitSet {
+        BitSet {
+            words: self
+                .words
+                .iter()
+                .zip(other.words.iter())
+                .map(|(word, other_word)| word & other_word)
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    /// Ids in the set, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            (0..64)
+                .filter(move |bit| word & (1u64 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+/// Rule of the interned grammar: ids of its origin and of the symbols of its body.
+pub struct InternedRule {
+    pub origin: usize,
+    pub body: Vec<usize>,
+}
+
+/// Extended grammar where every symbol is replaced by an integer id. Terminals (ordered by
+/// name) come first, then `$`, then the non terminals, with the augmented start symbol last.
+/// FIRST and FOLLOW are computed once, as bitsets of terminal ids. String names are only
+/// kept to print results.
+pub struct InternedGrammar {
+    pub names: Vec<String>,
+    pub ids: HashMap<String, usize>,
+    /// Id of `$`, which is also the number of terminals.
+    pub eof: usize,
+    /// Id of the augmented start symbol (origin of rule 0).
+    pub start: usize,
+    /// Same numbering as the extended grammar.
+    pub rules: Vec<InternedRule>,
+    pub rules_by_origin: Vec<Vec<usize>>,
+    pub nullable: Vec<bool>,
+    pub firsts: Vec<BitSet>,
+    pub follows: Vec<BitSet>,
+}
+
+impl InternedGrammar {
+    pub fn new(extended_grammar: &[SlrRule]) -> InternedGrammar {
+        // non terminals are the origins of the rules, everything else is a terminal
+        let augmented = &extended_grammar[0].origin;
+        let origins: HashSet<&String> = extended_grammar.iter().map(|rule| &rule.origin).collect();
+        let mut non_terminals: Vec<&String> = origins
+            .iter()
+            .copied()
+            .filter(|origin| *origin != augmented)
+            .collect();
+        non_terminals.sort();
+        non_terminals.push(augmented);
+        let mut terminals: Vec<&String> = extended_grammar
+            .iter()
+            .flat_map(|rule| rule.body().iter())
+            .filter(|symbol| !origins.contains(symbol))
+            .collect::<HashSet<&String>>()
+            .into_iter()
+            .collect();
+        terminals.sort();
+
+        let mut names: Vec<String> = terminals.iter().map(|s| s.to_string()).collect();
+        let eof = names.len();
+        names.push("$".to_string());
+        names.extend(non_terminals.iter().map(|s| s.to_string()));
+        let ids: HashMap<String, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect();
+
+        let mut rules = Vec::new();
+        let mut rules_by_origin = vec![Vec::new(); names.len()];
+        for rule in extended_grammar.iter() {
+            let origin = ids[&rule.origin];
+            rules_by_origin[origin].push(rules.len());
+            rules.push(InternedRule {
+                origin,
+                body: rule.body().iter().map(|s| ids[s]).collect(),
+            });
+        }
+
+        let n_symbols = names.len();
+        let mut grammar = InternedGrammar {
+            names,
+            ids,
+            eof,
+            start: n_symbols - 1,
+            rules,
+            rules_by_origin,
+            nullable: vec![false; n_symbols],
+            firsts: vec![BitSet::new(n_symbols); n_symbols],
+            follows: vec![BitSet::new(n_symbols); n_symbols],
+        };
+        grammar.compute_firsts();
+        grammar.compute_follows();
+        grammar
+    }
+
+    pub fn is_terminal(&self, id: usize) -> bool {
+        id <= self.eof
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &String {
+        &self.names[id]
+    }
+
+    /// Ids of the non terminals of the user grammar (without the augmented start symbol).
+    pub fn non_terminals(&self) -> std::ops::Range<usize> {
+        self.eof + 1..self.start
+    }
+
+    /// Names of the symbols in `set`, in id order (terminals are sorted by name).
+    pub fn names_of(&self, set: &BitSet) -> Vec<String> {
+        set.iter().map(|id| self.names[id].clone()).collect()
+    }
+
+    /// FIRST of a sequence of symbols and whether the whole sequence can derive epsilon.
+    pub fn first_of_sequence(&self, symbols: &[usize]) -> (BitSet, bool) {
+        let mut ret = BitSet::new(self.names.len());
+        for symbol in symbols.iter() {
+            ret.union_with(&self.firsts[*symbol]);
+            if !self.nullable[*symbol] {
+                return (ret, false);
+            }
+        }
+        (ret, true)
+    }
+
+    /// Fixed point over every rule until no FIRST set or NULLABLE flag changes.
+    fn compute_firsts(&mut self) {
+        for id in 0..=self.eof {
+            self.firsts[id].insert(id);
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in 0..self.rules.len() {
+                let origin = self.rules[rule].origin;
+                let (firsts, nullable) = self.first_of_sequence(&self.rules[rule].body);
+                changed |= self.firsts[origin].union_with(&firsts);
+                if nullable && !self.nullable[origin] {
+                    self.nullable[origin] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// Fixed point: for `A -> x B y`, FOLLOW(B) gets FIRST(y), and FOLLOW(A) if `y` is nullable.
+    fn compute_follows(&mut self) {
+        self.follows[self.start].insert(self.eof);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in 0..self.rules.len() {
+                let origin = self.rules[rule].origin;
+                for pos in 0..self.rules[rule].body.len() {
+                    let symbol = self.rules[rule].body[pos];
+                    if self.is_terminal(symbol) {
+                        continue;
+                    }
+                    let (mut follows, nullable) =
+                        self.first_of_sequence(&self.rules[rule].body[pos + 1..]);
+                    if nullable {
+                        follows.union_with(&self.follows[origin]);
+                    }
+                    changed |= self.follows[symbol].union_with(&follows);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(grammar: &InternedGrammar, ids: impl Iterator<Item = usize>) -> Vec<&str> {
+        ids.map(|id| grammar.name(id).as_str()).collect()
+    }
+
+    #[test]
+    fn bitsets_hold_ids_across_words() {
+        let mut set = BitSet::new(130);
+        assert!(set.is_empty());
+        assert!(set.insert(3));
+        assert!(set.insert(129));
+        assert!(!set.insert(3));
+        let mut other = BitSet::new(130);
+        other.insert(64);
+        other.insert(129);
+        assert!(set.union_with(&other));
+        assert!(!set.union_with(&other));
+        assert_eq!(set.iter().collect::<Vec<usize>>(), [3, 64, 129]);
+        assert_eq!(
+            set.intersection(&other).iter().collect::<Vec<usize>>(),
+            [64, 129]
+        );
+    }
+
+    #[test]
+    fn ids_put_terminals_then_eof_then_non_terminals() {
+        let extended_grammar = crate::extended_grammar_of(include_str!("../test_txts/input1.txt"));
+        let grammar = &InternedGrammar::new(&extended_grammar);
+        assert_eq!(names(grammar, 0..grammar.eof), ["(", ")", "*", "+", "id"]);
+        assert_eq!(grammar.name(grammar.eof), "$");
+        assert_eq!(
+            names(grammar, grammar.non_terminals()),
+            ["E", "EPrime", "F", "T", "TPrime"]
+        );
+        assert_eq!(grammar.name(grammar.start), "E'");
+        assert_eq!(grammar.id("id"), Some(4));
+        assert_eq!(grammar.id("' '"), None);
+        assert_eq!(grammar.rules.len(), extended_grammar.len());
+    }
+
+    #[test]
+    fn nullable_firsts_and_follows() {
+        let grammar = &InternedGrammar::new(&crate::extended_grammar_of(include_str!(
+            "../test_txts/input1.txt"
+        )));
+        let id = |name: &str| grammar.id(name).unwrap();
+
+        assert!(grammar.nullable[id("EPrime")]);
+        assert!(!grammar.nullable[id("E")]);
+        assert_eq!(grammar.names_of(&grammar.firsts[id("E")]), ["(", "id"]);
+        assert_eq!(grammar.names_of(&grammar.firsts[id("TPrime")]), ["*"]);
+        assert_eq!(grammar.names_of(&grammar.follows[id("E")]), [")", "$"]);
+        assert_eq!(
+            grammar.names_of(&grammar.follows[id("F")]),
+            [")", "*", "+", "$"]
+        );
+
+        let (firsts, nullable) = grammar.first_of_sequence(&[id("TPrime"), id("EPrime")]);
+        assert_eq!(grammar.names_of(&firsts), ["*", "+"]);
+        assert!(nullable);
+    }
+}
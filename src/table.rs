@@ -0,0 +1,263 @@
+use crate::symbols::InternedGrammar;
+use crate::SlrRow;
+
+/// Rectangular table of already formatted cells. Every renderer (HTML,
+/// Markdown, CSV, plain text and LaTeX) works over this model so they all
+/// show exactly the same columns and values.
+pub struct TextTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Output formats available for a `TextTable`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TableFormat {
+    Html,
+    Markdown,
+    Csv,
+    Text,
+    Latex,
+}
+
+impl TableFormat {
+    /// Parses the name given on the command line (`html`, `markdown`/`md`, `csv`, `text`/`ascii`,
+    /// `latex`/`tex`).
+    pub fn from_name(name: &str) -> Option<TableFormat> {
+        match name.to_lowercase().as_str() {
+            "html" => Some(TableFormat::Html),
+            "markdown" | "md" => Some(TableFormat::Markdown),
+            "csv" => Some(TableFormat::Csv),
+            "text" | "ascii" | "txt" => Some(TableFormat::Text),
+            "latex" | "tex" => Some(TableFormat::Latex),
+            _ => None,
+        }
+    }
+}
+
+impl TextTable {
+    /// Builds the action/goto table model: one column for the state id, one per terminal,
+    /// one for `$` and one per non terminal, in id order (terminals and non terminals are
+    /// each sorted by name).
+    pub fn from_slr_table(table: &[SlrRow], grammar: &InternedGrammar) -> TextTable {
+        // add headers
+        let mut headers = vec!["state".to_string()];
+        headers.extend((0..=grammar.eof).map(|term| grammar.name(term).clone()));
+        headers.extend(
+            grammar
+                .non_terminals()
+                .map(|nterm| grammar.name(nterm).clone()),
+        );
+
+        // add rows
+        let mut rows = Vec::new();
+        for (i, slr_row) in table.iter().enumerate() {
+            let mut row = vec![i.to_string()];
+            //      actions (including $)
+            for term in 0..=grammar.eof {
+                match slr_row.actions.get(&term) {
+                    Some(action) => row.push(action.to_string()),
+                    None => row.push(String::new()),
+                }
+            }
+            //      gotos
+            for nterm in grammar.non_terminals() {
+                match slr_row.gotos.get(&nterm) {
+                    Some(goto) => row.push(goto.to_string()),
+                    None => row.push(String::new()),
+                }
+            }
+            rows.push(row);
+        }
+
+        TextTable { headers, rows }
+    }
+
+    pub fn render(&self, format: TableFormat) -> String {
+        match format {
+            TableFormat::Html => self.to_html(),
+            TableFormat::Markdown => self.to_markdown(),
+            TableFormat::Csv => self.to_csv(),
+            TableFormat::Text => self.to_text(),
+            TableFormat::Latex => crate::latex::table_to_latex(self),
+        }
+    }
+
+    /// Single line HTML `<table>`.
+    pub fn to_html(&self) -> String {
+        let mut ret = "<table>".to_string();
+
+        ret += "<tr>";
+        for header in self.headers.iter() {
+            ret += &format!("<th>{}</th>", escape_html(header));
+        }
+        ret += "</tr>";
+
+        for row in self.rows.iter() {
+            ret += "<tr>";
+            for cell in row.iter() {
+                ret += &format!("<td>{}</td>", escape_html(cell));
+            }
+            ret += "</tr>";
+        }
+
+        ret += "</table>";
+        ret
+    }
+
+    /// GitHub flavoured Markdown table.
+    pub fn to_markdown(&self) -> String {
+        let mut ret = String::new();
+
+        ret += &markdown_row(&self.headers);
+        ret += "|";
+        for _ in self.headers.iter() {
+            ret += " --- |";
+        }
+        ret += "\n";
+
+        for row in self.rows.iter() {
+            ret += &markdown_row(row);
+        }
+
+        ret
+    }
+
+    /// Comma separated values, quoting cells as described in RFC 4180.
+    pub fn to_csv(&self) -> String {
+        let mut ret = String::new();
+
+        for row in std::iter::once(&self.headers).chain(self.rows.iter()) {
+            let cells: Vec<String> = row.iter().map(|cell| escape_csv(cell)).collect();
+            ret += &cells.join(",");
+            ret += "\n";
+        }
+
+        ret
+    }
+
+    /// Plain text table with aligned columns, meant to be read in a terminal.
+    pub fn to_text(&self) -> String {
+        // get width of every column
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in self.rows.iter() {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        let separator = {
+            let mut line = "+".to_string();
+            for width in widths.iter() {
+                line += &"-".repeat(width + 2);
+                line += "+";
+            }
+            line + "\n"
+        };
+
+        let mut ret = separator.clone();
+        ret += &text_row(&self.headers, &widths);
+        ret += &separator;
+        for row in self.rows.iter() {
+            ret += &text_row(row, &widths);
+        }
+        ret += &separator;
+
+        ret
+    }
+}
+
+fn markdown_row(cells: &[String]) -> String {
+    let mut ret = "|".to_string();
+    for cell in cells.iter() {
+        ret += " ";
+        ret += &cell.replace('\\', "\\\\").replace('|', "\\|");
+        ret += " |";
+    }
+    ret + "\n"
+}
+
+fn text_row(cells: &[String], widths: &[usize]) -> String {
+    let mut ret = "|".to_string();
+    for (cell, width) in cells.iter().zip(widths.iter()) {
+        ret += &format!(" {:<width$} |", cell, width = width);
+    }
+    ret + "\n"
+}
+
+pub fn escape_html(txt: &str) -> String {
+    txt.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_csv(txt: &str) -> String {
+    if txt.contains(',') || txt.contains('"') || txt.contains('\n') || txt.contains('\r') {
+        format!("\"{}\"", txt.replace('"', "\"\""))
+    } else {
+        txt.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+
+    fn small_table() -> TextTable {
+        TextTable {
+            headers: vec!["state".to_string(), "a|b".to_string(), "c,d".to_string()],
+            rows: vec![vec![
+                "0".to_string(),
+                "s1".to_string(),
+                "say \"hi\"".to_string(),
+            ]],
+        }
+    }
+
+    #[test]
+    fn renders_the_same_cells_in_every_format() {
+        let table = small_table();
+        assert_eq!(
+            table.to_markdown(),
+            "| state | a\\|b | c,d |\n| --- | --- | --- |\n| 0 | s1 | say \"hi\" |\n"
+        );
+        assert_eq!(
+            table.to_csv(),
+            "state,a|b,\"c,d\"\n0,s1,\"say \"\"hi\"\"\"\n"
+        );
+        assert_eq!(
+            table.to_text(),
+            "+-------+-----+----------+\n\
+             | state | a|b | c,d      |\n\
+             +-------+-----+----------+\n\
+             | 0     | s1  | say \"hi\" |\n\
+             +-------+-----+----------+\n"
+        );
+        assert_eq!(
+            table.to_html(),
+            "<table><tr><th>state</th><th>a|b</th><th>c,d</th></tr>\
+             <tr><td>0</td><td>s1</td><td>say &quot;hi&quot;</td></tr></table>"
+        );
+    }
+
+    #[test]
+    fn slr_table_has_a_column_per_symbol_in_id_order() {
+        let grammar = InternedGrammar::new(&crate::extended_grammar_of("2\nS -> b S\nS -> a"));
+        let id = |name: &str| grammar.id(name).unwrap();
+        let mut row = SlrRow::new();
+        row.set_action(id("b"), Action::S(1));
+        row.set_action(id("a"), Action::S(2));
+        row.set_action(id("a"), Action::R(2));
+        row.gotos.insert(id("S"), 3);
+        let mut accept = SlrRow::new();
+        accept.set_action(grammar.eof, Action::Acc);
+
+        let table = TextTable::from_slr_table(&[row, accept], &grammar);
+        assert_eq!(table.headers, ["state", "a", "b", "$", "S"]);
+        assert_eq!(
+            table.rows,
+            [["0", "ERR", "s1", "", "3"], ["1", "", "", "ACC", ""]]
+        );
+    }
+}